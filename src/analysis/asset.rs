@@ -1,26 +1,45 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Instant;
 // src/analysis/pairs.rs
-use crate::core::types::{SyntheticPairFullData, TradingPair, PairData, TimeInterval};
+use crate::core::types::{SyntheticPairFullData, TradingPair, PairData, TimeInterval, TradingStrategy, SyntheticBasket, Candle, ConfigId};
 use anyhow::{anyhow, Result};
 use crate::mathematics;
 use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use log::error;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use tracing::info;
 use crate::core::config::Config;
 
 use crate::exchange::binance::BinanceExchange;
 use crate::logic::convert_to_candles;
-use crate::mathematics::{calculate_synthetic_pair_data_h4, calculate_synthetic_pair_data_minutes, calculate_synthetic_pair_data_with_dickeyfuller, calculate_z_score, calculate_z_score_minutes, calculate_z_score_minutes_from_h4, calculate_z_score_ndarray};
+use crate::mathematics::mean_reversion::passes_mean_reversion_limits;
+use crate::mathematics::stationarity::{passes_stationarity_rule, StationarityTest};
+use crate::mathematics::{basket_minutes_since, push_minute_candles, synthetic_minutes_since, calculate_basket_data_h4, calculate_basket_data_minutes, calculate_basket_data_with_johansen, calculate_synthetic_pair_data_h4, calculate_synthetic_pair_data_minutes, calculate_synthetic_pair_data_with_dickeyfuller, calculate_z_score, calculate_z_score_minutes_from_bars};
 #[derive(Clone)]
 pub struct AssetAnalyzer {
-
+    // Synthetic pairs are spread over this pool, shared between clones of the analyzer
+    pool: Arc<ThreadPool>,
 }
 impl AssetAnalyzer{
-    pub fn new() -> Self {
-        Self {}
+    // workers == 0 takes one worker per core
+    pub fn new(workers: usize) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|i| format!("analyzer-{}", i))
+            .build()
+            .map_err(|e| anyhow!("failed to build analyzer pool: {}", e))?;
+        Ok(Self { pool: Arc::new(pool) })
+    }
+    pub fn workers(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+    fn pairs_by_name(pairs:&[TradingPair]) -> HashMap<&str, &TradingPair> {
+        pairs.iter().map(|pair| (pair.name.as_str(), pair)).collect()
     }
 
-    pub fn calculate_asset_minutes(&self, last_config_id:i32, pairs:&mut Vec<TradingPair>, synthetic_pairs:&mut Vec<SyntheticPairFullData>, time_for_candle:NaiveDateTime) ->Result<()>{
+    pub fn calculate_asset_minutes(&self, last_config_id:i32, pairs:&mut Vec<TradingPair>, synthetic_pairs:&mut Vec<SyntheticPairFullData>, time_for_candle:NaiveDateTime, strategy:&TradingStrategy) ->Result<()>{
         info!(" calculate pairs minutes");
 
         for pair in &mut *pairs {
@@ -28,15 +47,8 @@ impl AssetAnalyzer{
 
             if let Some(timeframes) = pair.pair_data_map.get_mut(&last_config_id) {
                 for another_timeframe in timeframes {
-                    let candles_minutes: Vec<f32> = another_timeframe.candles_minutes.iter().map(|candle|
-                        candle.price
-                    ).collect();
-                    let candles_h4: Vec<f32> = another_timeframe.candles_h4.iter().map(|candle|
-                        candle.price
-                    ).collect();
-
-                    if !candles_minutes.is_empty(){
-                        another_timeframe.z_score_minutes=calculate_z_score_minutes_from_h4(candles_h4,candles_minutes,240)?;
+                    if !another_timeframe.candles_minutes.is_empty(){
+                        another_timeframe.z_score_minutes=calculate_z_score_minutes_from_bars(&another_timeframe.candles,&another_timeframe.candles_minutes,strategy.z_score_period as usize)?;
 
                     } else {
                         info!("C_W| candels is empty in pair: {}", name.as_str());
@@ -61,34 +73,28 @@ impl AssetAnalyzer{
             }
         });
 
-        let mut last_update_time = Instant::now();
-
-        for (i, synthetic_pair) in synthetic_pairs.iter_mut().enumerate() {
-            if last_update_time.elapsed() >= std::time::Duration::from_secs(100) {
-                last_update_time = Instant::now();
-            }
+        let pairs_by_name = Self::pairs_by_name(pairs);
 
+        // In-place over the slice, so the order of synthetic_pairs does not depend on the scheduling
+        self.pool.install(|| synthetic_pairs.par_iter_mut().enumerate().try_for_each(|(i, synthetic_pair)| -> Result<()> {
             if i % 1000 == 0 {
                 info!("NUM: {}", i);
             }
 
-            let first_pair = pairs.iter().find(|&pair| pair.name == synthetic_pair.first_pair.name);
-            let second_pair = pairs.iter().find(|&pair| pair.name == synthetic_pair.second_pair.name);
-
-            if first_pair.is_none() || second_pair.is_none() {
+            let (Some(first_pair), Some(second_pair)) = (
+                pairs_by_name.get(synthetic_pair.first_pair.name.as_str()).copied(),
+                pairs_by_name.get(synthetic_pair.second_pair.name.as_str()).copied()
+            ) else {
                 synthetic_pair.id = -2;
-                continue;
-            }
-
-            let first_pair = first_pair.unwrap();
-            let second_pair = second_pair.unwrap();
+                return Ok(());
+            };
 
             if !synthetic_pair.synthetic_data.contains_key(&last_config_id) {
-                synthetic_pair.init_pair_data(last_config_id);
+                synthetic_pair.init_pair_data(last_config_id, &strategy.intervals);
             }
 
             if let Some(synthetic_timeframes) = synthetic_pair.synthetic_data.get_mut(&last_config_id) {
-                for (j, time_interval) in TimeInterval::all().iter().enumerate() {
+                for (j, time_interval) in strategy.intervals.iter().enumerate() {
                     if let (Some(first_timeframes), Some(second_timeframes)) = (
                         first_pair.pair_data_map.get(&last_config_id),
                         second_pair.pair_data_map.get(&last_config_id)
@@ -97,16 +103,18 @@ impl AssetAnalyzer{
                             first_timeframes.iter().find(|data| data.time_interval == *time_interval),
                             second_timeframes.iter().find(|data| data.time_interval == *time_interval)
                         ) {
-                            let mut pair_data;
-
-
-                                pair_data = calculate_synthetic_pair_data_minutes(
-                                    &synthetic_timeframes[0],
-                                    &first_data.candles_minutes,
-                                    &second_data.candles_minutes,
-                                    time_interval.clone(),
-                                    last_config_id
-                                )?;
+                            let Some(pair_data) = calculate_synthetic_pair_data_minutes(
+                                &synthetic_timeframes[j],
+                                first_data,
+                                second_data,
+                                strategy,
+                                time_interval.clone(),
+                                last_config_id
+                            )? else {
+                                info!("C_W| too many gaps between the legs of {}", synthetic_pair.name.as_str());
+                                synthetic_pair.id = -2;
+                                continue;
+                            };
 
 
 
@@ -119,12 +127,13 @@ impl AssetAnalyzer{
                     }
                 }
             }
-        }
+            Ok(())
+        }))?;
 
         synthetic_pairs.retain(|synthetic_pair| {
             if synthetic_pair.id != -2 {
                 if let Some(timeframes) = synthetic_pair.synthetic_data.get(&last_config_id) {
-                    if timeframes.iter().all(|data| !data.z_score_minutes.is_empty() && !data.z_score_h4.is_empty()) {
+                    if timeframes.iter().all(|data| !data.z_score_minutes.is_empty() && !data.z_score.is_empty()) {
                         return true;
                     }
                 }
@@ -135,7 +144,7 @@ impl AssetAnalyzer{
 
         Ok(())
     }
-    pub fn calculate_asset_h4(&self, last_config_id:i32, pairs:&mut Vec<TradingPair>, synthetic_pairs:&mut Vec<SyntheticPairFullData>, time_for_candle:NaiveDateTime) ->Result<()>{
+    pub fn calculate_asset_h4(&self, last_config_id:i32, pairs:&mut Vec<TradingPair>, synthetic_pairs:&mut Vec<SyntheticPairFullData>, time_for_candle:NaiveDateTime, strategy:&TradingStrategy) ->Result<()>{
         info!(" calculate pairs h4 ");
 
         for pair in &mut *pairs {
@@ -143,12 +152,12 @@ impl AssetAnalyzer{
 
             if let Some(timeframes) = pair.pair_data_map.get_mut(&last_config_id) {
                 for another_timeframe in timeframes {
-                    let candles_h4: Vec<f32> = another_timeframe.candles_h4.iter().map(|candle|
-                        candle.price
+                    let candles_h4: Vec<f32> = another_timeframe.candles.iter().map(|candle|
+                        candle.price as f32
                     ).collect();
 
                     if !candles_h4.is_empty(){
-                        another_timeframe.z_score_h4=calculate_z_score(&candles_h4,240)?;
+                        another_timeframe.z_score=calculate_z_score(&candles_h4,strategy.z_score_period as usize)?;
                         let test=10;
                     } else {
                         info!("C_W| candels is empty in pair: {}", name.as_str());
@@ -161,7 +170,7 @@ impl AssetAnalyzer{
 
         pairs.retain(|pair| {
             if let Some(timeframes) = pair.pair_data_map.get(&last_config_id) {
-                if timeframes.iter().all(|data| !data.z_score_h4.is_empty() ) {
+                if timeframes.iter().all(|data| !data.z_score.is_empty() ) {
                     true
                 } else {
                     info!("C_W| Remove the pairs that are not suitable {}", pair.name.as_str());
@@ -173,34 +182,28 @@ impl AssetAnalyzer{
             }
         });
 
-        let mut last_update_time = Instant::now();
-
-        for (i, synthetic_pair) in synthetic_pairs.iter_mut().enumerate() {
-            if last_update_time.elapsed() >= std::time::Duration::from_secs(100) {
-                last_update_time = Instant::now();
-            }
+        let pairs_by_name = Self::pairs_by_name(pairs);
 
+        // In-place over the slice, so the order of synthetic_pairs does not depend on the scheduling
+        self.pool.install(|| synthetic_pairs.par_iter_mut().enumerate().try_for_each(|(i, synthetic_pair)| -> Result<()> {
             if i % 1000 == 0 {
                 info!("NUM: {}", i);
             }
 
-            let first_pair = pairs.iter().find(|&pair| pair.name == synthetic_pair.first_pair.name);
-            let second_pair = pairs.iter().find(|&pair| pair.name == synthetic_pair.second_pair.name);
-
-            if first_pair.is_none() || second_pair.is_none() {
+            let (Some(first_pair), Some(second_pair)) = (
+                pairs_by_name.get(synthetic_pair.first_pair.name.as_str()).copied(),
+                pairs_by_name.get(synthetic_pair.second_pair.name.as_str()).copied()
+            ) else {
                 synthetic_pair.id = -2;
-                continue;
-            }
-
-            let first_pair = first_pair.unwrap();
-            let second_pair = second_pair.unwrap();
+                return Ok(());
+            };
 
             if !synthetic_pair.synthetic_data.contains_key(&last_config_id) {
-                synthetic_pair.init_pair_data(last_config_id);
+                synthetic_pair.init_pair_data(last_config_id, &strategy.intervals);
             }
 
             if let Some(synthetic_timeframes) = synthetic_pair.synthetic_data.get_mut(&last_config_id) {
-                for (j, time_interval) in TimeInterval::all().iter().enumerate() {
+                for (j, time_interval) in strategy.intervals.iter().enumerate() {
                     if let (Some(first_timeframes), Some(second_timeframes)) = (
                         first_pair.pair_data_map.get(&last_config_id),
                         second_pair.pair_data_map.get(&last_config_id)
@@ -209,16 +212,18 @@ impl AssetAnalyzer{
                             first_timeframes.iter().find(|data| data.time_interval == *time_interval),
                             second_timeframes.iter().find(|data| data.time_interval == *time_interval)
                         ) {
-                            let mut pair_data;
-
-
-                            pair_data = calculate_synthetic_pair_data_h4(
-                                &first_data.candles_h4,
-                                &second_data.candles_h4,
-                                synthetic_timeframes[0].stationarity,
+                            let Some(pair_data) = calculate_synthetic_pair_data_h4(
+                                &first_data.candles,
+                                &second_data.candles,
+                                &synthetic_timeframes[j],
+                                strategy,
                                 time_interval.clone(),
                                 last_config_id
-                            )?;
+                            )? else {
+                                info!("C_W| too many gaps between the legs of {}", synthetic_pair.name.as_str());
+                                synthetic_pair.id = -2;
+                                continue;
+                            };
 
 
 
@@ -231,12 +236,13 @@ impl AssetAnalyzer{
                     }
                 }
             }
-        }
+            Ok(())
+        }))?;
 
         synthetic_pairs.retain(|synthetic_pair| {
             if synthetic_pair.id != -2 {
                 if let Some(timeframes) = synthetic_pair.synthetic_data.get(&last_config_id) {
-                    if timeframes.iter().all(|data| !data.z_score_h4.is_empty()) {
+                    if timeframes.iter().all(|data| !data.z_score.is_empty()) {
                         return true;
                     }
                 }
@@ -247,7 +253,7 @@ impl AssetAnalyzer{
 
         Ok(())
     }
-    pub fn calculate_asset_with_dickyfuller(&self, last_config_id:i32, pairs:&mut Vec<TradingPair>, synthetic_pairs:&mut Vec<SyntheticPairFullData>, stationarity:f32) ->Result<()>{
+    pub fn calculate_asset_with_dickyfuller(&self, last_config_id:i32, pairs:&mut Vec<TradingPair>, synthetic_pairs:&mut Vec<SyntheticPairFullData>, stationarity_test:&dyn StationarityTest, strategy:&TradingStrategy) ->Result<()>{
         info!(" calculate pairs with dickyfuller");
 
         for pair in &mut *pairs {
@@ -255,12 +261,12 @@ impl AssetAnalyzer{
 
             if let Some(timeframes) = pair.pair_data_map.get_mut(&last_config_id) {
                 for another_timeframe in timeframes {
-                    let candles_h4: Vec<f32> = another_timeframe.candles_h4.iter().map(|candle|
-                    candle.price
+                    let candles_h4: Vec<f32> = another_timeframe.candles.iter().map(|candle|
+                    candle.price as f32
                     ).collect();
 
                     if !candles_h4.is_empty() {
-                        another_timeframe.z_score_h4=calculate_z_score(&candles_h4,240)?;
+                        another_timeframe.z_score=calculate_z_score(&candles_h4,strategy.z_score_period as usize)?;
                     } else {
                         info!("C_W| candels is empty in pair: {}", name.as_str());
                     }
@@ -272,7 +278,7 @@ impl AssetAnalyzer{
 
         pairs.retain(|pair| {
             if let Some(timeframes) = pair.pair_data_map.get(&last_config_id) {
-                if timeframes.iter().all(|data| !data.z_score_h4.is_empty()) {
+                if timeframes.iter().all(|data| !data.z_score.is_empty()) {
                     true
                 } else {
                     info!("C_W| delete pair {}", pair.name.as_str());
@@ -284,34 +290,28 @@ impl AssetAnalyzer{
             }
         });
 
-        let mut last_update_time = Instant::now();
-
-        for (i, synthetic_pair) in synthetic_pairs.iter_mut().enumerate() {
-            if last_update_time.elapsed() >= std::time::Duration::from_secs(100) {
-                last_update_time = Instant::now(); // Оновлюємо час останнього запуску
-            }
+        let pairs_by_name = Self::pairs_by_name(pairs);
 
+        // In-place over the slice, so the order of synthetic_pairs does not depend on the scheduling
+        self.pool.install(|| synthetic_pairs.par_iter_mut().enumerate().try_for_each(|(i, synthetic_pair)| -> Result<()> {
             if i % 1000 == 0 {
                 info!("NUM: {}", i);
             }
 
-            let first_pair = pairs.iter().find(|&pair| pair.name == synthetic_pair.first_pair.name);
-            let second_pair = pairs.iter().find(|&pair| pair.name == synthetic_pair.second_pair.name);
-
-            if first_pair.is_none() || second_pair.is_none() {
+            let (Some(first_pair), Some(second_pair)) = (
+                pairs_by_name.get(synthetic_pair.first_pair.name.as_str()).copied(),
+                pairs_by_name.get(synthetic_pair.second_pair.name.as_str()).copied()
+            ) else {
                 synthetic_pair.id = -2;
-                continue;
-            }
-
-            let first_pair = first_pair.unwrap();
-            let second_pair = second_pair.unwrap();
+                return Ok(());
+            };
 
             if !synthetic_pair.synthetic_data.contains_key(&last_config_id) {
-                synthetic_pair.init_pair_data(last_config_id);
+                synthetic_pair.init_pair_data(last_config_id, &strategy.intervals);
             }
 
             if let Some(synthetic_timeframes) = synthetic_pair.synthetic_data.get_mut(&last_config_id) {
-                for (j, time_interval) in TimeInterval::all().iter().enumerate() {
+                for (j, time_interval) in strategy.intervals.iter().enumerate() {
                     if let (Some(first_timeframes), Some(second_timeframes)) = (
                         first_pair.pair_data_map.get(&last_config_id),
                         second_pair.pair_data_map.get(&last_config_id)
@@ -320,17 +320,31 @@ impl AssetAnalyzer{
                             first_timeframes.iter().find(|data| data.time_interval == *time_interval),
                             second_timeframes.iter().find(|data| data.time_interval == *time_interval)
                         ) {
-                            let mut pair_data;
-
-
-                                pair_data = calculate_synthetic_pair_data_with_dickeyfuller(
-                                    &first_data.candles_h4,
-                                    &second_data.candles_h4,
+                                // A pair the tests can not run on, e.g. a zero price in a log spread, is dropped alone
+                                let pair_data = match calculate_synthetic_pair_data_with_dickeyfuller(
+                                    &first_data.candles,
+                                    &second_data.candles,
+                                    stationarity_test,
+                                    strategy,
                                     time_interval.clone(),
                                     last_config_id
-                                )?;
-
-                                if pair_data.stationarity<stationarity&&synthetic_pair.is_request_pair.is_none(){
+                                ) {
+                                    Ok(Some(pair_data)) => pair_data,
+                                    Ok(None) => {
+                                        info!("C_W| too many gaps between the legs of {}", synthetic_pair.name.as_str());
+                                        synthetic_pair.id = -2;
+                                        continue;
+                                    }
+                                    Err(e) => {
+                                        info!("C_W| pair {} skipped: {}", synthetic_pair.name.as_str(), e);
+                                        synthetic_pair.id = -2;
+                                        continue;
+                                    }
+                                };
+
+                                let passes = passes_stationarity_rule(&pair_data, strategy.stationarity_rule, strategy.percent)
+                                    && passes_mean_reversion_limits(&pair_data, strategy.max_half_life, strategy.max_hurst);
+                                if !passes&&synthetic_pair.is_request_pair.is_none(){
                                     synthetic_pair.id = -2;
                                     continue;
                                 }
@@ -344,12 +358,13 @@ impl AssetAnalyzer{
                     }
                 }
             }
-        }
+            Ok(())
+        }))?;
 
         synthetic_pairs.retain(|synthetic_pair| {
             if synthetic_pair.id != -2 {
                 if let Some(timeframes) = synthetic_pair.synthetic_data.get(&last_config_id) {
-                    if timeframes.iter().all(|data|  !data.z_score_h4.is_empty()) {
+                    if timeframes.iter().all(|data|  !data.z_score.is_empty()) {
                         return true;
                     }
                 }
@@ -360,4 +375,355 @@ impl AssetAnalyzer{
 
         Ok(())
     }
+    // Спільна частина для кошиків: знаходимо свічки всіх ніг, кошик без будь-якої ноги видаляємо
+    fn basket_legs<'a>(pairs_by_name:&HashMap<&str, &'a TradingPair>, basket:&SyntheticBasket, config_id:ConfigId, time_interval:&TimeInterval) -> Option<Vec<&'a PairData>> {
+        basket.legs.iter().map(|leg| {
+            pairs_by_name.get(leg.name.as_str()).copied()
+                .and_then(|pair| pair.pair_data_map.get(&config_id))
+                .and_then(|timeframes| timeframes.iter().find(|data| data.time_interval == *time_interval))
+        }).collect()
+    }
+    pub fn calculate_baskets_with_johansen(&self, last_config_id:i32, pairs:&Vec<TradingPair>, baskets:&mut Vec<SyntheticBasket>, stationarity_test:&dyn StationarityTest, strategy:&TradingStrategy) ->Result<()>{
+        info!(" calculate baskets with johansen");
+        let pairs_by_name = Self::pairs_by_name(pairs);
+        // Кошики рахуємо тільки на основному інтервалі стратегії
+        let intervals = [strategy.primary_interval()];
+
+        for basket in baskets.iter_mut() {
+            if !basket.synthetic_data.contains_key(&last_config_id) {
+                basket.init_pair_data(last_config_id, &intervals);
+            }
+            for (j, time_interval) in intervals.iter().enumerate() {
+                let Some(legs) = Self::basket_legs(&pairs_by_name, basket, last_config_id, time_interval) else {
+                    basket.id = -2;
+                    break;
+                };
+                let legs_h4:Vec<&[Candle]>=legs.iter().map(|data| data.candles.as_slice()).collect();
+                match calculate_basket_data_with_johansen(&legs_h4, stationarity_test, strategy, time_interval.clone(), last_config_id) {
+                    Ok(Some((_, pair_data))) if !passes_mean_reversion_limits(&pair_data, strategy.max_half_life, strategy.max_hurst) => {
+                        basket.id = -2;
+                    }
+                    Ok(Some((weights, pair_data))) => {
+                        basket.weights = weights;
+                        if let Some(timeframes) = basket.synthetic_data.get_mut(&last_config_id) {
+                            timeframes[j] = pair_data;
+                        }
+                    }
+                    Ok(None) => {
+                        basket.id = -2;
+                    }
+                    Err(e) => {
+                        info!("C_W| basket {} skipped: {}", basket.name.as_str(), e);
+                        basket.id = -2;
+                    }
+                }
+            }
+        }
+
+        baskets.retain(|basket| basket.id != -2);
+        info!("BASKETS: {}", baskets.len());
+        Ok(())
+    }
+    pub fn calculate_baskets_h4(&self, last_config_id:i32, pairs:&Vec<TradingPair>, baskets:&mut Vec<SyntheticBasket>, strategy:&TradingStrategy) ->Result<()>{
+        info!(" calculate baskets h4");
+        let pairs_by_name = Self::pairs_by_name(pairs);
+        let intervals = [strategy.primary_interval()];
+
+        for basket in baskets.iter_mut() {
+            if !basket.synthetic_data.contains_key(&last_config_id) {
+                basket.init_pair_data(last_config_id, &intervals);
+            }
+            for (j, time_interval) in intervals.iter().enumerate() {
+                let Some(legs) = Self::basket_legs(&pairs_by_name, basket, last_config_id, time_interval) else {
+                    basket.id = -2;
+                    break;
+                };
+                let legs_h4:Vec<&[Candle]>=legs.iter().map(|data| data.candles.as_slice()).collect();
+                if let Some(timeframes) = basket.synthetic_data.get_mut(&last_config_id) {
+                    match calculate_basket_data_h4(&legs_h4, &basket.weights, &timeframes[j], strategy, time_interval.clone(), last_config_id) {
+                        Ok(Some(pair_data)) => timeframes[j] = pair_data,
+                        Ok(None) => basket.id = -2,
+                        Err(e) => {
+                            info!("C_W| basket {} skipped: {}", basket.name.as_str(), e);
+                            basket.id = -2;
+                        }
+                    }
+                }
+            }
+        }
+
+        baskets.retain(|basket| basket.id != -2);
+        Ok(())
+    }
+    pub fn calculate_baskets_minutes(&self, last_config_id:i32, pairs:&Vec<TradingPair>, baskets:&mut Vec<SyntheticBasket>, strategy:&TradingStrategy) ->Result<()>{
+        info!(" calculate baskets minutes");
+        let pairs_by_name = Self::pairs_by_name(pairs);
+        let intervals = [strategy.primary_interval()];
+
+        for basket in baskets.iter_mut() {
+            for (j, time_interval) in intervals.iter().enumerate() {
+                let Some(legs) = Self::basket_legs(&pairs_by_name, basket, last_config_id, time_interval) else {
+                    basket.id = -2;
+                    break;
+                };
+                let legs_minutes:Vec<&[Candle]>=legs.iter().map(|data| data.candles_minutes.as_slice()).collect();
+                if let Some(timeframes) = basket.synthetic_data.get_mut(&last_config_id) {
+                    match calculate_basket_data_minutes(&timeframes[j], &legs_minutes, &basket.weights, strategy, time_interval.clone(), last_config_id) {
+                        Ok(Some(pair_data)) => timeframes[j] = pair_data,
+                        Ok(None) => basket.id = -2,
+                        Err(e) => {
+                            info!("C_W| basket {} skipped: {}", basket.name.as_str(), e);
+                            basket.id = -2;
+                        }
+                    }
+                }
+            }
+        }
+
+        baskets.retain(|basket| {
+            if basket.id != -2 {
+                if let Some(timeframes) = basket.synthetic_data.get(&last_config_id) {
+                    if timeframes.iter().all(|data| !data.z_score_minutes.is_empty() && !data.z_score.is_empty()) {
+                        return true;
+                    }
+                }
+            }
+            info!("C_W|delete basket {}", basket.name.as_str());
+            false
+        });
+        Ok(())
+    }
+    // Live between two closes: the intraday candles closed since, by symbol, are fed to the pairs, synthetic pairs
+    // and baskets of the last analysis. Each gets the new candles with their z-scores and drops as many old ones,
+    // the bars, hedge ratios, weights and stationarity stay those of the close. Returns the appended candles.
+    pub fn push_minutes(&self, last_config_id:i32, pairs:&mut [TradingPair], synthetic_pairs:&mut [SyntheticPairFullData], baskets:&mut [SyntheticBasket], minutes:&HashMap<String, Vec<Candle>>, strategy:&TradingStrategy) -> Result<usize> {
+        let period = strategy.z_score_period as usize;
+        let mut pushed = 0;
+
+        for pair in pairs.iter_mut() {
+            let (Some(new_minutes), Some(timeframes)) = (minutes.get(&pair.name), pair.pair_data_map.get_mut(&last_config_id)) else {
+                continue;
+            };
+            for timeframe in timeframes {
+                match push_minute_candles(timeframe, new_minutes, period) {
+                    Ok(count) => pushed += count,
+                    Err(e) => info!("C_W| minutes of {} skipped: {}", pair.name.as_str(), e),
+                }
+            }
+        }
+
+        let pairs_by_name = Self::pairs_by_name(pairs);
+
+        pushed += self.pool.install(|| synthetic_pairs.par_iter_mut().map(|synthetic_pair| {
+            let (Some(first_pair), Some(second_pair)) = (
+                pairs_by_name.get(synthetic_pair.first_pair.name.as_str()).copied(),
+                pairs_by_name.get(synthetic_pair.second_pair.name.as_str()).copied()
+            ) else {
+                return 0;
+            };
+            let Some(synthetic_timeframes) = synthetic_pair.synthetic_data.get_mut(&last_config_id) else {
+                return 0;
+            };
+            let mut pushed = 0;
+            for synthetic_data in synthetic_timeframes.iter_mut() {
+                let (Some(first_data), Some(second_data)) = (
+                    first_pair.get_timeframe_data(last_config_id, &synthetic_data.time_interval),
+                    second_pair.get_timeframe_data(last_config_id, &synthetic_data.time_interval)
+                ) else {
+                    continue;
+                };
+                let result = synthetic_minutes_since(synthetic_data, first_data, second_data, strategy)
+                    .and_then(|new_minutes| push_minute_candles(synthetic_data, &new_minutes, period));
+                match result {
+                    Ok(count) => pushed += count,
+                    Err(e) => info!("C_W| minutes of {} skipped: {}", synthetic_pair.name.as_str(), e),
+                }
+            }
+            pushed
+        }).sum::<usize>());
+
+        // Кошики мають лише основний інтервал стратегії
+        let time_interval = strategy.primary_interval();
+        for basket in baskets.iter_mut() {
+            let Some(legs) = Self::basket_legs(&pairs_by_name, basket, last_config_id, &time_interval) else {
+                continue;
+            };
+            let legs_minutes:Vec<&[Candle]>=legs.iter().map(|data| data.candles_minutes.as_slice()).collect();
+            let Some(basket_data) = basket.synthetic_data.get_mut(&last_config_id).and_then(|timeframes| timeframes.first_mut()) else {
+                continue;
+            };
+            let new_minutes = basket_minutes_since(basket_data, &legs_minutes, &basket.weights);
+            match push_minute_candles(basket_data, &new_minutes, period) {
+                Ok(count) => pushed += count,
+                Err(e) => info!("C_W| minutes of basket {} skipped: {}", basket.name.as_str(), e),
+            }
+        }
+        Ok(pushed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::generate_synthetic_pair;
+    use crate::core::types::SpreadKind;
+    use crate::mathematics::stationarity::NativeAdf;
+    use crate::test_util::{candle, september_1};
+
+    const CONFIG_ID: ConfigId = 1;
+
+    fn trading_pair(name: &str, seed: u64) -> TradingPair {
+        trading_pair_with_intervals(name, seed, &[TimeInterval::h4])
+    }
+
+    // Every interval gets its own random walk, 200 bars for the first one and 50 fewer for each next
+    fn trading_pair_with_intervals(name: &str, seed: u64, intervals: &[TimeInterval]) -> TradingPair {
+        let mut pair = TradingPair::new();
+        pair.name = name.to_string();
+        pair.init_pair_data(CONFIG_ID, intervals);
+        let mut state = seed;
+        for (j, timeframe) in pair.pair_data_map.get_mut(&CONFIG_ID).unwrap().iter_mut().enumerate() {
+            let mut price = 100.0;
+            timeframe.candles = (0..200 - 50 * j).map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                price += (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5;
+                Candle { price, ..Candle::default() }
+            }).collect();
+        }
+        pair
+    }
+
+    fn analyze(workers: usize) -> Result<Vec<SyntheticPairFullData>> {
+        let mut strategy = TradingStrategy::new();
+        strategy.percent = 0.0;
+        strategy.z_score_period = 20;
+        let mut pairs: Vec<TradingPair> = (0..8).map(|i| trading_pair(&format!("COIN{}USDT", i), i + 1)).collect();
+        let mut synthetic_pairs = generate_synthetic_pair(&pairs, &strategy)?;
+        let mut orphan = SyntheticPairFullData::new();
+        orphan.name = "COIN0USDT/GONEUSDT".to_string();
+        orphan.first_pair.name = "COIN0USDT".to_string();
+        orphan.second_pair.name = "GONEUSDT".to_string();
+        synthetic_pairs.insert(3, orphan);
+
+        AssetAnalyzer::new(workers)?.calculate_asset_with_dickyfuller(CONFIG_ID, &mut pairs, &mut synthetic_pairs, &NativeAdf::default(), &strategy)?;
+        Ok(synthetic_pairs)
+    }
+
+    #[test]
+    fn test_parallel_analysis_is_deterministic() -> Result<()> {
+        let sequential = analyze(1)?;
+        let parallel = analyze(4)?;
+
+        let names: Vec<&str> = sequential.iter().map(|pair| pair.name.as_str()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names.len(), 28);
+        assert_eq!(names, sorted);
+        assert!(!names.contains(&"COIN0USDT/GONEUSDT"));
+        assert_eq!(names, parallel.iter().map(|pair| pair.name.as_str()).collect::<Vec<&str>>());
+        for (first, second) in sequential.iter().zip(parallel.iter()) {
+            let (first, second) = (&first.synthetic_data[&CONFIG_ID][0], &second.synthetic_data[&CONFIG_ID][0]);
+            assert_eq!(first.stationarity, second.stationarity);
+            assert_eq!(first.z_score, second.z_score);
+        }
+        assert_eq!(AssetAnalyzer::new(3)?.workers(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing_pair_does_not_stop_the_analysis() -> Result<()> {
+        let mut strategy = TradingStrategy::new();
+        strategy.percent = 0.0;
+        strategy.z_score_period = 20;
+        strategy.spread_kind = SpreadKind::LogRatio;
+        let mut pairs: Vec<TradingPair> = (0..5).map(|i| trading_pair(&format!("COIN{}USDT", i), i + 1)).collect();
+        // A zero close kept by the default quality action makes every log ratio with COIN4USDT non-finite
+        pairs[4].pair_data_map.get_mut(&CONFIG_ID).unwrap()[0].candles[100].price = 0.0;
+        let mut synthetic_pairs = generate_synthetic_pair(&pairs, &strategy)?;
+        assert_eq!(synthetic_pairs.len(), 10);
+
+        AssetAnalyzer::new(2)?.calculate_asset_with_dickyfuller(CONFIG_ID, &mut pairs, &mut synthetic_pairs, &NativeAdf::default(), &strategy)?;
+
+        assert_eq!(synthetic_pairs.len(), 6);
+        assert!(synthetic_pairs.iter().all(|pair| !pair.name.contains("COIN4USDT") && !pair.synthetic_data[&CONFIG_ID][0].z_score.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_every_strategy_interval_is_analyzed() -> Result<()> {
+        let mut strategy = TradingStrategy::new();
+        strategy.id = CONFIG_ID;
+        strategy.z_score_period = 20;
+        strategy.intervals = vec![TimeInterval::h1, TimeInterval::h4, TimeInterval::d1];
+        let mut pairs: Vec<TradingPair> = (0..3).map(|i| trading_pair_with_intervals(&format!("COIN{}USDT", i), i + 1, &strategy.intervals)).collect();
+        let mut synthetic_pairs = generate_synthetic_pair(&pairs, &strategy)?;
+
+        AssetAnalyzer::new(2)?.calculate_asset_h4(CONFIG_ID, &mut pairs, &mut synthetic_pairs, NaiveDateTime::default(), &strategy)?;
+
+        assert_eq!(synthetic_pairs.len(), 3);
+        for synthetic_pair in &synthetic_pairs {
+            let timeframes = &synthetic_pair.synthetic_data[&CONFIG_ID];
+            assert_eq!(timeframes.iter().map(|data| data.time_interval.clone()).collect::<Vec<_>>(), strategy.intervals);
+            assert_eq!(timeframes.iter().map(|data| data.z_score.len()).collect::<Vec<_>>(), vec![200, 150, 100]);
+        }
+        Ok(())
+    }
+
+    // 200 H4 bars from September 1 and the M1 candles of the 90 minutes after them
+    fn live_pair(name: &str, seed: u64) -> TradingPair {
+        let mut pair = trading_pair(name, seed);
+        let timeframe = &mut pair.pair_data_map.get_mut(&CONFIG_ID).unwrap()[0];
+        for (i, bar) in timeframe.candles.iter_mut().enumerate() {
+            *bar = candle(september_1() + Duration::hours(4 * i as i64), 240, bar.price);
+        }
+        let after_bars = september_1() + Duration::hours(4 * 200);
+        let last = timeframe.candles[199].price;
+        timeframe.candles_minutes = (0..90).map(|k| candle(after_bars + Duration::minutes(k), 1, last + ((k * seed as i64) % 7) as f64 * 0.1)).collect();
+        pair
+    }
+
+    // The close analyses the first 60 minutes, `minutes` the 60 minutes after `from`
+    fn analyze_minutes(analyzer: &AssetAnalyzer, strategy: &TradingStrategy, from: usize) -> Result<(Vec<TradingPair>, Vec<SyntheticPairFullData>)> {
+        let mut pairs: Vec<TradingPair> = (0..3).map(|i| live_pair(&format!("COIN{}USDT", i), i + 1)).collect();
+        for pair in pairs.iter_mut() {
+            let timeframe = &mut pair.pair_data_map.get_mut(&CONFIG_ID).unwrap()[0];
+            timeframe.candles_minutes = timeframe.candles_minutes[from..from + 60].to_vec();
+        }
+        let mut synthetic_pairs = generate_synthetic_pair(&pairs, strategy)?;
+        analyzer.calculate_asset_h4(CONFIG_ID, &mut pairs, &mut synthetic_pairs, NaiveDateTime::default(), strategy)?;
+        analyzer.calculate_asset_minutes(CONFIG_ID, &mut pairs, &mut synthetic_pairs, NaiveDateTime::default(), strategy)?;
+        Ok((pairs, synthetic_pairs))
+    }
+
+    #[test]
+    fn test_pushed_minutes_update_the_close() -> Result<()> {
+        let mut strategy = TradingStrategy::new();
+        strategy.z_score_period = 20;
+        let analyzer = AssetAnalyzer::new(2)?;
+        let (mut pairs, mut synthetic_pairs) = analyze_minutes(&analyzer, &strategy, 0)?;
+        let (expected_pairs, expected_synthetic_pairs) = analyze_minutes(&analyzer, &strategy, 30)?;
+        // COIN2USDT has no new candles yet
+        let minutes: HashMap<String, Vec<Candle>> = (0..2).map(|i| {
+            let pair = live_pair(&format!("COIN{}USDT", i), i + 1);
+            (pair.name.clone(), pair.pair_data_map[&CONFIG_ID][0].candles_minutes[60..].to_vec())
+        }).collect();
+
+        assert_eq!(analyzer.push_minutes(CONFIG_ID, &mut pairs, &mut synthetic_pairs, &mut [], &minutes, &strategy)?, 2 * 30 + 30);
+        assert_eq!(analyzer.push_minutes(CONFIG_ID, &mut pairs, &mut synthetic_pairs, &mut [], &minutes, &strategy)?, 0);
+
+        let close = |a: &[f32], b: &[f32]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+        for (pair, expected) in pairs.iter().zip(&expected_pairs).take(2) {
+            assert!(close(&pair.pair_data_map[&CONFIG_ID][0].z_score_minutes, &expected.pair_data_map[&CONFIG_ID][0].z_score_minutes));
+        }
+        for (synthetic_pair, expected) in synthetic_pairs.iter().zip(&expected_synthetic_pairs) {
+            let (data, expected) = (&synthetic_pair.synthetic_data[&CONFIG_ID][0], &expected.synthetic_data[&CONFIG_ID][0]);
+            assert_eq!(data.candles_minutes.len(), 60);
+            if synthetic_pair.name == "COIN0USDT/COIN1USDT" {
+                assert!(close(&data.z_score_minutes, &expected.z_score_minutes));
+            } else {
+                assert_eq!(data.candles_minutes.last().unwrap().open_time, september_1() + Duration::hours(800) + Duration::minutes(59));
+            }
+        }
+        Ok(())
+    }
 }
@@ -1 +1,2 @@
-pub mod asset;
\ No newline at end of file
+pub mod asset;
+pub mod quality;
@@ -0,0 +1,225 @@
+use tracing::info;
+use crate::core::types::{Candle, ConfigId, QualityAction, TimeInterval, TradingPair, TradingStrategy};
+
+// Data-quality pass over the bars returned by the exchange, run before AssetAnalyzer. Every flagged
+// timeframe of a pair gets a report, what happens to the pair is decided by the strategy.
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CandleQualityReport {
+    pub pair_name: String,
+    pub time_interval: TimeInterval,
+    pub bars: i32,
+    // Bars absent between the first and the last open_time
+    pub missing_bars: i32,
+    // Repeated open_time
+    pub duplicated_bars: i32,
+    // Bars opened earlier than the bar received before them
+    pub unordered_bars: i32,
+    // Zero, negative or non-finite prices
+    pub non_positive_prices: i32,
+    // Moves from the previous bar above the strategy max_jump_percent
+    pub extreme_jumps: i32,
+    pub action: QualityAction,
+}
+impl CandleQualityReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing_bars == 0 && self.duplicated_bars == 0 && self.unordered_bars == 0
+            && self.non_positive_prices == 0 && self.extreme_jumps == 0
+    }
+}
+
+fn is_valid_price(price: f64) -> bool {
+    price.is_finite() && price > 0.0
+}
+
+fn is_jump(previous: f64, current: f64, max_jump_percent: f32) -> bool {
+    ((current / previous - 1.0).abs() * 100.0) > max_jump_percent as f64
+}
+
+// Stable sort by open_time, of the candles sharing an open_time the last received one is kept
+fn sort_and_deduplicate(candles: &mut Vec<Candle>) {
+    candles.sort_by_key(|candle| candle.open_time);
+    candles.dedup_by(|later, earlier| {
+        if later.open_time == earlier.open_time {
+            *earlier = later.clone();
+            return true;
+        }
+        false
+    });
+}
+
+pub fn inspect_candles(candles: &[Candle], interval: &TimeInterval, max_jump_percent: f32) -> CandleQualityReport {
+    let mut report = CandleQualityReport { time_interval: interval.clone(), bars: candles.len() as i32, ..Default::default() };
+    report.unordered_bars = candles.windows(2).filter(|pair| pair[1].open_time < pair[0].open_time).count() as i32;
+    report.non_positive_prices = candles.iter().filter(|candle| !is_valid_price(candle.price)).count() as i32;
+
+    let mut sorted = candles.to_vec();
+    sorted.sort_by_key(|candle| candle.open_time);
+    let step = interval.minutes();
+    for pair in sorted.windows(2) {
+        let minutes = (pair[1].open_time - pair[0].open_time).num_minutes();
+        if minutes == 0 {
+            report.duplicated_bars += 1;
+        } else if minutes > step {
+            report.missing_bars += (minutes / step - 1) as i32;
+        }
+    }
+
+    sort_and_deduplicate(&mut sorted);
+    let prices: Vec<f64> = sorted.iter().map(|candle| candle.price).filter(|&price| is_valid_price(price)).collect();
+    report.extreme_jumps = prices.windows(2).filter(|pair| is_jump(pair[0], pair[1], max_jump_percent)).count() as i32;
+    report
+}
+
+// Missing bars are not invented here, the legs of synthetic pairs are joined on open_time later.
+// A jump is only undone when the next bar returns to the level before it, a lasting move is real.
+pub fn repair_candles(candles: &mut Vec<Candle>, max_jump_percent: f32) {
+    sort_and_deduplicate(candles);
+
+    let mut last_valid: Option<Candle> = None;
+    candles.retain_mut(|candle| {
+        if is_valid_price(candle.price) {
+            last_valid = Some(candle.clone());
+            return true;
+        }
+        match &last_valid {
+            Some(last) => {
+                *candle = last.flat(candle.open_time, candle.close_time);
+                true
+            }
+            None => false,
+        }
+    });
+
+    for i in 1..candles.len().saturating_sub(1) {
+        let (previous, current, next) = (candles[i - 1].price, candles[i].price, candles[i + 1].price);
+        if is_jump(previous, current, max_jump_percent) && is_jump(current, next, max_jump_percent) && !is_jump(previous, next, max_jump_percent) {
+            candles[i] = candles[i - 1].flat(candles[i].open_time, candles[i].close_time);
+        }
+    }
+}
+
+// Inspects the bars of every pair for the configuration and applies the strategy quality_action.
+// Returns the reports of the flagged timeframes, excluded pairs are removed from pairs.
+pub fn check_candle_quality(pairs: &mut Vec<TradingPair>, config_id: ConfigId, strategy: &TradingStrategy) -> Vec<CandleQualityReport> {
+    let mut reports = Vec::new();
+    for pair in pairs.iter_mut() {
+        let Some(timeframes) = pair.pair_data_map.get_mut(&config_id) else {
+            continue;
+        };
+        let action = match strategy.quality_action {
+            QualityAction::Exclude if pair.is_request_pair.is_some() => QualityAction::Repair,
+            action => action,
+        };
+        let mut flagged = false;
+        for timeframe in timeframes.iter_mut() {
+            let mut report = inspect_candles(&timeframe.candles, &timeframe.time_interval, strategy.max_jump_percent);
+            if report.is_clean() {
+                continue;
+            }
+            flagged = true;
+            if action == QualityAction::Repair {
+                repair_candles(&mut timeframe.candles, strategy.max_jump_percent);
+            }
+            report.pair_name = pair.name.clone();
+            report.action = action;
+            reports.push(report);
+        }
+        if flagged && action == QualityAction::Exclude {
+            info!("C_W| candles of {} failed the quality check", pair.name);
+            pair.id = -2;
+        }
+    }
+    pairs.retain(|pair| pair.id != -2);
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::h4_candles;
+
+    const CONFIG_ID: ConfigId = 1;
+
+    // A pair with H4 candles at the given bar numbers with the given prices, in the order they are listed
+    fn pair(name: &str, bars: &[(i64, f64)]) -> TradingPair {
+        let mut pair = TradingPair::new();
+        pair.name = name.to_string();
+        pair.init_pair_data(CONFIG_ID, &[TimeInterval::h4]);
+        pair.pair_data_map.get_mut(&CONFIG_ID).unwrap()[0].candles = h4_candles(bars);
+        pair
+    }
+
+    fn prices(candles: &[Candle]) -> Vec<f64> {
+        candles.iter().map(|candle| candle.price).collect()
+    }
+
+    // Bar 3 missing, bar 5 received twice, bar 2 received after bar 4, a zero price and a spike on bar 7
+    fn flawed() -> Vec<(i64, f64)> {
+        vec![(0, 10.0), (1, 10.5), (4, 0.0), (2, 11.0), (5, 11.0), (5, 11.5), (6, 11.2), (7, 30.0), (8, 11.4), (9, 11.6)]
+    }
+
+    #[test]
+    fn test_inspect_flags_every_issue() {
+        let report = inspect_candles(&h4_candles(&flawed()), &TimeInterval::h4, 50.0);
+        assert_eq!(report.bars, 10);
+        assert_eq!(report.missing_bars, 1);
+        assert_eq!(report.duplicated_bars, 1);
+        assert_eq!(report.unordered_bars, 1);
+        assert_eq!(report.non_positive_prices, 1);
+        assert_eq!(report.extreme_jumps, 2);
+        assert!(!report.is_clean());
+
+        let clean = h4_candles(&(0..10).map(|k| (k, 100.0 + k as f64)).collect::<Vec<_>>());
+        assert!(inspect_candles(&clean, &TimeInterval::h4, 50.0).is_clean());
+        // Read as hourly bars, every four-hour step misses three of them
+        assert_eq!(inspect_candles(&clean, &TimeInterval::h1, 50.0).missing_bars, 27);
+    }
+
+    #[test]
+    fn test_repair_keeps_lasting_moves() {
+        let mut repaired = h4_candles(&flawed());
+        repair_candles(&mut repaired, 50.0);
+
+        assert!(repaired.windows(2).all(|pair| pair[0].open_time < pair[1].open_time));
+        assert_eq!(prices(&repaired), vec![10.0, 10.5, 11.0, 11.0, 11.5, 11.2, 11.2, 11.4, 11.6]);
+        let report = inspect_candles(&repaired, &TimeInterval::h4, 50.0);
+        assert_eq!((report.missing_bars, report.duplicated_bars, report.unordered_bars, report.non_positive_prices, report.extreme_jumps), (1, 0, 0, 0, 0));
+
+        // A level shift that holds is kept, a leading bad price is dropped
+        let mut shifted = h4_candles(&[(0, -1.0), (1, 10.0), (2, 10.0), (3, 30.0), (4, 30.5)]);
+        repair_candles(&mut shifted, 50.0);
+        assert_eq!(prices(&shifted), vec![10.0, 10.0, 30.0, 30.5]);
+    }
+
+    #[test]
+    fn test_strategy_decides_on_flagged_pairs() {
+        let clean: Vec<(i64, f64)> = (0..10).map(|k| (k, 100.0 + k as f64)).collect();
+        let mut strategy = TradingStrategy { id: CONFIG_ID, ..Default::default() };
+        let pairs = || {
+            let mut requested = pair("ETHUSDT", &flawed());
+            requested.is_request_pair = Some(true);
+            vec![pair("BTCUSDT", &clean), pair("XRPUSDT", &flawed()), requested]
+        };
+
+        let mut kept = pairs();
+        let reports = check_candle_quality(&mut kept, CONFIG_ID, &strategy);
+        assert_eq!(kept.len(), 3);
+        assert_eq!(reports.iter().map(|report| report.pair_name.as_str()).collect::<Vec<_>>(), vec!["XRPUSDT", "ETHUSDT"]);
+        assert!(reports.iter().all(|report| report.action == QualityAction::Keep && report.time_interval == TimeInterval::h4));
+        assert_eq!(prices(&kept[1].pair_data_map[&CONFIG_ID][0].candles), prices(&h4_candles(&flawed())));
+
+        strategy.quality_action = QualityAction::Repair;
+        let mut repaired = pairs();
+        check_candle_quality(&mut repaired, CONFIG_ID, &strategy);
+        assert_eq!(repaired.len(), 3);
+        assert_eq!(repaired[1].pair_data_map[&CONFIG_ID][0].candles.len(), 9);
+
+        strategy.quality_action = QualityAction::Exclude;
+        let mut excluded = pairs();
+        let reports = check_candle_quality(&mut excluded, CONFIG_ID, &strategy);
+        assert_eq!(excluded.iter().map(|pair| pair.name.as_str()).collect::<Vec<_>>(), vec!["BTCUSDT", "ETHUSDT"]);
+        assert_eq!(reports.iter().map(|report| report.action).collect::<Vec<_>>(), vec![QualityAction::Exclude, QualityAction::Repair]);
+        assert_eq!(excluded[1].pair_data_map[&CONFIG_ID][0].candles.len(), 9);
+    }
+}
@@ -1,11 +1,13 @@
+use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
 use tokio::time::{sleep};
 use binance_async::futures::market::FuturesMarket;
 use tokio::sync::Mutex;
 use crate::analysis::asset::{AssetAnalyzer};
+use crate::analysis::quality::check_candle_quality;
 
-use crate::core::types::{BaseAsset, SyntheticPair, SyntheticPairFullData, TimeInterval};
+use crate::core::types::{BaseAsset, Candle, SyntheticBasket, SyntheticPair, SyntheticPairFullData, TimeInterval};
 use crate::exchange::binance::{BinanceExchange, ExchangeInterface};
 
 use crate::storage::database::{DatabaseInterface, PostgresDataBase};
@@ -15,10 +17,11 @@ use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
 use serde_json::json;
 
 use tokio::task;
-use tracing::info;
+use tracing::{error, info};
 use crate::core::config::Config;
-use crate::logic::{generate_synthetic_pair, remove_duplicate_full_pairs};
-use crate::core::types::{ConfigId, TradingPair, PairData};
+use crate::logic::{generate_synthetic_baskets, generate_synthetic_pair, remove_duplicate_full_pairs};
+use crate::mathematics::stationarity::stationarity_test;
+use crate::core::types::{ConfigId, TradingPair, PairData, ReplayWindow, RunId};
 
 
 pub struct BinanceCollector<D: DatabaseInterface,C:ExchangeInterface> {
@@ -29,21 +32,107 @@ pub struct BinanceCollector<D: DatabaseInterface,C:ExchangeInterface> {
     current_day:i32,
     logs_id: i32,
     base_date:NaiveDateTime,
+    // The analysed moment: the simulated day in replay, the last analysed close live
     last_date_bypass: NaiveDateTime,
+    mode: CollectorMode,
+    // The run the progress, analyses and pair data belong to
+    run_id: RunId,
+    // Days a replay analyses, stored with the run
+    replay_window: ReplayWindow,
+    // Endpoint the analysed pairs are sent to
+    export_url: String,
+    // Live, the analysis of the last close, fed with the intraday candles of the stream until the next one
+    live_analysis: Option<LiveAnalysis>,
+}
+// Pairs, synthetic pairs and baskets of a live close before the export trims them
+struct LiveAnalysis {
+    config_id: ConfigId,
+    pairs: Vec<TradingPair>,
+    synthetic_pairs: Vec<SyntheticPairFullData>,
+    baskets: Vec<SyntheticBasket>,
+}
+// Seconds after a close before the live run starts, so the closed bar is served by the exchange
+const LIVE_SETTLE_SECONDS: i64 = 5;
+// Reloads of the requested pairs still missing candles after the first load of a day
+const MISSING_CANDLES_ATTEMPTS: usize = 3;
+
+// What drives the collector. Both modes share the analysis, storage and export of run_stationarity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum CollectorMode {
+    // Over the days of the replay window, the next day starts once date_going reports the previous one consumed
+    #[default]
+    Replay,
+    // On every UTC close of `interval`, for the closes as they happen and without an end
+    Live { interval: TimeInterval },
+}
+impl CollectorMode {
+    // `live` runs on the closes of `interval` (a Binance name such as 4h), an empty mode or `replay` replays
+    pub fn parse(mode: &str, interval: &str) -> Result<Self> {
+        match mode {
+            "" | "replay" => Ok(CollectorMode::Replay),
+            "live" => {
+                let interval = TimeInterval::from_binance_name(interval).ok_or(anyhow!("unknown live interval {}", interval))?;
+                Ok(CollectorMode::Live { interval })
+            }
+            mode => Err(anyhow!("unknown collector mode {}, expected replay or live", mode)),
+        }
+    }
 }
-const MAX_DAYS: i32 = 270;
 impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
-    pub async fn new(db:D,exchange:C)-> Result<BinanceCollector<D,C>> {
-        let mut start_info =db.get_start_info().await?;
+    pub async fn new(db:D,exchange:C,run_id:RunId)-> Result<BinanceCollector<D,C>> {
+        let mut start_info =db.get_start_info(run_id).await?;
+        let workers = env::var("ANALYZER_WORKERS").ok().and_then(|workers| workers.parse().ok()).unwrap_or(0);
         let mut bot=BinanceCollector{current_day:start_info.current_day,
             last_date_bypass:start_info.last_date_bypass,base_date:start_info.base_date,logs_id:start_info.last_logs_id,
-            exchange,config:Config::new(&db).await?,storage:db,asset_analyzer:AssetAnalyzer::new()};
+            exchange,config:Config::for_strategy(&db,start_info.strategy).await?,storage:db,run_id,asset_analyzer:AssetAnalyzer::new(workers)?,mode:CollectorMode::Replay,
+            replay_window:start_info.replay_window,export_url:env::var("URL").unwrap_or_else(|_| "http://localhost:3000/upload".to_string()),live_analysis:None};
+        info!("run {}, analyzer workers: {}", run_id, bot.asset_analyzer.workers());
         Ok(bot)
     }
+    pub fn with_mode(mut self, mode:CollectorMode)->Self{
+        info!("collector mode: {:?}", mode);
+        self.mode=mode;
+        self
+    }
+    // 0 uses every core
+    pub fn with_analyzer_workers(mut self, workers:usize)->Result<Self>{
+        self.asset_analyzer=AssetAnalyzer::new(workers)?;
+        info!("analyzer workers: {}", self.asset_analyzer.workers());
+        Ok(self)
+    }
+    pub fn with_export_url(mut self, export_url:String)->Self{
+        self.export_url=export_url;
+        self
+    }
+    // Replays `window` and stores it with the run. A run with days done only goes on with the window
+    // it was started with, or with a new end of it.
+    pub async fn with_replay_window(mut self, window:ReplayWindow)->Result<Self>{
+        self.replay_window.check_resume(&window, self.current_day)?;
+        if window != self.replay_window {
+            self.storage.save_replay_window(self.run_id, &window).await?;
+        }
+        self.base_date=window.day(0);
+        self.last_date_bypass=window.day(self.current_day);
+        info!("replay from {} to {} every {} days, day {} of {}", window.start, window.end, window.step_days, self.current_day, window.days());
+        self.replay_window=window;
+        Ok(self)
+    }
     pub fn get_time_now(&self) ->NaiveDateTime{
 
         self.last_date_bypass
     }
+    // The analysis uses the candles closed before this moment: the end of the simulated day in replay,
+    // the analysed close live
+    fn candles_until(&self) -> NaiveDateTime {
+        match self.mode {
+            CollectorMode::Replay => self.get_time_now() + Duration::days(1),
+            CollectorMode::Live { .. } => self.get_time_now(),
+        }
+    }
+    // The latest close a live run is due for
+    fn live_close(interval: &TimeInterval) -> NaiveDateTime {
+        interval.last_close(Utc::now().naive_utc() - Duration::seconds(LIVE_SETTLE_SECONDS))
+    }
 
     async fn start_to_stationarity(&mut self, new_log_id:i32) ->Result<NaiveDateTime>{//%
         info!("start stationarity");
@@ -53,12 +142,12 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
             log_id= new_log_id;
         }else {
             let config_id = self.config.get_last_config_id()?;
-            let id = self.storage.start_market_analysis(time.clone(), config_id).await?;
+            let id = self.storage.start_market_analysis(self.run_id, time.clone(), config_id).await?;
             log_id=id;
             self.logs_id = id;
         }
         self.last_date_bypass = time;
-        self.storage.start_update(log_id,time).await?;
+        self.storage.start_update(self.run_id,log_id,time).await?;
         Ok(time)
     }
     async fn end_to_stationarity(&mut self,new_log_id:i32)->Result<()> {//%
@@ -68,33 +157,61 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
             self.storage.finish_analysis(date, self.logs_id).await?;
 
         }
-        self.storage.finish_update().await?;
+        self.storage.finish_update(self.run_id).await?;
         Ok(())
     }
+    // Свічка для експорту, з ohlcv додаються open/high/low, обсяги та кількість угод
+    fn serialize_candle(c: &Candle, ohlcv: bool) -> serde_json::Value {
+        let mut candle = json!({
+            "price": c.price,
+            "close_time": c.close_time.to_string()
+        });
+        if ohlcv {
+            candle["open_time"] = json!(c.open_time.to_string());
+            candle["open"] = json!(c.open);
+            candle["high"] = json!(c.high);
+            candle["low"] = json!(c.low);
+            candle["volume"] = json!(c.volume);
+            candle["quote_volume"] = json!(c.quote_volume);
+            candle["trades"] = json!(c.trades);
+        }
+        candle
+    }
+    // Підсумок одного інтервалу для масиву "timeframes"
+    fn serialize_timeframe(data: &PairData, ohlcv: bool) -> serde_json::Value {
+        json!({
+            "interval": data.time_interval.binance_name(),
+            "stationarity": data.stationarity,
+            "candles": data.candles.iter().map(|c| Self::serialize_candle(c, ohlcv)).collect::<Vec<_>>(),
+            "z_scores": data.z_score
+        })
+    }
     fn serialize_trading_pair(&self, pair: &TradingPair) -> Result<String> {
         let config_id = self.config.get_last_config_id()?;
+        let strategy = self.config.get(config_id)?;
+        let primary_interval = strategy.primary_interval();
+        // Synthetic series have no OHLCV, only the candles of real pairs are exported in full
+        let ohlcv = strategy.export_ohlcv;
 
-        let h4_data = pair.get_timeframe_data(config_id, &TimeInterval::h4)
-            .ok_or_else(|| anyhow!("Немає даних H4 для пари {}", pair.name))?;
+        let h4_data = pair.get_timeframe_data(config_id, &primary_interval)
+            .ok_or_else(|| anyhow!("Немає даних {} для пари {}", primary_interval.binance_name(), pair.name))?;
+        let timeframes = pair.pair_data_map.get(&config_id).map(|timeframes| timeframes.iter().map(|data| Self::serialize_timeframe(data, ohlcv)).collect::<Vec<_>>()).unwrap_or_default();
 
         let pair_json = json!({
             "id": pair.id,
             "name": pair.name,
             "is_request_pair": pair.is_request_pair,
+            "interval": primary_interval.binance_name(),
             "stationarity": h4_data.stationarity,
-            "candles": h4_data.candles_h4.iter().map(|c| {
-                json!({
-                    "price": c.price,
-                    "close_time": c.close_time.to_string()
-                })
-            }).collect::<Vec<_>>(),
-            "z_scores": h4_data.z_score_h4,
+            "candles": h4_data.candles.iter().map(|c| Self::serialize_candle(c, ohlcv)).collect::<Vec<_>>(),
+            "z_scores": h4_data.z_score,
             "z_score_minutes": h4_data.z_score_minutes,
             "candles_minutes":h4_data.candles_minutes.iter().map(|c| {
 
                     c.price
 
             }).collect::<Vec<_>>(),
+            "timeframes": timeframes,
 
             "configuration_id": config_id
         });
@@ -105,8 +222,11 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
     fn serialize_synthetic_pair(&self, syn_pair: &SyntheticPairFullData) -> Result<String> {
         let config_id = self.config.get_last_config_id()?;
 
-        let h4_data = syn_pair.get_timeframe_data(config_id, &TimeInterval::h4)
-            .ok_or_else(|| anyhow!("Немає даних H4 для синтетичної пари {}", syn_pair.name))?;
+        let primary_interval = self.config.get(config_id)?.primary_interval();
+
+        let h4_data = syn_pair.get_timeframe_data(config_id, &primary_interval)
+            .ok_or_else(|| anyhow!("Немає даних {} для синтетичної пари {}", primary_interval.binance_name(), syn_pair.name))?;
+        let timeframes = syn_pair.synthetic_data.get(&config_id).map(|timeframes| timeframes.iter().map(|data| Self::serialize_timeframe(data, false)).collect::<Vec<_>>()).unwrap_or_default();
 
         let syn_pair_json = json!({
             "id": syn_pair.id,
@@ -118,36 +238,92 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
             "second_pair": {
                 "name": syn_pair.second_pair.name
             },
+            "interval": primary_interval.binance_name(),
             "stationarity": h4_data.stationarity,
-            "candles": h4_data.candles_h4.iter().map(|c| {
+            "kpss_p_value": h4_data.kpss_p_value,
+            "pp_p_value": h4_data.pp_p_value,
+            "hedge_ratio": h4_data.hedge_ratio,
+            "hedge_intercept": h4_data.hedge_intercept,
+            "spread_kind": h4_data.spread_kind.as_str(),
+            "half_life": h4_data.half_life,
+            "hurst": h4_data.hurst,
+            "repaired_bars": h4_data.repaired_bars,
+            "candles": h4_data.candles.iter().map(|c| {
                 json!({
                     "price": c.price,
                     "close_time": c.close_time.to_string()
                 })
             }).collect::<Vec<_>>(),
-            "z_scores": h4_data.z_score_h4,
+            "z_scores": h4_data.z_score,
             "z_score_minutes": h4_data.z_score_minutes,
             "candles_minutes":h4_data.candles_minutes.iter().map(|c| {
 
                     c.price
 
             }).collect::<Vec<_>>(),
+            "timeframes": timeframes,
             "configuration_id": config_id
         });
 
         Ok(serde_json::to_string(&syn_pair_json)?)
     }
 
+    fn serialize_synthetic_basket(&self, basket: &SyntheticBasket) -> Result<String> {
+        let config_id = self.config.get_last_config_id()?;
+
+        let primary_interval = self.config.get(config_id)?.primary_interval();
+
+        let h4_data = basket.get_timeframe_data(config_id, &primary_interval)
+            .ok_or_else(|| anyhow!("Немає даних {} для кошика {}", primary_interval.binance_name(), basket.name))?;
+
+        let basket_json = json!({
+            "id": basket.id,
+            "name": basket.name,
+            "legs": basket.legs.iter().zip(basket.weights.iter()).map(|(leg, weight)| {
+                json!({
+                    "name": leg.name,
+                    "weight": weight
+                })
+            }).collect::<Vec<_>>(),
+            "interval": primary_interval.binance_name(),
+            "stationarity": h4_data.stationarity,
+            "kpss_p_value": h4_data.kpss_p_value,
+            "pp_p_value": h4_data.pp_p_value,
+            "spread_kind": h4_data.spread_kind.as_str(),
+            "half_life": h4_data.half_life,
+            "hurst": h4_data.hurst,
+            "candles": h4_data.candles.iter().map(|c| {
+                json!({
+                    "price": c.price,
+                    "close_time": c.close_time.to_string()
+                })
+            }).collect::<Vec<_>>(),
+            "z_scores": h4_data.z_score,
+            "z_score_minutes": h4_data.z_score_minutes,
+            "candles_minutes":h4_data.candles_minutes.iter().map(|c| {
+
+                    c.price
+
+            }).collect::<Vec<_>>(),
+            "configuration_id": config_id
+        });
+
+        Ok(serde_json::to_string(&basket_json)?)
+    }
+
 
 
-    async fn send_pairs_to_http_server(&self, pairs: &Vec<TradingPair>, synthetic_pairs: &Vec<SyntheticPairFullData>) -> Result<()> {
+    async fn send_pairs_to_http_server(&self, pairs: &Vec<TradingPair>, synthetic_pairs: &Vec<SyntheticPairFullData>, baskets: &Vec<SyntheticBasket>) -> Result<()> {
         let client = reqwest::Client::new();
-        let base_url = env::var("URL").unwrap_or_else(|_| "http://localhost:3000/upload".to_string());
+        let base_url = &self.export_url;
+        // Only a receiver reading this parameter keeps the exports of the runs apart. One ignoring it holds a single
+        // export, and the /clear of a run wipes the export of any other run in progress.
+        let run = [("run_id", self.run_id)];
 
         const BATCH_SIZE: usize = 100;
 
         info!("Clearing old data...");
-        let clear_response = client.post(&format!("{}/clear", base_url))
+        let clear_response = client.post(&format!("{}/clear", base_url)).query(&run)
             .send()
             .await?;
         if !clear_response.status().is_success() {
@@ -171,7 +347,7 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
                 continue;
             }
 
-            let response = client.post(&format!("{}/append_trading", base_url))
+            let response = client.post(&format!("{}/append_trading", base_url)).query(&run)
                 .header("Content-Type", "application/json")
                 .body(batch_json.clone()) // Клонуємо для логування або повторних спроб
                 .send()
@@ -185,7 +361,7 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
         }
 
         info!("Starting synthetic pairs section...");
-        let start_synthetic_response = client.post(&format!("{}/start_synthetic", base_url))
+        let start_synthetic_response = client.post(&format!("{}/start_synthetic", base_url)).query(&run)
             .send()
             .await?;
         if !start_synthetic_response.status().is_success() {
@@ -208,7 +384,7 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
                 continue;
             }
 
-            let response = client.post(&format!("{}/append_synthetic", base_url))
+            let response = client.post(&format!("{}/append_synthetic", base_url)).query(&run)
                 .header("Content-Type", "application/json")
                 .body(batch_json.clone())
                 .send()
@@ -221,8 +397,40 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
             info!("Sent batch {} of synthetic pairs", batch_idx + 1);
         }
 
+        // Кошики шлемо лише коли стратегія їх увімкнула
+        if !baskets.is_empty() {
+            info!("Sending baskets...");
+            for (batch_idx, chunk) in baskets.chunks(BATCH_SIZE).enumerate() {
+                let mut batch_json = String::new();
+                for (i, basket) in chunk.iter().enumerate() {
+                    if let Ok(basket_json) = self.serialize_synthetic_basket(basket) {
+                        if i > 0 {
+                            batch_json.push_str(",");
+                        }
+                        batch_json.push_str(&basket_json);
+                    }
+                }
+
+                if batch_json.is_empty() {
+                    continue;
+                }
+
+                let response = client.post(&format!("{}/append_basket", base_url)).query(&run)
+                    .header("Content-Type", "application/json")
+                    .body(batch_json)
+                    .send()
+                    .await?;
+
+                if !response.status().is_success() {
+
+                    return Err(anyhow!("Error sending baskets: status {}", response.status()));
+                }
+                info!("Sent batch {} of baskets", batch_idx + 1);
+            }
+        }
+
         info!("Finalizing data...");
-        let finalize_response = client.post(&format!("{}/finalize", base_url))
+        let finalize_response = client.post(&format!("{}/finalize", base_url)).query(&run)
             .send()
             .await?;
         if !finalize_response.status().is_success() {
@@ -232,29 +440,94 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
         info!("Data successfully sent to HTTP server");
         Ok(())
     }
-    async fn update_pair(&mut self,pairs:&mut Vec<TradingPair>,synthetic_pairs: &mut Vec<SyntheticPairFullData>) ->Result<()>{//%
+    async fn update_pair(&mut self,pairs:&mut Vec<TradingPair>,synthetic_pairs: &mut Vec<SyntheticPairFullData>,baskets: &mut Vec<SyntheticBasket>) ->Result<()>{//%
 
 
         for i in 0..pairs.len() {
-            self.storage.save_trading_instrument(&self.config,&mut pairs[i],self.logs_id).await?;
+            self.storage.save_trading_instrument(&self.config,&mut pairs[i],self.run_id,self.logs_id).await?;
         }
 
         for i in 0..synthetic_pairs.len() {
             self.storage.save_synthetic_trading_pair(&self.config,&mut synthetic_pairs[i], self.logs_id).await?;
         }
 
-        self.send_pairs_to_http_server(&pairs,&synthetic_pairs).await?;
+        for i in 0..baskets.len() {
+            self.storage.save_synthetic_basket(&self.config,&mut baskets[i], self.logs_id).await?;
+        }
+
+        self.send_pairs_to_http_server(&pairs,&synthetic_pairs,&baskets).await?;
         Ok(())
     }
     pub async fn is_stationarity_time(&mut self) -> Result<bool> {//%
         self.config.update_configuration(&self.storage).await?;
+        if let CollectorMode::Live { interval } = &self.mode {
+            return Ok(Self::live_close(interval) > self.last_date_bypass);
+        }
 
-        let mut res=self.storage.check_for_new_date_going().await?;
-        if self.current_day==MAX_DAYS{
+        let mut res=self.storage.check_for_new_date_going(self.run_id).await?;
+        if self.is_finished(){
             res=false;
         }
         Ok(res)
     }
+    // Live between two closes: the intraday candles the kline stream closed since the last call are fed to the
+    // analysis of the close, which is exported again. The database keeps the analysis of the close.
+    pub async fn update_live(&mut self) -> Result<()> {
+        if self.feed_live_minutes().await? == 0 {
+            return Ok(());
+        }
+        let Some(analysis) = &self.live_analysis else {
+            return Ok(());
+        };
+        let (mut pairs, mut synthetic_pairs, mut baskets) = (analysis.pairs.clone(), analysis.synthetic_pairs.clone(), analysis.baskets.clone());
+        self.trim_data_to_last_10_elements(&mut pairs, &mut synthetic_pairs)?;
+        self.trim_baskets_to_last_10_elements(&mut baskets);
+        self.send_pairs_to_http_server(&pairs, &synthetic_pairs, &baskets).await
+    }
+    // Returns the number of candles the analysis got. An analysis of a strategy no longer the last one is dropped.
+    async fn feed_live_minutes(&mut self) -> Result<usize> {
+        let Some(mut analysis) = self.live_analysis.take() else {
+            return Ok(0);
+        };
+        if analysis.config_id != self.config.get_last_config_id()? {
+            info!("live: strategy changed, the analysis waits for the next close");
+            return Ok(0);
+        }
+        let strategy = self.config.get(analysis.config_id)?;
+        let mut minutes = HashMap::new();
+        for pair in &analysis.pairs {
+            let last = pair.pair_data_map.get(&analysis.config_id)
+                .and_then(|timeframes| timeframes.first())
+                .and_then(|data| data.candles_minutes.last());
+            let Some(last) = last else {
+                continue;
+            };
+            match self.exchange.get_streamed_candles(&pair.name, &strategy.intraday_interval, last.open_time).await {
+                Ok(candles) if !candles.is_empty() => {
+                    minutes.insert(pair.name.clone(), candles);
+                }
+                Ok(_) => {}
+                Err(e) => error!("live: streamed candles of {}: {:?}", pair.name, e),
+            }
+        }
+        if minutes.is_empty() {
+            self.live_analysis = Some(analysis);
+            return Ok(0);
+        }
+        let analyzer = self.asset_analyzer.clone();
+        let (analysis, pushed) = task::spawn_blocking(move || {
+            let pushed = analyzer.push_minutes(analysis.config_id, &mut analysis.pairs, &mut analysis.synthetic_pairs, &mut analysis.baskets, &minutes, &strategy);
+            (analysis, pushed)
+        }).await?;
+        self.live_analysis = Some(analysis);
+        let pushed = pushed?;
+        info!("live: {} intraday candles fed to the analysis", pushed);
+        Ok(pushed)
+    }
+    // Every day of the replay window is analysed, the live mode never finishes
+    pub fn is_finished(&self) -> bool {
+        self.mode==CollectorMode::Replay && self.current_day>=self.replay_window.days()
+    }
     async fn add_requested_regular_pairs(&self, pairs: &mut Vec<TradingPair>, config_id: i32) -> Result<()> {
         let requested_pairs = self.storage.get_requested_regular_pairs(config_id).await?;
 
@@ -269,7 +542,7 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
                     pair_data_map: std::collections::HashMap::new(),
                     is_request_pair:Some(false)
                 };
-                new_pair.init_pair_data(config_id);
+                new_pair.init_pair_data(config_id, &self.config.get(config_id)?.intervals);
                 pairs.push(new_pair);
             }else{
                 pairs.iter_mut().find(|pair| pair.name==req_pair.name)
@@ -305,7 +578,7 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
                     synthetic_data: std::collections::HashMap::new(),
                 };
 
-                new_pair.init_pair_data(config_id);
+                new_pair.init_pair_data(config_id, &self.config.get(config_id)?.intervals);
                 synthetic_pairs.push(new_pair);
             }else{
                 synthetic_pairs.iter_mut().find(|pair| pair.name==req_pair.name)
@@ -321,13 +594,13 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
         for pair in pairs.iter_mut() {
             for (_, timeframes) in pair.pair_data_map.iter_mut() {
                 for timeframe_data in timeframes.iter_mut() {
-                    if timeframe_data.candles_h4.len() > 10 {
-                        let len = timeframe_data.candles_h4.len();
-                        timeframe_data.candles_h4 = timeframe_data.candles_h4.split_off(len - 10);
+                    if timeframe_data.candles.len() > 10 {
+                        let len = timeframe_data.candles.len();
+                        timeframe_data.candles = timeframe_data.candles.split_off(len - 10);
                     }
-                    if timeframe_data.z_score_h4.len() > 10 {
-                        let len = timeframe_data.z_score_h4.len();
-                        timeframe_data.z_score_h4 = timeframe_data.z_score_h4.split_off(len - 10);
+                    if timeframe_data.z_score.len() > 10 {
+                        let len = timeframe_data.z_score.len();
+                        timeframe_data.z_score = timeframe_data.z_score.split_off(len - 10);
                     }
                 }
             }
@@ -336,13 +609,13 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
         for syn_pair in synthetic_pairs.iter_mut() {
             for (_, timeframes) in syn_pair.synthetic_data.iter_mut() {
                 for timeframe_data in timeframes.iter_mut() {
-                    if timeframe_data.candles_h4.len() > 10 {
-                        let len = timeframe_data.candles_h4.len();
-                        timeframe_data.candles_h4 = timeframe_data.candles_h4.split_off(len - 10);
+                    if timeframe_data.candles.len() > 10 {
+                        let len = timeframe_data.candles.len();
+                        timeframe_data.candles = timeframe_data.candles.split_off(len - 10);
                     }
-                    if timeframe_data.z_score_h4.len() > 10 {
-                        let len = timeframe_data.z_score_h4.len();
-                        timeframe_data.z_score_h4 = timeframe_data.z_score_h4.split_off(len - 10);
+                    if timeframe_data.z_score.len() > 10 {
+                        let len = timeframe_data.z_score.len();
+                        timeframe_data.z_score = timeframe_data.z_score.split_off(len - 10);
                     }
                 }
             }
@@ -351,16 +624,68 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
         info!("Дані успішно обрізано до останніх 10 елементів");
         Ok(())
     }
+    fn trim_baskets_to_last_10_elements(&self, baskets: &mut Vec<SyntheticBasket>) {
+        for basket in baskets.iter_mut() {
+            for (_, timeframes) in basket.synthetic_data.iter_mut() {
+                for timeframe_data in timeframes.iter_mut() {
+                    if timeframe_data.candles.len() > 10 {
+                        let len = timeframe_data.candles.len();
+                        timeframe_data.candles = timeframe_data.candles.split_off(len - 10);
+                    }
+                    if timeframe_data.z_score.len() > 10 {
+                        let len = timeframe_data.z_score.len();
+                        timeframe_data.z_score = timeframe_data.z_score.split_off(len - 10);
+                    }
+                }
+            }
+        }
+    }
+
+    // Requested pairs still without candles, or without intraday candles with `minutes`
+    fn missing_requested(pairs:&[TradingPair], config_id:ConfigId, minutes:bool)->Result<Vec<String>>{
+        let mut missing=Vec::new();
+        for pair in pairs.iter().filter(|pair| pair.is_request_pair.is_some()) {
+            let pair_data = pair.pair_data_map.get(&config_id).ok_or(anyhow!("error get pair req"))?;
+            if (minutes && pair_data[0].candles_minutes.is_empty()) || (!minutes && pair_data[0].candles.is_empty()) {
+                missing.push(pair.name.clone());
+            }
+        }
+        Ok(missing)
+    }
+    // A failing symbol no longer fails the load, so the requested pairs are reloaded a few times only: a symbol
+    // rejected for good or missing from the archives is given up and dropped by the analysis like any empty pair
+    async fn reload_missing_requested(&self, pairs:&mut Vec<TradingPair>, config_id:ConfigId, until:NaiveDateTime, minutes:bool)->Result<()>{
+        for attempt in 1..=MISSING_CANDLES_ATTEMPTS {
+            let missing=Self::missing_requested(pairs, config_id, minutes)?;
+            if missing.is_empty() {
+                return Ok(());
+            }
+            info!("\n We dont have pairs: {:?}, attempt {} \n", missing, attempt);
+            self.exchange.get_candles_for_pairs(pairs, &self.config, until, minutes).await?;
+        }
+        for name in Self::missing_requested(pairs, config_id, minutes)? {
+            error!("requested pair {} has no {} after {} attempts, it is dropped from the day", name, if minutes { "intraday candles" } else { "candles" }, MISSING_CANDLES_ATTEMPTS);
+        }
+        Ok(())
+    }
 
     pub async fn run_stationarity(&mut self)->Result<()> {//%
         info!("run stationarity");
-        self.storage.clear_analysis_data().await?;
+        self.live_analysis=None;
+        self.storage.clear_analysis_data(self.run_id).await?;
         self.current_day+=1;
 
         self.config.update_configuration(&self.storage).await?;
         let last_configuration_id = self.config.get_last_config_id()?;
         let last_configuration = self.config.get(last_configuration_id)?;
-        let log_id=self.storage.get_logs_id_by_date(self.last_date_bypass).await?;
+        // Live every close is a new analysis, replay resumes a day left unfinished
+        let log_id=match &self.mode {
+            CollectorMode::Replay => self.storage.get_logs_id_by_date(self.run_id, self.last_date_bypass).await?,
+            CollectorMode::Live { interval } => {
+                self.last_date_bypass=Self::live_close(interval);
+                -1
+            }
+        };
         info!("LOG ID: {}",log_id);
 
         let mut pairs:Vec<TradingPair>=Vec::new();
@@ -374,32 +699,16 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
             info!("Generate pair");
         }
         self.add_requested_regular_pairs(&mut pairs, last_configuration_id).await?;
-        let load_time=self.get_time_now();
+        let load_time=self.candles_until();
         self.exchange.get_candles_for_pairs(&mut pairs, &self.config, load_time,false).await?;
         if log_id!=-1{
-            let mut is_all=false;
-            while is_all==false {
-                is_all=true;
-                for i in 0..pairs.len() {
-                    if pairs[i].is_request_pair.is_some() {
-                        let pair_data = pairs[i].pair_data_map.get(&last_configuration_id).ok_or(anyhow!("error get pair req"))?;
-                        if pair_data[0].candles_h4.is_empty() {
-                            is_all=false;
-                            info!("\n We dont have pair: {} \n",pairs[i].name);
-                            self.exchange.get_candles_for_pairs(&mut pairs, &self.config, load_time,false).await?;
-
-                            break;
-                        }
-                    }
-                }
-
-            }
+            self.reload_missing_requested(&mut pairs, last_configuration_id, load_time, false).await?;
         }
         let config_id = self.config.get_last_config_id()?;
         pairs.retain(|pair| {
             if let Some(timeframes_data) = pair.pair_data_map.get(&config_id) {
                 let has_empty_candles = timeframes_data.iter().any(|data|
-                    data.candles_h4.is_empty()
+                    data.candles.is_empty()
                 );
 
                 if has_empty_candles {
@@ -414,34 +723,52 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
                 false
             }
         });
+        let quality_reports = check_candle_quality(&mut pairs, config_id, &last_configuration);
+        if !quality_reports.is_empty() {
+            info!("QUALITY: {} flagged timeframes, action {:?}", quality_reports.len(), last_configuration.quality_action);
+            self.storage.save_candle_quality_reports(self.logs_id, &quality_reports).await?;
+        }
 
         let mut synthetic_pairs:Vec<SyntheticPairFullData>=Vec::new();
         if log_id!=-1{
             synthetic_pairs=self.storage.get_synthetic_pairs_by_log_id(log_id,&last_configuration).await?;
 
         }else{
-            synthetic_pairs = generate_synthetic_pair(&pairs, last_configuration.id)?;
+            synthetic_pairs = generate_synthetic_pair(&pairs, &last_configuration)?;
         }
         self.add_requested_synthetic_pairs(&mut synthetic_pairs, last_configuration_id).await?;
+        let mut baskets:Vec<SyntheticBasket>=Vec::new();
+        if log_id!=-1{
+            baskets=self.storage.get_baskets_by_log_id(log_id,&last_configuration).await?;
+        }else{
+            baskets=generate_synthetic_baskets(&pairs, &last_configuration)?;
+        }
         {
             let analyzer = self.asset_analyzer.clone();
             let config_id = last_configuration_id;
             let time = self.get_time_now();
 
+            let strategy = last_configuration.clone();
+
             if log_id!=-1 {
-                let (p, syn_p) = tokio::task::spawn_blocking(move || {
-                    let result = analyzer.calculate_asset_h4(config_id, &mut pairs, &mut synthetic_pairs, time)?;
-                    Ok::<(Vec<TradingPair>, Vec<SyntheticPairFullData>), anyhow::Error>((pairs, synthetic_pairs))
+                let (p, syn_p, b) = tokio::task::spawn_blocking(move || {
+                    let result = analyzer.calculate_asset_h4(config_id, &mut pairs, &mut synthetic_pairs, time, &strategy)?;
+                    analyzer.calculate_baskets_h4(config_id, &pairs, &mut baskets, &strategy)?;
+                    Ok::<(Vec<TradingPair>, Vec<SyntheticPairFullData>, Vec<SyntheticBasket>), anyhow::Error>((pairs, synthetic_pairs, baskets))
                 }).await??;
                 pairs=p;
                 synthetic_pairs=syn_p;
+                baskets=b;
             }else{
-                let (p, syn_p) = tokio::task::spawn_blocking(move || {
-                        let result = analyzer.calculate_asset_with_dickyfuller(config_id, &mut pairs, &mut synthetic_pairs,last_configuration.percent)?;
-                        Ok::<(Vec<TradingPair>, Vec<SyntheticPairFullData>), anyhow::Error>((pairs, synthetic_pairs))
+                let stationarity_test = stationarity_test(&last_configuration.stationarity_backend)?;
+                let (p, syn_p, b) = tokio::task::spawn_blocking(move || {
+                        let result = analyzer.calculate_asset_with_dickyfuller(config_id, &mut pairs, &mut synthetic_pairs,stationarity_test.as_ref(),&strategy)?;
+                        analyzer.calculate_baskets_with_johansen(config_id, &pairs, &mut baskets, stationarity_test.as_ref(), &strategy)?;
+                        Ok::<(Vec<TradingPair>, Vec<SyntheticPairFullData>, Vec<SyntheticBasket>), anyhow::Error>((pairs, synthetic_pairs, baskets))
                     }).await??;
                 pairs=p;
                 synthetic_pairs=syn_p;
+                baskets=b;
 
             }
 
@@ -449,41 +776,37 @@ impl<D: DatabaseInterface,C:ExchangeInterface> BinanceCollector<D,C>{
         pairs.shrink_to_fit();
         synthetic_pairs.shrink_to_fit();
         self.exchange.get_candles_for_pairs(&mut pairs, &self.config,load_time,true).await?;
-        let mut is_all=false;
-        while is_all==false {
-            is_all=true;
-            for i in 0..pairs.len() {
-                if pairs[i].is_request_pair.is_some() {
-                    let pair_data = pairs[i].pair_data_map.get(&config_id).ok_or(anyhow!("error get pair req"))?;
-                    if pair_data[0].candles_minutes.is_empty() {
-                        is_all=false;
-                        self.exchange.get_candles_for_pairs(&mut pairs, &self.config, self.get_time_now(),true).await?;
-                        break;
-                    }
-                }
-            }
-
-        }
+        self.reload_missing_requested(&mut pairs, config_id, self.candles_until(), true).await?;
         {
             let analyzer = self.asset_analyzer.clone();
             let config_id = last_configuration_id;
             let time = self.get_time_now();
+            let strategy = last_configuration.clone();
 
-            let (p, syn_p) = tokio::task::spawn_blocking(move || {
-                let result = analyzer.calculate_asset_minutes(config_id, &mut pairs, &mut synthetic_pairs, time)?;
-                Ok::<(Vec<TradingPair>, Vec<SyntheticPairFullData>), anyhow::Error>((pairs, synthetic_pairs))
+            let (p, syn_p, b) = tokio::task::spawn_blocking(move || {
+                analyzer.calculate_baskets_minutes(config_id, &pairs, &mut baskets, &strategy)?;
+                let result = analyzer.calculate_asset_minutes(config_id, &mut pairs, &mut synthetic_pairs, time, &strategy)?;
+                Ok::<(Vec<TradingPair>, Vec<SyntheticPairFullData>, Vec<SyntheticBasket>), anyhow::Error>((pairs, synthetic_pairs, baskets))
             }).await??;
             pairs=p;
             synthetic_pairs=syn_p;
+            baskets=b;
         }
 
         let pairs_len = pairs.len();
         let syn_len = synthetic_pairs.len();
+        let baskets_len = baskets.len();
+        if let CollectorMode::Live { .. } = self.mode {
+            self.live_analysis=Some(LiveAnalysis{config_id, pairs:pairs.clone(), synthetic_pairs:synthetic_pairs.clone(), baskets:baskets.clone()});
+        }
         self.trim_data_to_last_10_elements(&mut pairs, &mut synthetic_pairs)?;
-        self.update_pair(&mut pairs, &mut synthetic_pairs).await?;
-        info!("PAIRS: {} SYNTHETIC PAIR:{}", pairs_len, syn_len);
+        self.trim_baskets_to_last_10_elements(&mut baskets);
+        self.update_pair(&mut pairs, &mut synthetic_pairs, &mut baskets).await?;
+        info!("PAIRS: {} SYNTHETIC PAIR:{} BASKETS:{}", pairs_len, syn_len, baskets_len);
         self.end_to_stationarity(log_id).await?;
-        self.last_date_bypass+=chrono::Duration::days(1);
+        if self.mode == CollectorMode::Replay {
+            self.last_date_bypass+=chrono::Duration::days(self.replay_window.step_days as i64);
+        }
         Ok(())
     }
 }
@@ -508,20 +831,22 @@ mod tests {
         let mut mock_db = MockDatabaseInterface::new();
         mock_db.expect_get_active_strategies().returning(|| {
             let mut map = HashMap::new();
-            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0 });
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
             Ok((map, 1))
         });
-        mock_db.expect_get_start_info().returning(|| Ok(StartInfo {
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
             last_logs_id: 1,
             last_configuration_id: 1,
             base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
         }));
 
 
         let mock_exchange = MockExchangeInterface::new();
-        let mut collector = BinanceCollector::new(mock_db, mock_exchange).await?;
+        let mut collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
 
         let mut pair = TradingPair {
             id: 1,
@@ -529,16 +854,16 @@ mod tests {
             pair_data_map: HashMap::new(),
             is_request_pair: Some(true),
         };
-        pair.init_pair_data(1);
+        pair.init_pair_data(1, &[TimeInterval::h4]);
         let h4_data = pair.get_timeframe_data_mut(1, &TimeInterval::h4).unwrap();
         h4_data.stationarity = 95.5;
-        h4_data.candles_h4 = vec![
-            Candle { price: 100.0, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap() },
-            Candle { price: 101.0, close_time: NaiveDateTime::from_timestamp_opt(1627863600, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627863600, 0).unwrap() },
+        h4_data.candles = vec![
+            Candle { price: 100.0, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), ..Candle::default() },
+            Candle { price: 101.0, close_time: NaiveDateTime::from_timestamp_opt(1627863600, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627863600, 0).unwrap(), ..Candle::default() },
         ];
-        h4_data.z_score_h4 = vec![1.0, 1.1];
+        h4_data.z_score = vec![1.0, 1.1];
         h4_data.candles_minutes = vec![
-            Candle { price: 100.5, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap() },
+            Candle { price: 100.5, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), ..Candle::default() },
         ];
         h4_data.z_score_minutes = vec![0.5];
 
@@ -553,23 +878,73 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_serialize_trading_pair_with_ohlcv() -> Result<()> {
+        let mut mock_db = MockDatabaseInterface::new();
+        mock_db.expect_get_active_strategies().returning(|| {
+            let mut map = HashMap::new();
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, intervals: vec![TimeInterval::h4, TimeInterval::d1], export_ohlcv: true, ..Default::default() });
+            Ok((map, 1))
+        });
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
+            last_logs_id: 1,
+            last_configuration_id: 1,
+            base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
+        }));
+
+        let mock_exchange = MockExchangeInterface::new();
+        let collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
+
+        let mut pair = TradingPair::new();
+        pair.name = "BTCUSDT".to_string();
+        pair.init_pair_data(1, &[TimeInterval::h4, TimeInterval::d1]);
+        let open_time = NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(4, 0, 0).unwrap();
+        let candle = Candle { price: 101.0, open: 100.0, high: 102.5, low: 99.5, volume: 12.25, quote_volume: 1236.5, trades: 42,
+            open_time, close_time: open_time + chrono::Duration::hours(4) - chrono::Duration::milliseconds(1) };
+        for timeframe in pair.pair_data_map.get_mut(&1).unwrap() {
+            timeframe.candles = vec![candle.clone()];
+        }
+
+        let json_value: Value = serde_json::from_str(&collector.serialize_trading_pair(&pair)?)?;
+
+        for exported in [&json_value["candles"][0], &json_value["timeframes"][1]["candles"][0]] {
+            assert_eq!(exported["price"], 101.0);
+            assert_eq!(exported["open"], 100.0);
+            assert_eq!(exported["high"], 102.5);
+            assert_eq!(exported["low"], 99.5);
+            assert_eq!(exported["volume"], 12.25);
+            assert_eq!(exported["quote_volume"], 1236.5);
+            assert_eq!(exported["trades"], 42);
+            assert_eq!(exported["open_time"], open_time.to_string());
+        }
+        assert!(BinanceCollector::<MockDatabaseInterface, MockExchangeInterface>::serialize_candle(&candle, false).get("volume").is_none());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_serialize_synthetic_pair() -> Result<()> {
         let mut mock_db = MockDatabaseInterface::new();
         mock_db.expect_get_active_strategies().returning(|| {
             let mut map = HashMap::new();
-            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0 });
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
             Ok((map, 1))
         });
-        mock_db.expect_get_start_info().returning(|| Ok(StartInfo {
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
             last_logs_id: 1,
             last_configuration_id: 1,
             base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
         }));
         let mock_exchange = MockExchangeInterface::new();
-        let mut collector = BinanceCollector::new(mock_db, mock_exchange).await?;
+        let mut collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
 
         let mut syn_pair = SyntheticPairFullData {
             id: 1,
@@ -579,15 +954,22 @@ mod tests {
             synthetic_data: HashMap::new(),
             is_request_pair: Some(true),
         };
-        syn_pair.init_pair_data(1);
+        syn_pair.init_pair_data(1, &[TimeInterval::h4]);
         let h4_data = syn_pair.get_timeframe_data_mut(1, &TimeInterval::h4).unwrap();
         h4_data.stationarity = 96.0;
-        h4_data.candles_h4 = vec![
-            Candle { price: 1.5, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap() },
+        h4_data.kpss_p_value = 0.5;
+        h4_data.pp_p_value = 0.25;
+        h4_data.hedge_ratio = 1.5;
+        h4_data.hedge_intercept = -0.125;
+        h4_data.spread_kind = SpreadKind::LogSpread;
+        h4_data.half_life = 12.5;
+        h4_data.hurst = 0.375;
+        h4_data.candles = vec![
+            Candle { price: 1.5, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), ..Candle::default() },
         ];
-        h4_data.z_score_h4 = vec![0.8];
+        h4_data.z_score = vec![0.8];
         h4_data.candles_minutes = vec![
-            Candle { price: 1.6, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap() },
+            Candle { price: 1.6, close_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), open_time: NaiveDateTime::from_timestamp_opt(1627849200, 0).unwrap(), ..Candle::default() },
         ];
         h4_data.z_score_minutes = vec![0.9];
 
@@ -597,30 +979,80 @@ mod tests {
         assert_eq!(json_value["id"], 1);
         assert_eq!(json_value["name"], "BTCUSDT/ETHUSDT");
         assert_eq!(json_value["stationarity"], 96.0);
+        assert_eq!(json_value["kpss_p_value"], 0.5);
+        assert_eq!(json_value["pp_p_value"], 0.25);
+        assert_eq!(json_value["hedge_ratio"], 1.5);
+        assert_eq!(json_value["hedge_intercept"], -0.125);
+        assert_eq!(json_value["spread_kind"], "log_spread");
+        assert_eq!(json_value["half_life"], 12.5);
+        assert_eq!(json_value["hurst"], 0.375);
         assert_eq!(json_value["first_pair"]["name"], "BTCUSDT");
         assert_eq!(json_value["second_pair"]["name"], "ETHUSDT");
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_serialize_synthetic_basket() -> Result<()> {
+        let mut mock_db = MockDatabaseInterface::new();
+        mock_db.expect_get_active_strategies().returning(|| {
+            let mut map = HashMap::new();
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
+            Ok((map, 1))
+        });
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
+            last_logs_id: 1,
+            last_configuration_id: 1,
+            base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
+        }));
+        let mock_exchange = MockExchangeInterface::new();
+        let collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
+
+        let mut basket = SyntheticBasket::new(vec![
+            BaseAsset { id: 1, name: "BTCUSDT".to_string() },
+            BaseAsset { id: 2, name: "ETHUSDT".to_string() },
+            BaseAsset { id: 3, name: "SOLUSDT".to_string() },
+        ]);
+        basket.id = 7;
+        basket.weights = vec![1.0, -0.5, -0.25];
+        basket.init_pair_data(1, &[TimeInterval::h4]);
+        basket.get_timeframe_data_mut(1, &TimeInterval::h4).unwrap().stationarity = 97.0;
+
+        let json_value: Value = serde_json::from_str(&collector.serialize_synthetic_basket(&basket)?)?;
+
+        assert_eq!(json_value["name"], "BTCUSDT+ETHUSDT+SOLUSDT");
+        assert_eq!(json_value["legs"][1]["name"], "ETHUSDT");
+        assert_eq!(json_value["legs"][2]["weight"], -0.25);
+        assert_eq!(json_value["stationarity"], 97.0);
+        assert_eq!(json_value["configuration_id"], 1);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_trim_data_to_last_10_elements() -> Result<()> {
         let mut mock_db = MockDatabaseInterface::new();
         mock_db.expect_get_active_strategies().returning(|| {
             let mut map = HashMap::new();
-            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0 });
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
             Ok((map, 1))
         });
 
-        mock_db.expect_get_start_info().returning(|| Ok(StartInfo {
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
             last_logs_id: 1,
             last_configuration_id: 1,
             base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
         }));
         let mock_exchange = MockExchangeInterface::new();
-        let mut collector = BinanceCollector::new(mock_db, mock_exchange).await?;
+        let mut collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
 
         let mut pairs = vec![TradingPair {
             id: 1,
@@ -628,10 +1060,10 @@ mod tests {
             pair_data_map: HashMap::new(),
             is_request_pair: None,
         }];
-        pairs[0].init_pair_data(1);
+        pairs[0].init_pair_data(1, &[TimeInterval::h4]);
         let h4_data = pairs[0].get_timeframe_data_mut(1, &TimeInterval::h4).unwrap();
-        h4_data.candles_h4 = (0..15).map(|i| Candle { price: i as f32, close_time: NaiveDateTime::default(), open_time: NaiveDateTime::default() }).collect();
-        h4_data.z_score_h4 = (0..15).map(|i| i as f32).collect();
+        h4_data.candles = (0..15).map(|i| Candle { price: i as f64, ..Candle::default() }).collect();
+        h4_data.z_score = (0..15).map(|i| i as f32).collect();
 
         let mut syn_pairs = vec![SyntheticPairFullData {
             id: 1,
@@ -641,20 +1073,20 @@ mod tests {
             synthetic_data: HashMap::new(),
             is_request_pair: None,
         }];
-        syn_pairs[0].init_pair_data(1);
+        syn_pairs[0].init_pair_data(1, &[TimeInterval::h4]);
         let syn_h4_data = syn_pairs[0].get_timeframe_data_mut(1, &TimeInterval::h4).unwrap();
-        syn_h4_data.candles_h4 = (0..20).map(|i| Candle { price: i as f32, close_time: NaiveDateTime::default(), open_time: NaiveDateTime::default() }).collect();
-        syn_h4_data.z_score_h4 = (0..20).map(|i| i as f32).collect();
+        syn_h4_data.candles = (0..20).map(|i| Candle { price: i as f64, ..Candle::default() }).collect();
+        syn_h4_data.z_score = (0..20).map(|i| i as f32).collect();
 
         collector.trim_data_to_last_10_elements(&mut pairs, &mut syn_pairs)?;
 
-        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles_h4.len(), 10);
-        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().z_score_h4.len(), 10);
-        assert_eq!(syn_pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles_h4.len(), 10);
-        assert_eq!(syn_pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().z_score_h4.len(), 10);
+        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles.len(), 10);
+        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().z_score.len(), 10);
+        assert_eq!(syn_pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles.len(), 10);
+        assert_eq!(syn_pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().z_score.len(), 10);
 
-        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles_h4[0].price, 5.0);
-        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles_h4[9].price, 14.0);
+        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles[0].price, 5.0);
+        assert_eq!(pairs[0].get_timeframe_data(1, &TimeInterval::h4).unwrap().candles[9].price, 14.0);
 
         Ok(())
     }
@@ -670,18 +1102,20 @@ mod tests {
         });
         mock_db.expect_get_active_strategies().returning(|| {
             let mut map = HashMap::new();
-            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0 });
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
             Ok((map, 1))
         });
-        mock_db.expect_get_start_info().returning(|| Ok(StartInfo {
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
             last_logs_id: 1,
             last_configuration_id: 1,
             base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
         }));
         let mock_exchange = MockExchangeInterface::new();
-        let mut collector = BinanceCollector::new(mock_db, mock_exchange).await?;
+        let mut collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
 
         let mut pairs = vec![TradingPair {
             id: 1,
@@ -689,7 +1123,7 @@ mod tests {
             pair_data_map: HashMap::new(),
             is_request_pair: None,
         }];
-        pairs[0].init_pair_data(1);
+        pairs[0].init_pair_data(1, &[TimeInterval::h4]);
 
         collector.add_requested_regular_pairs(&mut pairs, 1).await?;
 
@@ -711,18 +1145,20 @@ mod tests {
         });
         mock_db.expect_get_active_strategies().returning(|| {
             let mut map = HashMap::new();
-            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0 });
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
             Ok((map, 1))
         });
-        mock_db.expect_get_start_info().returning(|| Ok(StartInfo {
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
             last_logs_id: 1,
             last_configuration_id: 1,
             base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
         }));
         let mock_exchange = MockExchangeInterface::new();
-        let mut collector = BinanceCollector::new(mock_db, mock_exchange).await?;
+        let mut collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
 
         let mut syn_pairs = vec![SyntheticPairFullData {
             id: 2,
@@ -732,7 +1168,7 @@ mod tests {
             synthetic_data: HashMap::new(),
             is_request_pair: None,
         }];
-        syn_pairs[0].init_pair_data(1);
+        syn_pairs[0].init_pair_data(1, &[TimeInterval::h4]);
 
         collector.add_requested_synthetic_pairs(&mut syn_pairs, 1).await?;
 
@@ -751,18 +1187,20 @@ mod tests {
         let last_data_bypass=NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
         mock_db.expect_get_active_strategies().returning(|| {
             let mut map = HashMap::new();
-            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0 });
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
             Ok((map, 1))
         });
-        mock_db.expect_get_start_info().returning(|| Ok(StartInfo {
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
             last_logs_id: 1,
             last_configuration_id: 1,
             base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
              last_date_bypass:NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
         }));
         let mock_exchange = MockExchangeInterface::new();
-        let collector = BinanceCollector::new(mock_db, mock_exchange).await?;
+        let collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
 
         let time_now = collector.get_time_now();
         assert_eq!(time_now, last_data_bypass);
@@ -775,29 +1213,240 @@ mod tests {
         let mut mock_db = MockDatabaseInterface::new();
         mock_db.expect_get_active_strategies().returning(|| {
             let mut map = HashMap::new();
-            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0 });
+            map.insert(1, TradingStrategy { id: 1, h1: 500, h4: 1000, percent: 90.0, ..Default::default() });
             Ok((map, 1))
         });
-        mock_db.expect_get_start_info().returning(|| Ok(StartInfo {
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
             last_logs_id: 1,
             last_configuration_id: 1,
             base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
             current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
         }));
-        mock_db.expect_check_for_new_date_going().returning(|| Ok(true));
+        mock_db.expect_check_for_new_date_going().returning(|_| Ok(true));
         let mock_exchange = MockExchangeInterface::new();
-        let mut collector = BinanceCollector::new(mock_db, mock_exchange).await?;
-        collector.current_day = 269; // Менше MAX_DAYS
+        let mut collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
+        collector.current_day = collector.replay_window.days() - 1; // Менше днів вікна реплею
 
         let is_time = collector.is_stationarity_time().await?;
         assert!(is_time);
+        assert!(!collector.is_finished());
 
-        collector.current_day = 270; // Рівно MAX_DAYS
+        collector.current_day = collector.replay_window.days(); // Рівно днів вікна реплею
         let is_time_max = collector.is_stationarity_time().await?;
         assert!(!is_time_max);
+        assert!(collector.is_finished());
 
         Ok(())
     }
 
-   }
\ No newline at end of file
+    #[tokio::test]
+    async fn test_live_mode_follows_the_clock() -> Result<()> {
+        let mut mock_db = MockDatabaseInterface::new();
+        mock_db.expect_get_active_strategies().returning(|| {
+            let mut map = HashMap::new();
+            map.insert(1, TradingStrategy { id: 1, h4: 1000, ..Default::default() });
+            Ok((map, 1))
+        });
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
+            last_logs_id: 1,
+            last_configuration_id: 1,
+            base_date: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            last_date_bypass: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
+        }));
+        // Live never waits for date_going
+        mock_db.expect_check_for_new_date_going().never();
+        let collector = BinanceCollector::new(mock_db, MockExchangeInterface::new(), 1).await?;
+        assert_eq!(collector.candles_until(), NaiveDate::from_ymd_opt(2024, 9, 2).unwrap().and_hms_opt(0, 0, 0).unwrap());
+
+        let mut collector = collector.with_mode(CollectorMode::Live { interval: TimeInterval::h4 });
+        collector.current_day = DEFAULT_REPLAY_DAYS;
+        assert!(!collector.is_finished());
+        // The stored day is long past, the latest H4 close is due at once
+        assert!(collector.is_stationarity_time().await?);
+        collector.last_date_bypass = BinanceCollector::<MockDatabaseInterface, MockExchangeInterface>::live_close(&TimeInterval::h4);
+        assert!(!collector.is_stationarity_time().await?);
+        assert_eq!(collector.candles_until(), collector.last_date_bypass);
+        assert_eq!(collector.last_date_bypass.hour() % 4, 0);
+        assert!(Utc::now().naive_utc() - collector.last_date_bypass < Duration::hours(4) + Duration::seconds(LIVE_SETTLE_SECONDS));
+        Ok(())
+    }
+
+   
+    // A collector with three days done of a replay of January 1 to 10, 2025, expecting `saves` stored windows
+    async fn replay_collector(saves: usize) -> Result<BinanceCollector<MockDatabaseInterface, MockExchangeInterface>> {
+        let stored = ReplayWindow::new(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(), NaiveDate::from_ymd_opt(2025, 1, 10).unwrap(), 1)?;
+        let mut mock_db = MockDatabaseInterface::new();
+        mock_db.expect_get_active_strategies().returning(|| {
+            let mut map = HashMap::new();
+            map.insert(1, TradingStrategy { id: 1, h4: 1000, ..Default::default() });
+            Ok((map, 1))
+        });
+        mock_db.expect_get_start_info().returning(move |_| Ok(StartInfo {
+            last_logs_id: 1,
+            last_configuration_id: 1,
+            base_date: stored.day(0),
+            last_date_bypass: stored.day(3),
+            current_day: 3,
+            replay_window: stored.clone(),
+            strategy: None,
+        }));
+        mock_db.expect_check_for_new_date_going().returning(|_| Ok(true));
+        mock_db.expect_save_replay_window().times(saves).returning(|_, _| Ok(()));
+        BinanceCollector::new(mock_db, MockExchangeInterface::new(), 1).await
+    }
+
+    #[tokio::test]
+    async fn test_missing_requested_pairs_are_given_up() -> Result<()> {
+        let mut mock_db = MockDatabaseInterface::new();
+        mock_db.expect_get_active_strategies().returning(|| {
+            let mut map = HashMap::new();
+            map.insert(1, TradingStrategy { id: 1, h4: 1000, ..Default::default() });
+            Ok((map, 1))
+        });
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
+            last_logs_id: 1,
+            last_configuration_id: 1,
+            base_date: ReplayWindow::default().day(0),
+            last_date_bypass: ReplayWindow::default().day(0),
+            current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
+        }));
+        // BTCUSDT loads on the first reload, BADUSDT never does
+        let mut mock_exchange = MockExchangeInterface::new();
+        mock_exchange.expect_get_candles_for_pairs().times(MISSING_CANDLES_ATTEMPTS).returning(|pairs, _, _, _| {
+            for pair in pairs.iter_mut().filter(|pair| pair.name == "BTCUSDT") {
+                pair.pair_data_map.get_mut(&1).unwrap()[0].candles = vec![Candle::default()];
+            }
+            Ok(())
+        });
+        let collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?;
+        let mut pairs = ["BTCUSDT", "BADUSDT"].iter().map(|name| {
+            let mut pair = TradingPair::new();
+            pair.name = name.to_string();
+            pair.is_request_pair = Some(true);
+            pair.init_pair_data(1, &[TimeInterval::h4]);
+            pair
+        }).collect::<Vec<_>>();
+        collector.reload_missing_requested(&mut pairs, 1, collector.candles_until(), false).await?;
+        assert_eq!(BinanceCollector::<MockDatabaseInterface, MockExchangeInterface>::missing_requested(&pairs, 1, false)?, vec!["BADUSDT".to_string()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_scopes_storage_and_strategy() -> Result<()> {
+        // A run pinned to strategy 1 while 2 is the latest active one
+        let run_db = |strategy: ConfigId| {
+            let mut mock_db = MockDatabaseInterface::new();
+            mock_db.expect_get_active_strategies().returning(|| {
+                let strategies = [1, 2].map(|id| (id, TradingStrategy { id, h4: 1000, ..Default::default() }));
+                Ok((HashMap::from(strategies), 2))
+            });
+            mock_db.expect_get_start_info().with(eq(7)).returning(move |_| Ok(StartInfo {
+                last_logs_id: -1,
+                last_configuration_id: -1,
+                base_date: ReplayWindow::default().day(0),
+                last_date_bypass: ReplayWindow::default().day(0),
+                current_day: 0,
+                replay_window: ReplayWindow::default(),
+                strategy: Some(strategy),
+            }));
+            mock_db.expect_check_for_new_date_going().with(eq(7)).returning(|_| Ok(true));
+            mock_db
+        };
+        let mut collector = BinanceCollector::new(run_db(1), MockExchangeInterface::new(), 7).await?;
+        assert_eq!(collector.config.get_last_config_id()?, 1);
+        assert_eq!(collector.config.get_keys(), vec![1]);
+        assert!(collector.is_stationarity_time().await?);
+
+        // A run pinned to a strategy that is no longer active does not start
+        assert!(BinanceCollector::new(run_db(3), MockExchangeInterface::new(), 7).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_window() -> Result<()> {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+        // Resuming the stored window writes nothing
+        let collector = replay_collector(0).await?.with_replay_window(ReplayWindow::new(date(1), date(10), 1)?).await?;
+        // Three days are done, the fourth of ten is next
+        assert_eq!(collector.get_time_now(), date(4).and_hms_opt(0, 0, 0).unwrap());
+
+        let mut collector = replay_collector(1).await?.with_replay_window(ReplayWindow::new(date(1), date(20), 1)?).await?;
+        assert!(collector.is_stationarity_time().await?);
+        collector.current_day = 10;
+        assert!(collector.is_stationarity_time().await?);
+        collector.current_day = 20;
+        assert!(!collector.is_stationarity_time().await?);
+
+        // The days done are January 1 to 3, another start or step would mix them with the new ones
+        assert!(replay_collector(0).await?.with_replay_window(ReplayWindow::new(date(2), date(20), 1)?).await.is_err());
+        assert!(replay_collector(0).await?.with_replay_window(ReplayWindow::new(date(1), date(20), 2)?).await.is_err());
+        assert_eq!(CollectorMode::parse("live", "1h")?, CollectorMode::Live { interval: TimeInterval::h1 });
+        assert_eq!(CollectorMode::parse("", "4h")?, CollectorMode::Replay);
+        assert!(CollectorMode::parse("live", "7m").is_err());
+        assert!(CollectorMode::parse("paper", "4h").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_live_analysis_is_fed_from_the_stream() -> Result<()> {
+        use crate::test_util::{candle, september_1};
+        let mut mock_db = MockDatabaseInterface::new();
+        mock_db.expect_get_active_strategies().returning(|| {
+            let mut map = HashMap::new();
+            map.insert(1, TradingStrategy { id: 1, h4: 1000, z_score_period: 20, ..Default::default() });
+            Ok((map, 1))
+        });
+        mock_db.expect_get_start_info().returning(|_| Ok(StartInfo {
+            last_logs_id: 1,
+            last_configuration_id: 1,
+            base_date: september_1(),
+            last_date_bypass: september_1(),
+            current_day: 0,
+            replay_window: ReplayWindow::default(),
+            strategy: None,
+        }));
+        // 100 H4 bars and the first 30 minutes after them, the stream has 10 more minutes of BTCUSDT only
+        let after_bars = september_1() + Duration::hours(400);
+        let minute = move |k: i64| candle(after_bars + Duration::minutes(k), 1, 100.0 + k as f64);
+        let mut mock_exchange = MockExchangeInterface::new();
+        mock_exchange.expect_get_streamed_candles().returning(move |symbol, interval, after| {
+            assert_eq!(interval, &TimeInterval::m1);
+            Ok(if symbol == "BTCUSDT" { (0..40).map(minute).filter(|candle| candle.open_time > after).collect() } else { Vec::new() })
+        });
+        let mut collector = BinanceCollector::new(mock_db, mock_exchange, 1).await?.with_mode(CollectorMode::Live { interval: TimeInterval::h4 });
+        let pairs: Vec<TradingPair> = ["BTCUSDT", "ETHUSDT"].iter().map(|name| {
+            let mut pair = TradingPair::new();
+            pair.name = name.to_string();
+            pair.init_pair_data(1, &[TimeInterval::h4]);
+            let data = &mut pair.pair_data_map.get_mut(&1).unwrap()[0];
+            data.candles = (0..100).map(|i| candle(september_1() + Duration::hours(4 * i), 240, 100.0 + (i % 9) as f64)).collect();
+            data.candles_minutes = (0..30).map(minute).collect();
+            data.z_score_minutes = vec![0.0; 30];
+            pair
+        }).collect();
+        collector.live_analysis = Some(LiveAnalysis { config_id: 1, pairs, synthetic_pairs: Vec::new(), baskets: Vec::new() });
+
+        assert_eq!(collector.feed_live_minutes().await?, 10);
+        assert_eq!(collector.feed_live_minutes().await?, 0);
+        let analysis = collector.live_analysis.as_ref().unwrap();
+        let btc = &analysis.pairs[0].pair_data_map[&1][0];
+        assert_eq!((btc.candles_minutes.len(), btc.z_score_minutes.len()), (30, 30));
+        assert_eq!(btc.candles_minutes.last().unwrap().open_time, minute(39).open_time);
+        assert!(btc.z_score_minutes[20..].iter().all(|z_score| *z_score != 0.0));
+        assert_eq!(analysis.pairs[1].pair_data_map[&1][0].candles_minutes.last().unwrap().open_time, minute(29).open_time);
+
+        // A new strategy waits for its close
+        collector.live_analysis.as_mut().unwrap().config_id = 2;
+        assert_eq!(collector.feed_live_minutes().await?, 0);
+        assert!(collector.live_analysis.is_none());
+        Ok(())
+    }
+}
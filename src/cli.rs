@@ -0,0 +1,544 @@
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+use tracing::info;
+use crate::core::types::{ConfigId, GapPolicy, HedgeMode, QualityAction, ReplayWindow, RunId, SpreadKind, StationarityBackend, StationarityRule, TimeInterval, TradingStrategy, DEFAULT_INTRADAY_WINDOW_MINUTES, DEFAULT_MAX_JUMP_PERCENT, DEFAULT_Z_SCORE_PERIOD, INTRADAY_INTERVALS};
+use crate::exchange::stream::BINANCE_FUTURES_STREAM_URL;
+use crate::migrations::MIGRATIONS;
+use crate::storage::admin::RunSummary;
+use crate::storage::database::{DatabaseInterface, PostgresDataBase};
+use crate::storage::import::import_klines;
+
+// Command line of the collector. A flag with an environment variable falls back to it, so the .env setups
+// keep working and the flag overrides them for one run. Without a subcommand the collector runs like `run`.
+
+#[derive(Debug, Parser)]
+#[command(name = "binance_collector", version, about = "Stationarity collector of Binance futures pairs", args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// PostgreSQL connection string
+    #[arg(long, env = "DATABASE_URL", global = true, hide_env_values = true)]
+    pub database_url: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Runs the collector: replays history, or follows the clock with --mode live
+    Run(RunArgs),
+    /// Replays the days from --from up to and including --to, or resumes the stored window
+    Replay(ReplayArgs),
+    /// Applies the pending schema migrations
+    Migrate,
+    /// Prints the replay progress, the last analysis and the active strategies
+    Status(StatusArgs),
+    /// Deletes the replay progress and every analysis result of a run, the next start replays it over
+    Reset(ResetArgs),
+    /// Lists, creates, compares and deletes runs
+    Runs {
+        #[command(subcommand)]
+        command: RunsCommand,
+    },
+    /// Writes the stationarity results of an analysis as JSON
+    Export(ExportArgs),
+    /// Adds a strategy to the configuration table
+    AddStrategy(StrategyArgs),
+    /// Loads a directory of Binance Vision kline archives into the kline store
+    ImportKlines {
+        dir: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct RunArgs {
+    /// replay or live
+    #[arg(long, env = "COLLECTOR_MODE", default_value = "replay")]
+    pub mode: String,
+    /// Closes that drive the live mode
+    #[arg(long, env = "LIVE_INTERVAL", default_value = "4h")]
+    pub live_interval: String,
+    /// Kline stream of the live mode
+    #[arg(long, env = "BINANCE_STREAM_URL", default_value = BINANCE_FUTURES_STREAM_URL)]
+    pub stream_url: String,
+    #[command(flatten)]
+    pub collector: CollectorArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ReplayArgs {
+    #[command(flatten)]
+    pub window: WindowArgs,
+    #[command(flatten)]
+    pub collector: CollectorArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct WindowArgs {
+    /// First replayed day, YYYY-MM-DD. Without it the window stored with the run is resumed
+    #[arg(long, env = "REPLAY_FROM", requires = "end")]
+    pub from: Option<NaiveDate>,
+    /// Last replayed day, YYYY-MM-DD
+    #[arg(long, env = "REPLAY_TO", group = "end", requires = "from")]
+    pub to: Option<NaiveDate>,
+    /// Number of replayed days, instead of --to
+    #[arg(long, env = "REPLAY_DAYS", group = "end", requires = "from")]
+    pub days: Option<i32>,
+    /// Days between two replayed days, 1 by default
+    #[arg(long, env = "REPLAY_STEP_DAYS", requires = "from")]
+    pub step_days: Option<i32>,
+}
+impl WindowArgs {
+    // None resumes the stored window
+    pub fn window(&self) -> Result<Option<ReplayWindow>> {
+        let Some(from) = self.from else { return Ok(None) };
+        let step_days = self.step_days.unwrap_or(1);
+        match (self.to, self.days) {
+            (Some(to), _) => ReplayWindow::new(from, to, step_days).map(Some),
+            (None, Some(days)) => ReplayWindow::with_days(from, days, step_days).map(Some),
+            (None, None) => Err(anyhow!("--from needs --to or --days")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct CollectorArgs {
+    /// Run the collector works on, the latest one by default
+    #[arg(long, env = "RUN_ID")]
+    pub run: Option<RunId>,
+    /// Directory of Binance Vision kline archives replayed instead of the API
+    #[arg(long, env = "REPLAY_DIR")]
+    pub replay_dir: Option<PathBuf>,
+    /// Threads analysing synthetic pairs, 0 uses every core
+    #[arg(long, env = "ANALYZER_WORKERS")]
+    pub workers: Option<usize>,
+    /// Endpoint the analysed pairs are sent to
+    #[arg(long, env = "URL")]
+    pub export_url: Option<String>,
+    /// Binance API credentials, not needed with --replay-dir
+    #[arg(long, env = "API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+    #[arg(long, env = "SECRET_KEY", hide_env_values = true)]
+    pub secret_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct StatusArgs {
+    /// Run to report on, the latest one by default
+    #[arg(long)]
+    pub run: Option<RunId>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ResetArgs {
+    /// Confirms the deletion
+    #[arg(long)]
+    pub yes: bool,
+    /// Run to reset, the latest one by default
+    #[arg(long)]
+    pub run: Option<RunId>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ExportArgs {
+    /// Analysis to export, the last finished one of the run by default
+    #[arg(long, conflicts_with = "run")]
+    pub log_id: Option<i32>,
+    /// Run whose last finished analysis is exported, the latest run by default
+    #[arg(long)]
+    pub run: Option<RunId>,
+    /// File written instead of stdout
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RunsCommand {
+    /// Lists the runs with their window, progress and results
+    List,
+    /// Creates a run, `replay --run` or `run --run` then works on it
+    New(NewRunArgs),
+    /// Prints two runs side by side
+    Compare {
+        first: RunId,
+        second: RunId,
+    },
+    /// Deletes a run with its progress and results
+    Delete {
+        run: RunId,
+        /// Confirms the deletion
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct NewRunArgs {
+    /// Label shown by `runs list`
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Strategy the run analyses with, the latest active one by default
+    #[arg(long)]
+    pub strategy: Option<ConfigId>,
+    /// 270 days from 2024-09-01 without --from
+    #[command(flatten)]
+    pub window: WindowArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct StrategyArgs {
+    /// Bars fetched for every analysed interval without its own count in --interval-bars
+    #[arg(long)]
+    pub bars: i32,
+    /// Confidence in percent a pair needs to pass the stationarity rule
+    #[arg(long)]
+    pub percent: f32,
+    /// Analysed intervals, the first one is the primary
+    #[arg(long, value_delimiter = ',', value_parser = parse_interval, default_value = "4h")]
+    pub intervals: Vec<TimeInterval>,
+    /// Bars fetched per interval, in the order of --intervals
+    #[arg(long, value_delimiter = ',')]
+    pub interval_bars: Vec<i32>,
+    #[arg(long, value_enum, default_value_t)]
+    pub stationarity_backend: StationarityBackend,
+    #[arg(long, value_enum, default_value_t)]
+    pub stationarity_rule: StationarityRule,
+    #[arg(long, value_enum, default_value_t)]
+    pub hedge_mode: HedgeMode,
+    #[arg(long, value_enum, default_value_t)]
+    pub spread_kind: SpreadKind,
+    /// Legs per Johansen basket, below 3 disables them
+    #[arg(long, default_value_t = 0)]
+    pub max_basket_size: i32,
+    /// Symbols combined into baskets
+    #[arg(long, default_value_t = 20)]
+    pub basket_universe: i32,
+    /// Slowest mean reversion kept, in bars of the analysed interval
+    #[arg(long)]
+    pub max_half_life: Option<f32>,
+    #[arg(long)]
+    pub max_hurst: Option<f32>,
+    /// Rolling window of the z-score, in bars
+    #[arg(long, default_value_t = DEFAULT_Z_SCORE_PERIOD)]
+    pub z_score_period: i32,
+    /// Resolution of the intraday candles: 1m, 3m, 5m or 15m
+    #[arg(long, value_parser = parse_interval, default_value = "1m")]
+    pub intraday_interval: TimeInterval,
+    #[arg(long, default_value_t = DEFAULT_INTRADAY_WINDOW_MINUTES)]
+    pub intraday_window_minutes: i32,
+    #[arg(long, value_enum, default_value_t)]
+    pub gap_policy: GapPolicy,
+    #[arg(long)]
+    pub max_gap_percent: Option<f32>,
+    #[arg(long, value_enum, default_value_t)]
+    pub quality_action: QualityAction,
+    #[arg(long, default_value_t = DEFAULT_MAX_JUMP_PERCENT)]
+    pub max_jump_percent: f32,
+    /// Exports the candles of regular pairs with open/high/low, volumes and trades
+    #[arg(long)]
+    pub export_ohlcv: bool,
+    /// Adds the strategy without activating it
+    #[arg(long)]
+    pub inactive: bool,
+}
+impl StrategyArgs {
+    // Checked here as well as by the table constraints, so a mistake is reported before the insert
+    pub fn to_strategy(&self) -> Result<TradingStrategy> {
+        if self.bars <= 0 {
+            return Err(anyhow!("--bars must be positive"));
+        }
+        if !(self.percent > 0.0 && self.percent <= 100.0) {
+            return Err(anyhow!("--percent must be in (0, 100]"));
+        }
+        if self.z_score_period <= 1 {
+            return Err(anyhow!("--z-score-period must be above 1"));
+        }
+        if !INTRADAY_INTERVALS.contains(&self.intraday_interval) {
+            return Err(anyhow!("--intraday-interval must be one of 1m, 3m, 5m, 15m"));
+        }
+        if !self.interval_bars.is_empty() && self.interval_bars.len() != self.intervals.len() {
+            return Err(anyhow!("--interval-bars needs one count per interval of --intervals"));
+        }
+        if self.interval_bars.iter().any(|&bars| bars <= 0) {
+            return Err(anyhow!("--interval-bars must be positive"));
+        }
+        // A repeated interval keeps its first bar count
+        let mut intervals = Vec::new();
+        let mut interval_bars = Vec::new();
+        for (index, interval) in self.intervals.iter().enumerate() {
+            if !intervals.contains(interval) {
+                intervals.push(interval.clone());
+                interval_bars.extend(self.interval_bars.get(index));
+            }
+        }
+        let strategy = TradingStrategy {
+            h4: self.bars,
+            intervals,
+            interval_bars: (!interval_bars.is_empty()).then_some(interval_bars),
+            percent: self.percent,
+            stationarity_backend: self.stationarity_backend.clone(),
+            stationarity_rule: self.stationarity_rule,
+            hedge_mode: self.hedge_mode,
+            spread_kind: self.spread_kind,
+            max_basket_size: self.max_basket_size,
+            basket_universe: self.basket_universe,
+            max_half_life: self.max_half_life,
+            max_hurst: self.max_hurst,
+            z_score_period: self.z_score_period,
+            intraday_interval: self.intraday_interval.clone(),
+            intraday_window_minutes: self.intraday_window_minutes,
+            gap_policy: self.gap_policy,
+            max_gap_percent: self.max_gap_percent,
+            quality_action: self.quality_action,
+            max_jump_percent: self.max_jump_percent,
+            export_ohlcv: self.export_ohlcv,
+            ..TradingStrategy::new()
+        };
+        strategy.intraday_limit()?;
+        strategy.check_hedge().map_err(|_| anyhow!("--hedge-mode engle_granger needs --spread-kind log_spread or normalized_spread"))?;
+        Ok(strategy)
+    }
+}
+
+// Binance names such as 4h
+fn parse_interval(name: &str) -> Result<TimeInterval, String> {
+    TimeInterval::from_binance_name(name).ok_or_else(|| format!("unknown interval {}", name))
+}
+
+pub async fn migrate(db: &PostgresDataBase) -> Result<()> {
+    // PostgresDataBase::new has applied them already
+    println!("schema version {}", db.schema_version().await?);
+    Ok(())
+}
+
+pub async fn status(db: &PostgresDataBase, args: &StatusArgs) -> Result<()> {
+    let latest = MIGRATIONS.iter().map(|migration| migration.to).max().unwrap_or_default();
+    println!("schema version: {} of {}", db.schema_version().await?, latest);
+    let run = db.run_summary(db.resolve_run(args.run).await?).await?;
+    println!("run: {}", run_label(&run));
+    println!("replay window: {} to {} every {} days", run.window.start, run.window.end, run.window.step_days);
+    println!("days done: {} of {}", run.days_done, run.window.days());
+    match db.get_logs_id(run.id).await? {
+        (-1, _) => println!("last analysis: none"),
+        (log_id, configuration_id) => println!("last analysis: {} with strategy {}, day {}", log_id, configuration_id, db.get_last_analysis_time(run.id).await?.date()),
+    }
+    let (strategies, last_id) = db.get_active_strategies().await?;
+    let mut ids: Vec<_> = strategies.keys().copied().collect();
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    match ids.is_empty() {
+        true => println!("active strategies: none"),
+        false => println!("active strategies: {} (analysed with {})", ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", "), last_id),
+    }
+    Ok(())
+}
+
+pub async fn reset(db: &PostgresDataBase, args: &ResetArgs) -> Result<()> {
+    if !args.yes {
+        return Err(anyhow!("reset deletes the replay progress and every analysis result of the run, run it again with --yes"));
+    }
+    let run_id = db.resolve_run(args.run).await?;
+    println!("run {}: {} analyses deleted", run_id, db.reset_progress(run_id).await?);
+    Ok(())
+}
+
+pub async fn runs(db: &PostgresDataBase, command: &RunsCommand) -> Result<()> {
+    match command {
+        RunsCommand::List => {
+            for run in db.list_runs().await? {
+                println!("{}: {} to {} every {} days, {} of {} days done, {} analyses, {} results",
+                         run_label(&run), run.window.start, run.window.end, run.window.step_days, run.days_done, run.window.days(), run.analyses, run.results);
+            }
+        }
+        RunsCommand::New(args) => {
+            let window = args.window.window()?.unwrap_or_default();
+            let run_id = db.create_run(args.name.as_deref(), args.strategy, &window).await?;
+            println!("run {} created: {} to {} every {} days", run_id, window.start, window.end, window.step_days);
+        }
+        RunsCommand::Compare { first, second } => {
+            let runs = [db.run_summary(*first).await?, db.run_summary(*second).await?];
+            let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            type Column = fn(&RunSummary) -> String;
+            let rows: [(&str, Column); 9] = [
+                ("strategy", |run| run.strategy.map_or("latest active".to_string(), |id| id.to_string())),
+                ("window", |run| format!("{} to {}", run.window.start, run.window.end)),
+                ("step days", |run| run.window.step_days.to_string()),
+                ("days done", |run| format!("{} of {}", run.days_done, run.window.days())),
+                ("analyses", |run| run.analyses.to_string()),
+                ("results", |run| run.results.to_string()),
+                ("pairs", |run| run.pairs.to_string()),
+                ("mean stationarity", |run| run.mean_stationarity.map_or("-".to_string(), |value| format!("{:.4}", value))),
+                ("last analysis", |run| run.last_analysis.map_or("-".to_string(), |time| time.date().to_string())),
+            ];
+            println!("{:<20}{:<28}{:<28}", "run", runs[0].id, runs[1].id);
+            println!("{:<20}{:<28}{:<28}", "name", optional(runs[0].name.clone()), optional(runs[1].name.clone()));
+            for (label, value) in rows {
+                println!("{:<20}{:<28}{:<28}", label, value(&runs[0]), value(&runs[1]));
+            }
+            println!("{:<20}{}", "common pairs", db.common_pairs(*first, *second).await?);
+        }
+        RunsCommand::Delete { run, yes } => {
+            if !yes {
+                return Err(anyhow!("deleting run {} drops its progress and every analysis result, run it again with --yes", run));
+            }
+            println!("run {} deleted with {} analyses", run, db.delete_run(*run).await?);
+        }
+    }
+    Ok(())
+}
+
+// `3 "bull market", strategy 2`
+fn run_label(run: &RunSummary) -> String {
+    let name = run.name.as_ref().map(|name| format!(" {:?}", name)).unwrap_or_default();
+    let strategy = run.strategy.map_or("latest active strategy".to_string(), |id| format!("strategy {}", id));
+    format!("{}{}, {}", run.id, name, strategy)
+}
+
+pub async fn export(db: &PostgresDataBase, args: &ExportArgs) -> Result<()> {
+    let log_id = match args.log_id {
+        Some(log_id) => log_id,
+        None => match db.get_logs_id(db.resolve_run(args.run).await?).await? {
+            (-1, _) => return Err(anyhow!("no finished analysis to export")),
+            (log_id, _) => log_id,
+        },
+    };
+    let results: serde_json::Value = serde_json::from_str(&db.export_results(log_id).await?)?;
+    let content = serde_json::to_string_pretty(&results)?;
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, content)?;
+            println!("analysis {}: {} results written to {}", log_id, results.as_array().map_or(0, Vec::len), path.display());
+        }
+        None => println!("{}", content),
+    }
+    Ok(())
+}
+
+pub async fn add_strategy(db: &PostgresDataBase, args: &StrategyArgs) -> Result<()> {
+    let strategy = args.to_strategy()?;
+    let id = db.add_strategy(&strategy, !args.inactive).await?;
+    info!("strategy {} added: {:?}", id, strategy);
+    println!("strategy {} added{}", id, if args.inactive { ", not activated" } else { "" });
+    Ok(())
+}
+
+// Loads a directory of Binance Vision kline archives into the kline store, see storage/import.rs
+pub async fn import(db: &PostgresDataBase, dir: &Path) -> Result<()> {
+    let summary = import_klines(db, dir).await?;
+    println!("{} archives: {} imported ({} klines), {} already imported, {} unsupported, {} of another market, {} failed",
+             summary.archives, summary.imported, summary.klines, summary.already_imported, summary.unsupported, summary.other_market, summary.failed);
+    info!("import: {:?}", summary);
+    if summary.failed > 0 {
+        return Err(anyhow!("{} archives failed to import, run the command again to retry them", summary.failed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("binance_collector").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_subcommands_and_flags() -> Result<()> {
+        Cli::command().debug_assert();
+
+        // No subcommand runs the collector with the flags given at the top level
+        let cli = parse(&["--mode", "live", "--live-interval", "1h", "--workers", "3"])?;
+        assert!(cli.command.is_none());
+        assert_eq!((cli.run.mode.as_str(), cli.run.live_interval.as_str(), cli.run.collector.workers), ("live", "1h", Some(3)));
+
+        let cli = parse(&["replay", "--from", "2025-01-01", "--to", "2025-03-31", "--replay-dir", "./archives", "--database-url", "postgres://db"])?;
+        assert_eq!(cli.database_url.as_deref(), Some("postgres://db"));
+        let Some(Command::Replay(replay)) = cli.command else { panic!("expected replay") };
+        let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+        assert_eq!(replay.window.window()?, Some(ReplayWindow::new(date(1, 1), date(3, 31), 1)?));
+        assert_eq!(replay.collector.replay_dir, Some(PathBuf::from("./archives")));
+        let Some(Command::Replay(replay)) = parse(&["replay", "--api-key", "key", "--secret-key", "secret"])?.command else { panic!("expected replay") };
+        assert_eq!((replay.collector.api_key.as_deref(), replay.collector.secret_key.as_deref()), (Some("key"), Some("secret")));
+        assert!(parse(&["replay", "--from", "2025-01-01"]).is_err());
+        assert!(parse(&["replay", "--from", "01.01.2025", "--to", "2025-03-31"]).is_err());
+
+        // A day count and a step instead of the last day, or nothing to resume the stored window
+        let Some(Command::Replay(replay)) = parse(&["replay", "--from", "2025-01-01", "--days", "30", "--step-days", "7"])?.command else { panic!("expected replay") };
+        assert_eq!(replay.window.window()?, Some(ReplayWindow::with_days(date(1, 1), 30, 7)?));
+        let Some(Command::Replay(replay)) = parse(&["replay"])?.command else { panic!("expected replay") };
+        assert_eq!(replay.window.window()?, None);
+        assert!(parse(&["replay", "--from", "2025-01-01", "--to", "2025-03-31", "--days", "30"]).is_err());
+        assert!(parse(&["replay", "--days", "30"]).is_err());
+        assert!(parse(&["replay", "--step-days", "7"]).is_err());
+        let Some(Command::Replay(replay)) = parse(&["replay", "--from", "2025-01-01", "--days", "30", "--step-days", "0"])?.command else { panic!("expected replay") };
+        assert!(replay.window.window().is_err());
+
+        assert!(matches!(parse(&["reset"])?.command, Some(Command::Reset(ResetArgs { yes: false, run: None }))));
+        assert!(matches!(parse(&["export", "--log-id", "7"])?.command, Some(Command::Export(ExportArgs { log_id: Some(7), output: None, run: None }))));
+        assert!(matches!(parse(&["import-klines", "./archives"])?.command, Some(Command::ImportKlines { .. })));
+        let cli = parse(&["status", "--database-url", "postgres://db"])?;
+        assert!(matches!(cli.command, Some(Command::Status(StatusArgs { run: None }))));
+        assert_eq!(cli.database_url.as_deref(), Some("postgres://db"));
+        // The flags of the default run do not mix with a subcommand
+        assert!(parse(&["--mode", "live", "status"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_runs_subcommands() -> Result<()> {
+        assert!(matches!(parse(&["runs", "list"])?.command, Some(Command::Runs { command: RunsCommand::List })));
+        let Some(Command::Runs { command: RunsCommand::New(args) }) = parse(&["runs", "new", "--name", "bull", "--strategy", "2", "--from", "2021-01-01", "--days", "90"])?.command else {
+            panic!("expected runs new")
+        };
+        assert_eq!((args.name.as_deref(), args.strategy), (Some("bull"), Some(2)));
+        assert_eq!(args.window.window()?, Some(ReplayWindow::with_days(NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(), 90, 1)?));
+        let Some(Command::Runs { command: RunsCommand::New(args) }) = parse(&["runs", "new"])?.command else { panic!("expected runs new") };
+        assert_eq!(args.window.window()?.unwrap_or_default(), ReplayWindow::default());
+        assert!(matches!(parse(&["runs", "compare", "1", "2"])?.command, Some(Command::Runs { command: RunsCommand::Compare { first: 1, second: 2 } })));
+        assert!(matches!(parse(&["runs", "delete", "3"])?.command, Some(Command::Runs { command: RunsCommand::Delete { run: 3, yes: false } })));
+        assert!(parse(&["runs", "delete"]).is_err());
+
+        // The run of the collector and of the commands that read one
+        let Some(Command::Replay(replay)) = parse(&["replay", "--run", "3"])?.command else { panic!("expected replay") };
+        assert_eq!(replay.collector.run, Some(3));
+        assert_eq!(parse(&["--run", "4"])?.run.collector.run, Some(4));
+        assert!(matches!(parse(&["status", "--run", "2"])?.command, Some(Command::Status(StatusArgs { run: Some(2) }))));
+        assert!(matches!(parse(&["reset", "--run", "2", "--yes"])?.command, Some(Command::Reset(ResetArgs { yes: true, run: Some(2) }))));
+        assert!(parse(&["export", "--log-id", "7", "--run", "2"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_strategy_args() -> Result<()> {
+        let Some(Command::AddStrategy(args)) = parse(&["add-strategy", "--bars", "500", "--percent", "95", "--intervals", "4h,1h,4h", "--interval-bars", "500,2000,300",
+            "--stationarity-rule", "adf_and_kpss", "--spread-kind", "log_spread", "--max-hurst", "0.5", "--inactive"])?.command else {
+            panic!("expected add-strategy")
+        };
+        let strategy = args.to_strategy()?;
+        assert_eq!((strategy.h4, strategy.percent, strategy.intervals.clone()), (500, 95.0, vec![TimeInterval::h4, TimeInterval::h1]));
+        assert_eq!(strategy.interval_bars, Some(vec![500, 2000]));
+        assert_eq!((strategy.stationarity_rule, strategy.spread_kind, strategy.max_hurst), (StationarityRule::AdfAndKpss, SpreadKind::LogSpread, Some(0.5)));
+        assert_eq!((strategy.stationarity_backend, strategy.hedge_mode, strategy.gap_policy), (StationarityBackend::Native, HedgeMode::Ratio, GapPolicy::Drop));
+        assert_eq!((strategy.z_score_period, strategy.intraday_interval, strategy.basket_universe), (DEFAULT_Z_SCORE_PERIOD, TimeInterval::m1, 20));
+        assert!(args.inactive);
+
+        assert!(parse(&["add-strategy", "--bars", "500", "--percent", "95", "--intervals", "7m"]).is_err());
+        assert!(parse(&["add-strategy", "--bars", "500", "--percent", "95", "--hedge-mode", "kalman"]).is_err());
+        let rejected = |flags: &[&str]| -> Result<bool> {
+            let Some(Command::AddStrategy(args)) = parse(&[&["add-strategy"], flags].concat())?.command else { panic!("expected add-strategy") };
+            Ok(args.to_strategy().is_err())
+        };
+        assert!(rejected(&["--bars", "0", "--percent", "95"])?);
+        assert!(rejected(&["--bars", "500", "--percent", "0"])?);
+        assert!(rejected(&["--bars", "500", "--percent", "95", "--z-score-period", "1"])?);
+        assert!(rejected(&["--bars", "500", "--percent", "95", "--intraday-interval", "1h"])?);
+        assert!(rejected(&["--bars", "500", "--percent", "95", "--hedge-mode", "engle_granger"])?);
+        assert!(!rejected(&["--bars", "500", "--percent", "95", "--hedge-mode", "engle_granger", "--spread-kind", "normalized_spread"])?);
+        assert!(rejected(&["--bars", "500", "--percent", "95", "--intervals", "4h,1h", "--interval-bars", "500"])?);
+        assert!(rejected(&["--bars", "500", "--percent", "95", "--interval-bars", "0"])?);
+        assert!(!rejected(&["--bars", "500", "--percent", "95"])?);
+        Ok(())
+    }
+}
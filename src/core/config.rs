@@ -2,14 +2,20 @@ use std::collections::HashMap;
 use crate::core::types::{ConfigId, TradingStrategy};
 use crate::storage::database::{DatabaseInterface, PostgresDataBase};
 use anyhow::{anyhow, Result};
+use tracing::error;
 
 
 pub struct Config{
-    configuration_strategy:HashMap<ConfigId, TradingStrategy>
+    configuration_strategy:HashMap<ConfigId, TradingStrategy>,
+    // The strategy a run is pinned to, the other active ones are left out
+    strategy:Option<ConfigId>
 }
 impl Config{
     pub async fn new<D:DatabaseInterface>(database:&D) ->Result<Config>{
-        let mut config=Config{configuration_strategy:HashMap::new()};
+        Self::for_strategy(database, None).await
+    }
+    pub async fn for_strategy<D:DatabaseInterface>(database:&D, strategy:Option<ConfigId>) ->Result<Config>{
+        let mut config=Config{configuration_strategy:HashMap::new(),strategy};
         config.update_configuration(database).await?;
         Ok(config)
     }
@@ -33,7 +39,21 @@ impl Config{
     }
 
     pub async fn update_configuration<D: DatabaseInterface>(&mut self, database:&D) -> Result<()> {
-        let (config, _) = database.get_active_strategies().await?;
+        let (mut config, _) = database.get_active_strategies().await?;
+        // A strategy the pair analysis can not run as configured is left out
+        config.retain(|_, strategy| match strategy.check_hedge() {
+            Ok(()) => true,
+            Err(e) => {
+                error!("{}", e);
+                false
+            }
+        });
+        if let Some(strategy)=self.strategy {
+            config.retain(|id, _| *id==strategy);
+            if config.is_empty() {
+                return Err(anyhow!("strategy {} of the run is not active", strategy));
+            }
+        }
         self.configuration_strategy=config;
         Ok(())
     }
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use anyhow::anyhow;
 use sqlx::{FromRow};
 use anyhow::Result;
@@ -31,52 +31,349 @@ pub struct StartInfo{
     pub last_configuration_id:i32,
     pub base_date:NaiveDateTime,
     pub last_date_bypass:NaiveDateTime,
-    pub current_day:i32
+    pub current_day:i32,
+    // The window stored with the run, current_day counts its days
+    pub replay_window:ReplayWindow,
+    // The strategy the run is pinned to, None follows the latest active one
+    pub strategy:Option<ConfigId>
+}
+// Days a replay analyses: `start`, then every `step_days` days up to and including `end`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayWindow {
+    pub start:NaiveDate,
+    pub end:NaiveDate,
+    pub step_days:i32,
+}
+pub const DEFAULT_REPLAY_DAYS:i32=270;
+impl ReplayWindow {
+    pub fn new(start:NaiveDate, end:NaiveDate, step_days:i32)->Result<Self>{
+        if step_days<=0 {
+            return Err(anyhow!("replay step of {} days is not positive", step_days));
+        }
+        if end<start {
+            return Err(anyhow!("replay ends on {} before it starts on {}", end, start));
+        }
+        Ok(Self{start,end,step_days})
+    }
+    // `days` analysed days from `start`
+    pub fn with_days(start:NaiveDate, days:i32, step_days:i32)->Result<Self>{
+        if days<=0 {
+            return Err(anyhow!("replay of {} days is empty", days));
+        }
+        Self::new(start, start+Duration::days((days as i64-1)*step_days.max(1) as i64), step_days)
+    }
+    pub fn days(&self)->i32{
+        ((self.end-self.start).num_days()/self.step_days as i64) as i32+1
+    }
+    // Midnight of the analysed day `index`, counted from 0
+    pub fn day(&self, index:i32)->NaiveDateTime{
+        (self.start+Duration::days(index as i64*self.step_days as i64)).and_time(NaiveTime::MIN)
+    }
+    // A run stored with this window and `done` days analysed can go on with `next`: the days already
+    // analysed must stay the same, only the end may move, and not before them
+    pub fn check_resume(&self, next:&ReplayWindow, done:i32)->Result<()>{
+        if done<=0 || self==next {
+            return Ok(());
+        }
+        if self.start!=next.start || self.step_days!=next.step_days {
+            return Err(anyhow!("the replay in progress runs from {} every {} days with {} days done, it can't go on from {} every {} days; reset it first",
+                self.start, self.step_days, done, next.start, next.step_days));
+        }
+        if next.days()<done {
+            return Err(anyhow!("the replay in progress has {} days done, past the end {} of the new window", done, next.end));
+        }
+        Ok(())
+    }
+}
+impl Default for ReplayWindow {
+    // The window the collector replayed before it was configurable
+    fn default()->Self{
+        let start=NaiveDate::from_ymd_opt(2024, 9, 1).unwrap();
+        Self{start,end:start+Duration::days(DEFAULT_REPLAY_DAYS as i64-1),step_days:1}
+    }
 }
 #[derive(Clone)]
 pub struct TimeframeConfig {
-    pub name_interval:String,
+    pub interval:TimeInterval,
     pub limit:u16,
-    pub interval_hour:u16,
+    // Intraday candles scored against the bars: their resolution and how many of them are fetched
+    pub intraday_interval:TimeInterval,
+    pub intraday_limit:u16,
 }
 pub type ConfigId=i32;
-#[derive(Debug, FromRow, Clone, Default)]
+// A backtest or live run, see the runs table: its progress, analyses and pair data are kept apart from the other runs
+pub type RunId=i32;
+pub const DEFAULT_Z_SCORE_PERIOD:i32=240;
+pub const DEFAULT_INTRADAY_WINDOW_MINUTES:i32=1440;
+pub const DEFAULT_MAX_JUMP_PERCENT:f32=50.0;
+// Resolutions allowed for the intraday candles
+pub const INTRADAY_INTERVALS:[TimeInterval;4]=[TimeInterval::m1, TimeInterval::m3, TimeInterval::m5, TimeInterval::m15];
+#[derive(Debug, FromRow, Clone)]
 pub struct TradingStrategy {
     pub id:ConfigId,
     pub h1:i32,
+    // Bars fetched for the analysed intervals without their own count, the name is older than multi-interval support
     pub h4:i32,
-    pub percent:f32
+    // Intervals analysed side by side, the first one is the primary: it is exported at the top level and used for baskets
+    pub intervals:Vec<TimeInterval>,
+    // Bars fetched per interval, in the order of intervals, None fetches h4 bars for each of them
+    pub interval_bars:Option<Vec<i32>>,
+    pub percent:f32,
+    pub stationarity_backend:StationarityBackend,
+    pub stationarity_rule:StationarityRule,
+    pub hedge_mode:HedgeMode,
+    pub spread_kind:SpreadKind,
+    // Johansen baskets: legs per basket (below 3 disables them) and how many symbols are combined
+    pub max_basket_size:i32,
+    pub basket_universe:i32,
+    // Pairs reverting slower than this (in bars of the analysed interval) or with a higher Hurst exponent are dropped, None disables the limit
+    pub max_half_life:Option<f32>,
+    pub max_hurst:Option<f32>,
+    // Rolling window of the z-score, in bars of the series it is computed on
+    pub z_score_period:i32,
+    // Intraday candles: resolution and the length of the fetched window in minutes
+    pub intraday_interval:TimeInterval,
+    pub intraday_window_minutes:i32,
+    // Joining the legs of a synthetic pair on open_time: how gaps are repaired and above which share
+    // of gapped timestamps the pair is rejected, None disables the limit
+    pub gap_policy:GapPolicy,
+    pub max_gap_percent:Option<f32>,
+    // Candle quality pass before the analysis: what is done with flagged pairs and the single-bar
+    // price move, in percent, above which a bar counts as an extreme jump
+    pub quality_action:QualityAction,
+    pub max_jump_percent:f32,
+    // Candles of regular pairs are exported with open/high/low, volumes and trade count
+    pub export_ohlcv:bool
 }
 impl TradingStrategy {
     pub fn new()->Self{
-        Self{id:-1,h1:-1,h4:-1,percent:-1.0}
+        Self{id:-1,h1:-1,h4:-1,intervals:vec![TimeInterval::h4],interval_bars:None,percent:-1.0,stationarity_backend:StationarityBackend::Native,stationarity_rule:StationarityRule::Adf,hedge_mode:HedgeMode::Ratio,spread_kind:SpreadKind::PriceRatio,max_basket_size:0,basket_universe:0,max_half_life:None,max_hurst:None,z_score_period:DEFAULT_Z_SCORE_PERIOD,intraday_interval:TimeInterval::m1,intraday_window_minutes:DEFAULT_INTRADAY_WINDOW_MINUTES,gap_policy:GapPolicy::Drop,max_gap_percent:None,quality_action:QualityAction::Keep,max_jump_percent:DEFAULT_MAX_JUMP_PERCENT,export_ohlcv:false}
     }
     pub fn to_timeframe_config(&self,time_interval: &TimeInterval)->Result<TimeframeConfig>{
-        let mut res: anyhow::Result<TimeframeConfig> =Err(anyhow!("error timeframe"));
-        if *time_interval==TimeInterval::h4{
-            res=Ok(TimeframeConfig{
-                limit: self.h4 as u16,
-                interval_hour:4,
-                name_interval:"4h".to_string()
-            });
+        if !self.intervals.contains(time_interval){
+            return Err(anyhow!("interval {} is not configured for strategy {}", time_interval.binance_name(), self.id));
+        }
+        Ok(TimeframeConfig{
+            interval:time_interval.clone(),
+            limit: self.bars(time_interval)?,
+            intraday_interval:self.intraday_interval.clone(),
+            intraday_limit:self.intraday_limit()?,
+        })
+    }
+    // Bars fetched for one of the analysed intervals
+    pub fn bars(&self,time_interval: &TimeInterval)->Result<u16>{
+        let index=self.intervals.iter().position(|interval| interval==time_interval)
+            .ok_or(anyhow!("interval {} is not configured for strategy {}", time_interval.binance_name(), self.id))?;
+        let bars=match &self.interval_bars {
+            Some(interval_bars) if interval_bars.len()!=self.intervals.len() => {
+                return Err(anyhow!("strategy {} has {} interval bar counts for {} intervals", self.id, interval_bars.len(), self.intervals.len()));
+            }
+            Some(interval_bars) => interval_bars[index],
+            None => self.h4,
+        };
+        u16::try_from(bars).ok().filter(|&bars| bars>0)
+            .ok_or(anyhow!("{} bars of {} are out of range for strategy {}", bars, time_interval.binance_name(), self.id))
+    }
+    // Number of intraday candles covering intraday_window_minutes
+    pub fn intraday_limit(&self)->Result<u16>{
+        if !INTRADAY_INTERVALS.contains(&self.intraday_interval){
+            return Err(anyhow!("intraday interval {} is not supported for strategy {}", self.intraday_interval.binance_name(), self.id));
+        }
+        let minutes=self.intraday_interval.minutes();
+        let window=self.intraday_window_minutes as i64;
+        if window<=0 || window%minutes!=0 {
+            return Err(anyhow!("intraday window of {} minutes is not a multiple of {} for strategy {}", window, self.intraday_interval.binance_name(), self.id));
+        }
+        u16::try_from(window/minutes).map_err(|_| anyhow!("intraday window of {} minutes is too long for strategy {}", window, self.id))
+    }
+    // Engle-Granger fits a hedge ratio only the spreads use, with a ratio it would be dropped silently
+    pub fn check_hedge(&self)->Result<()>{
+        if self.hedge_mode==HedgeMode::EngleGranger && !self.spread_kind.is_hedged(){
+            return Err(anyhow!("hedge mode engle_granger needs spread kind log_spread or normalized_spread, not {} for strategy {}", self.spread_kind.as_str(), self.id));
+        }
+        Ok(())
+    }
+    pub fn primary_interval(&self)->TimeInterval{
+        self.intervals.first().cloned().unwrap_or_default()
+    }
+}
+impl Default for TradingStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[derive(Debug,sqlx::Type, Clone, Default,PartialEq, Eq, clap::ValueEnum)]
+#[sqlx(type_name = "stationarity_backend", rename_all = "lowercase")]
+#[value(rename_all = "lower")]
+pub enum StationarityBackend {
+    #[default]
+    Native,
+    Python,
+    Stub,
+}
+// How ADF, KPSS and Phillips-Perron are combined into the pair filter
+#[derive(Debug,sqlx::Type, Clone, Copy, Default,PartialEq, Eq, clap::ValueEnum)]
+#[sqlx(type_name = "stationarity_rule", rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum StationarityRule {
+    #[default]
+    Adf,
+    AdfAndKpss,
+    AdfAndPp,
+    All,
+    Majority,
+}
+// How the two legs of a synthetic pair are combined into one series
+#[derive(Debug,sqlx::Type, Clone, Copy, Default,PartialEq, Eq, clap::ValueEnum)]
+#[sqlx(type_name = "hedge_mode", rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum HedgeMode {
+    // first / second, hedge ratio 1
+    #[default]
+    Ratio,
+    // OLS of ln(first) on ln(second), the series is the residual spread
+    EngleGranger,
+}
+// What to do with a timestamp present in only one leg of a synthetic pair
+#[derive(Debug,sqlx::Type, Clone, Copy, Default,PartialEq, Eq, clap::ValueEnum)]
+#[sqlx(type_name = "gap_policy", rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum GapPolicy {
+    // Skip the timestamp
+    #[default]
+    Drop,
+    // Repeat the last price of the leg that has no candle, leading gaps are still dropped
+    ForwardFill,
+}
+// What the candle quality pass does with a pair whose candles were flagged
+#[derive(Debug,sqlx::Type, Clone, Copy, Default,PartialEq, Eq, clap::ValueEnum)]
+#[sqlx(type_name = "quality_action", rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum QualityAction {
+    // Analyse the candles as they came from the exchange
+    #[default]
+    Keep,
+    // Sort and deduplicate the candles, replace bars with non-positive prices and single-bar spikes by a flat bar at the previous close
+    Repair,
+    // Drop the pair, requested pairs are repaired instead
+    Exclude,
+}
+// Representation of the synthetic series, computed in f64. The hedge ratio from HedgeMode
+// only enters the two spread kinds, the ratios are unhedged
+#[derive(Debug,sqlx::Type, Clone, Copy, Default,PartialEq, Eq, clap::ValueEnum)]
+#[sqlx(type_name = "spread_kind", rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum SpreadKind {
+    // first / second
+    #[default]
+    PriceRatio,
+    // ln(first) - ln(second)
+    LogRatio,
+    // ln(first) - hedge_ratio * ln(second) - intercept
+    LogSpread,
+    // first / first[0] - hedge_ratio * second / second[0], prices rebased to the start of the h4 window
+    NormalizedSpread,
+}
+impl SpreadKind {
+    pub fn is_hedged(&self) -> bool {
+        matches!(self, SpreadKind::LogSpread | SpreadKind::NormalizedSpread)
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpreadKind::PriceRatio => "price_ratio",
+            SpreadKind::LogRatio => "log_ratio",
+            SpreadKind::LogSpread => "log_spread",
+            SpreadKind::NormalizedSpread => "normalized_spread",
         }
-        res
     }
 }
+// Binance kline intervals, named like the candle_timeframe values
 #[derive(Debug,sqlx::Type, Clone, Default,PartialEq, Eq, Hash)]
 #[sqlx(type_name = "candle_timeframe")]
 pub enum TimeInterval {
+    m1,
+    m3,
+    m5,
+    m15,
+    m30,
+    h1,
+    h2,
     #[default]
     h4,
+    h6,
+    h8,
+    h12,
+    d1,
+    d3,
+    w1,
 }
 impl TimeInterval {
     pub const fn all() -> &'static [TimeInterval] {
         &[
-
+            TimeInterval::m1,
+            TimeInterval::m3,
+            TimeInterval::m5,
+            TimeInterval::m15,
+            TimeInterval::m30,
+            TimeInterval::h1,
+            TimeInterval::h2,
             TimeInterval::h4,
-
+            TimeInterval::h6,
+            TimeInterval::h8,
+            TimeInterval::h12,
+            TimeInterval::d1,
+            TimeInterval::d3,
+            TimeInterval::w1,
         ]
     }
+    // Interval string of the Binance klines endpoint
+    pub fn binance_name(&self) -> &'static str {
+        match self {
+            TimeInterval::m1 => "1m",
+            TimeInterval::m3 => "3m",
+            TimeInterval::m5 => "5m",
+            TimeInterval::m15 => "15m",
+            TimeInterval::m30 => "30m",
+            TimeInterval::h1 => "1h",
+            TimeInterval::h2 => "2h",
+            TimeInterval::h4 => "4h",
+            TimeInterval::h6 => "6h",
+            TimeInterval::h8 => "8h",
+            TimeInterval::h12 => "12h",
+            TimeInterval::d1 => "1d",
+            TimeInterval::d3 => "3d",
+            TimeInterval::w1 => "1w",
+        }
+    }
+    pub fn from_binance_name(name: &str) -> Option<TimeInterval> {
+        TimeInterval::all().iter().find(|interval| interval.binance_name() == name).cloned()
+    }
+    pub fn minutes(&self) -> i64 {
+        match self {
+            TimeInterval::m1 => 1,
+            TimeInterval::m3 => 3,
+            TimeInterval::m5 => 5,
+            TimeInterval::m15 => 15,
+            TimeInterval::m30 => 30,
+            TimeInterval::h1 => 60,
+            TimeInterval::h2 => 120,
+            TimeInterval::h4 => 240,
+            TimeInterval::h6 => 360,
+            TimeInterval::h8 => 480,
+            TimeInterval::h12 => 720,
+            TimeInterval::d1 => 1440,
+            TimeInterval::d3 => 4320,
+            TimeInterval::w1 => 10080,
+        }
+    }
+    // The close of the last bar finished at `time`, which is the open_time of the bar forming then. Bars are
+    // aligned to the Unix epoch in UTC, the weekly ones to the first Monday after it.
+    pub fn last_close(&self, time: NaiveDateTime) -> NaiveDateTime {
+        let days = if *self == TimeInterval::w1 { 4 } else { 0 };
+        let origin = NaiveDate::from_ymd_opt(1970, 1, 1 + days).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let minutes = (time - origin).num_minutes().div_euclid(self.minutes()) * self.minutes();
+        origin + Duration::minutes(minutes)
+    }
 }
 
 
@@ -106,12 +403,25 @@ pub enum ValueType {
 
 
 
+// Kline as returned by the exchange, price is the close
 #[derive(Debug,Default,Clone,sqlx::FromRow)]
 pub struct Candle {
-    pub price:f32,
+    pub price:f64,
+    pub open:f64,
+    pub high:f64,
+    pub low:f64,
+    pub volume:f64,
+    pub quote_volume:f64,
+    pub trades:i64,
     pub close_time:NaiveDateTime,
     pub open_time:NaiveDateTime
 }
+impl Candle {
+    // Bar without trades at the close of this one, used where a missing or broken bar is filled
+    pub fn flat(&self, open_time:NaiveDateTime, close_time:NaiveDateTime)->Candle{
+        Candle{price:self.price, open:self.price, high:self.price, low:self.price, open_time, close_time, ..Default::default()}
+    }
+}
 #[derive(Debug,Default,Clone,sqlx::FromRow)]
 pub struct BaseAsset {
     pub id:i32,
@@ -128,8 +438,19 @@ impl BaseAsset {
 #[derive(Debug,Default,Clone,sqlx::FromRow)]
 pub struct PairData {
     pub stationarity:f32,
-    pub candles_h4:Vec<Candle>,
-    pub z_score_h4:Vec<f32>,
+    pub kpss_p_value:f32,
+    pub pp_p_value:f32,
+    pub hedge_ratio:f32,
+    pub hedge_intercept:f32,
+    pub spread_kind:SpreadKind,
+    // In bars of time_interval, infinite when the series does not revert
+    pub half_life:f32,
+    pub hurst:f32,
+    // Bars of the legs dropped or forward-filled when they were joined on open_time
+    pub repaired_bars:i32,
+    // Bars of time_interval and their z-scores
+    pub candles:Vec<Candle>,
+    pub z_score:Vec<f32>,
     pub candles_minutes:Vec<Candle>,
     pub z_score_minutes:Vec<f32>,
     pub time_interval: TimeInterval,
@@ -137,11 +458,11 @@ pub struct PairData {
 }
 impl PairData {
     pub fn new(time_frame: TimeInterval,config_id: ConfigId) -> PairData {
-        PairData {stationarity:-1.0, candles_minutes:Vec::new(), z_score_minutes:Vec::new(), candles_h4:Vec::new(),z_score_h4:Vec::new(), time_interval:time_frame,configuration_id:config_id}
+        PairData {stationarity:-1.0, kpss_p_value:-1.0, pp_p_value:-1.0, hedge_ratio:1.0, hedge_intercept:0.0, spread_kind:SpreadKind::PriceRatio, half_life:-1.0, hurst:-1.0, repaired_bars:0, candles_minutes:Vec::new(), z_score_minutes:Vec::new(), candles:Vec::new(),z_score:Vec::new(), time_interval:time_frame,configuration_id:config_id}
     }
-    pub fn new_array(config_id: ConfigId)->Vec<PairData>{
+    pub fn new_array(config_id: ConfigId, intervals:&[TimeInterval])->Vec<PairData>{
         let mut pair_data=Vec::new();
-        for element in TimeInterval::all(){
+        for element in intervals{
             pair_data.push(PairData::new(element.clone(),config_id));
         }
         pair_data
@@ -164,9 +485,9 @@ impl TradingPair {
         }
     }
 
-    pub fn init_pair_data(&mut self, config_id: ConfigId) {
+    pub fn init_pair_data(&mut self, config_id: ConfigId, intervals:&[TimeInterval]) {
         let mut timeframes = Vec::new();
-        for time_interval in TimeInterval::all() {
+        for time_interval in intervals {
             timeframes.push(PairData::new(time_interval.clone(), config_id));
         }
         self.pair_data_map.insert(config_id, timeframes);
@@ -218,9 +539,9 @@ impl SyntheticPairFullData {
 
         }
     }
-    pub fn init_pair_data(&mut self, config_id: ConfigId) {
+    pub fn init_pair_data(&mut self, config_id: ConfigId, intervals:&[TimeInterval]) {
         let mut timeframes = Vec::new();
-        for time_interval in TimeInterval::all() {
+        for time_interval in intervals {
             timeframes.push(PairData::new(time_interval.clone(), config_id));
         }
         self.synthetic_data.insert(config_id, timeframes);
@@ -242,3 +563,174 @@ impl SyntheticPairFullData {
         }
     }
 }
+pub const BASKET_SEPARATOR:char='+';
+#[derive(Debug,Default,Clone)]
+pub struct SyntheticBasket {
+    pub id:i32,
+    // Legs joined with BASKET_SEPARATOR, e.g. "BTCUSDT+ETHUSDT+SOLUSDT"
+    pub name:String,
+    pub legs:Vec<BaseAsset>,
+    // Cointegrating vector from the Johansen test over log prices, the first leg has weight 1
+    pub weights:Vec<f32>,
+    pub synthetic_data:HashMap<ConfigId, Vec<PairData>>,
+}
+impl SyntheticBasket {
+    pub fn new(legs:Vec<BaseAsset>)-> SyntheticBasket {
+        let names:Vec<&str>=legs.iter().map(|leg| leg.name.as_str()).collect();
+        SyntheticBasket {
+            id:-1,
+            name:names.join(&BASKET_SEPARATOR.to_string()),
+            legs,
+            weights:Vec::new(),
+            synthetic_data:HashMap::new()
+        }
+    }
+    pub fn parse_name(name:&str)->Option<Vec<String>>{
+        let legs:Vec<String>=name.split(BASKET_SEPARATOR).map(|leg| leg.to_string()).collect();
+        if legs.len()<3 || legs.iter().any(|leg| leg.is_empty()){
+            return None;
+        }
+        Some(legs)
+    }
+    pub fn init_pair_data(&mut self, config_id: ConfigId, intervals:&[TimeInterval]) {
+        let mut timeframes = Vec::new();
+        for time_interval in intervals {
+            timeframes.push(PairData::new(time_interval.clone(), config_id));
+        }
+        self.synthetic_data.insert(config_id, timeframes);
+    }
+
+    pub fn get_timeframe_data(&self, config_id: ConfigId, time_interval: &TimeInterval) -> Option<&PairData> {
+        if let Some(timeframes) = self.synthetic_data.get(&config_id) {
+            timeframes.iter().find(|data| &data.time_interval == time_interval)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_timeframe_data_mut(&mut self, config_id: ConfigId, time_interval: &TimeInterval) -> Option<&mut PairData> {
+        if let Some(timeframes) = self.synthetic_data.get_mut(&config_id) {
+            timeframes.iter_mut().find(|data| &data.time_interval == time_interval)
+        } else {
+            None
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_interval_binance_names() {
+        assert_eq!(TimeInterval::all().len(), 14);
+        for interval in TimeInterval::all() {
+            assert_eq!(TimeInterval::from_binance_name(interval.binance_name()).as_ref(), Some(interval));
+        }
+        assert!(TimeInterval::all().windows(2).all(|pair| pair[0].minutes() < pair[1].minutes()));
+        assert_eq!(TimeInterval::h4.minutes(), 240);
+        assert_eq!(TimeInterval::w1.binance_name(), "1w");
+        assert_eq!(TimeInterval::from_binance_name("4H"), None);
+    }
+
+    #[test]
+    fn test_last_close() {
+        let time = |day: u32, hour: u32, minute: u32| NaiveDate::from_ymd_opt(2024, 9, day).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        assert_eq!(TimeInterval::h4.last_close(time(4, 13, 59)), time(4, 12, 0));
+        assert_eq!(TimeInterval::h4.last_close(time(4, 12, 0)), time(4, 12, 0));
+        assert_eq!(TimeInterval::m15.last_close(time(4, 13, 59)), time(4, 13, 45));
+        assert_eq!(TimeInterval::d1.last_close(time(4, 13, 59)), time(4, 0, 0));
+        // September 2, 2024 is a Monday
+        assert_eq!(TimeInterval::w1.last_close(time(4, 13, 59)), time(2, 0, 0));
+        assert_eq!(TimeInterval::w1.last_close(time(9, 0, 0)), time(9, 0, 0));
+    }
+
+    #[test]
+    fn test_strategy_intervals() -> Result<()> {
+        let mut strategy = TradingStrategy { id: 1, h4: 500, ..Default::default() };
+        assert_eq!(strategy.primary_interval(), TimeInterval::h4);
+        assert!(strategy.to_timeframe_config(&TimeInterval::h1).is_err());
+
+        strategy.intervals = vec![TimeInterval::h1, TimeInterval::h4, TimeInterval::d1];
+        let parameter = strategy.to_timeframe_config(&TimeInterval::d1)?;
+        assert_eq!((parameter.interval, parameter.limit), (TimeInterval::d1, 500));
+        assert_eq!(strategy.primary_interval(), TimeInterval::h1);
+
+        let mut pair = TradingPair::new();
+        pair.init_pair_data(strategy.id, &strategy.intervals);
+        assert_eq!(pair.pair_data_map[&1].iter().map(|data| data.time_interval.clone()).collect::<Vec<_>>(), strategy.intervals);
+        assert!(pair.get_timeframe_data(1, &TimeInterval::m15).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_intraday_limit() -> Result<()> {
+        let mut strategy = TradingStrategy { h4: 500, ..TradingStrategy::new() };
+        assert_eq!(strategy.intraday_limit()?, 1440);
+        assert_eq!(strategy.to_timeframe_config(&TimeInterval::h4)?.intraday_interval, TimeInterval::m1);
+
+        strategy.intraday_interval = TimeInterval::m5;
+        strategy.intraday_window_minutes = 720;
+        assert_eq!(strategy.intraday_limit()?, 144);
+
+        strategy.intraday_window_minutes = 722;
+        assert!(strategy.intraday_limit().is_err());
+        strategy.intraday_interval = TimeInterval::h1;
+        strategy.intraday_window_minutes = 1440;
+        assert!(strategy.intraday_limit().is_err());
+        assert!(strategy.to_timeframe_config(&TimeInterval::h4).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bars_per_interval() -> Result<()> {
+        let mut strategy = TradingStrategy { id: 1, h4: 500, intervals: vec![TimeInterval::h4, TimeInterval::h1], ..Default::default() };
+        assert_eq!((strategy.bars(&TimeInterval::h4)?, strategy.bars(&TimeInterval::h1)?), (500, 500));
+        strategy.interval_bars = Some(vec![500, 2000]);
+        assert_eq!(strategy.to_timeframe_config(&TimeInterval::h1)?.limit, 2000);
+        assert_eq!(strategy.to_timeframe_config(&TimeInterval::h4)?.limit, 500);
+        assert!(strategy.bars(&TimeInterval::d1).is_err());
+        strategy.interval_bars = Some(vec![500]);
+        assert!(strategy.bars(&TimeInterval::h4).is_err());
+        strategy.interval_bars = Some(vec![500, 70000]);
+        assert!(strategy.bars(&TimeInterval::h1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_hedge() {
+        let mut strategy = TradingStrategy::new();
+        assert!(strategy.check_hedge().is_ok());
+        strategy.hedge_mode = HedgeMode::EngleGranger;
+        assert!(strategy.check_hedge().is_err());
+        strategy.spread_kind = SpreadKind::LogRatio;
+        assert!(strategy.check_hedge().is_err());
+        for spread_kind in [SpreadKind::LogSpread, SpreadKind::NormalizedSpread] {
+            strategy.spread_kind = spread_kind;
+            assert!(strategy.check_hedge().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_replay_window() -> Result<()> {
+        let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+        let window = ReplayWindow::new(date(1, 1), date(1, 10), 3)?;
+        // January 1, 4, 7 and 10
+        assert_eq!(window.days(), 4);
+        assert_eq!(window.day(3), date(1, 10).and_hms_opt(0, 0, 0).unwrap());
+        assert_eq!(ReplayWindow::with_days(date(1, 1), 4, 3)?, window);
+        assert_eq!(ReplayWindow::default().days(), DEFAULT_REPLAY_DAYS);
+        assert!(ReplayWindow::new(date(1, 10), date(1, 1), 1).is_err());
+        assert!(ReplayWindow::new(date(1, 1), date(1, 10), 0).is_err());
+        assert!(ReplayWindow::with_days(date(1, 1), 0, 1).is_err());
+
+        // A fresh run takes any window, a started one only a new end that keeps the days done
+        let other = ReplayWindow::new(date(3, 1), date(3, 31), 1)?;
+        window.check_resume(&other, 0)?;
+        assert!(window.check_resume(&other, 2).is_err());
+        assert!(window.check_resume(&ReplayWindow::new(date(1, 1), date(1, 10), 1)?, 2).is_err());
+        window.check_resume(&ReplayWindow::new(date(1, 1), date(1, 31), 3)?, 2)?;
+        window.check_resume(&ReplayWindow::new(date(1, 1), date(1, 4), 3)?, 2)?;
+        assert!(window.check_resume(&ReplayWindow::new(date(1, 1), date(1, 3), 3)?, 2).is_err());
+        Ok(())
+    }
+}
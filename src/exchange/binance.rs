@@ -3,15 +3,19 @@ use std::sync::Arc;
 use std::time::Instant;
 use binance_sync::api::Binance as SyncBinance;
 use binance_sync::futures::model::Symbol;
-use binance_async::api::Binance as AsyncBinance;
 use binance_sync::futures::general::FuturesGeneral;
-use binance_async::rest_model::{KlineSummaries,KlineSummary};
-use binance_async::futures::market::FuturesMarket;
+use binance_async::rest_model::KlineSummary;
 use anyhow::{Result, anyhow};
-use chrono::{DateTime, NaiveDateTime, Utc, TimeZone, Duration, NaiveDate};
+use chrono::{DateTime, NaiveDateTime, Utc, Duration, NaiveDate};
 use tokio::task;
 use crate::core::types::{TimeInterval, TimeframeConfig, Candle, TradingPair, PairData, TradingStrategy, ConfigId};
-use crate::logic::{convert_to_candles, remove_duplicate_full_pairs};
+use crate::logic::{convert_to_candles, convert_to_klines, remove_duplicate_full_pairs};
+use crate::storage::klines::{cache_lookup, CacheLookup, KlineStore};
+use crate::exchange::error::ExchangeError;
+use crate::exchange::limiter::{klines_weight, RateLimitConfig, RateLimiter};
+use crate::exchange::retry::RetryPolicy;
+use crate::exchange::stream::{KlineBackfill, SharedKlineBuffers};
+use reqwest::StatusCode;
 use async_trait::async_trait;
 use binance_sync::api::Binance;
 
@@ -23,8 +27,9 @@ use crate::core::config::Config;
 #[async_trait::async_trait]
 pub trait ExchangeInterface {
 
+    // Loads the candles closed before `until`
     async fn get_candles_for_pairs(&self,
-                                   pairs: &mut Vec<TradingPair>, config:&Config, data_today:NaiveDateTime
+                                   pairs: &mut Vec<TradingPair>, config:&Config, until:NaiveDateTime
                                    ,load_only_minute:bool) -> Result<()>;
     async fn set_time_from_server(&self) -> Result<()>;
     async fn get_uninitialized_pair(&self,last_configuration:TradingStrategy)->Result<Vec<TradingPair>>;
@@ -40,164 +45,388 @@ pub trait ExchangeInterface {
         download_date: NaiveDateTime,
         include_minutes: bool
     ) -> Result<(Vec<KlineSummary>,Vec<KlineSummary>)>;
+    // Closed candles opened after `after` that the live kline stream received, up to the first missing bar.
+    // Sources without a stream have none.
+    async fn get_streamed_candles(&self, symbol:&str, interval:&TimeInterval, after:NaiveDateTime) -> Result<Vec<Candle>>;
 }
-pub struct BinanceExchange {
-    async_binance:Arc<FuturesMarket>,
-    api_key:String,
-    secret_key:String
-}
-impl BinanceExchange {
-    pub fn new(api_key:String,secret_key:String) ->Result<Self>{
-        let market: FuturesMarket = AsyncBinance::new(
-            Some(api_key.clone()),
-            Some(secret_key.clone())
-        );
-        Ok(BinanceExchange{async_binance:Arc::new(market),api_key,secret_key})
+// Fills the empty candles (or the empty minutes with load_only_minute) of every pair through the exchange requests,
+// shared by the exchange implementations. Up to chunks_in_same_time pairs are requested at once.
+pub(crate) async fn load_candles_for_pairs<E: ExchangeInterface + Sync + ?Sized>(exchange:&E, pairs: &mut [TradingPair], config:&Config,
+                                                                                 until:NaiveDateTime, load_only_minute:bool,
+                                                                                 chunks_in_same_time:usize) -> Result<()> {
+    struct CandleRequest {
+        pair_index: usize,
+        config_id: ConfigId,
+        timeframe_index: usize,
+        parameter: TimeframeConfig,
+        name: String,
+        limit: u16,
     }
-}
-#[async_trait::async_trait]
-impl ExchangeInterface for BinanceExchange {
 
+    let mut candle_requests = Vec::new();
 
-    async fn get_candles_for_pairs(&self,
-                                              pairs: &mut Vec<TradingPair>, config:&Config, mut data_today:NaiveDateTime
-                                              ,load_only_minute:bool
-    ) -> Result<()> {
-        data_today=data_today+chrono::Duration::days(1);
-        let mut last_update_time_d = Instant::now();
-        let last_config_id = config.get_last_config_id()?;
-        let chunks_in_same_time=1;
-        struct CandleRequest {
-            pair_index: usize,
-            config_id: ConfigId,
-            timeframe_index: usize,
-            parameter: TimeframeConfig,
-            name: String,
-            limit: u16,
-        }
-
-        let mut candle_requests = Vec::new();
+    for (pair_index, pair) in pairs.iter().enumerate() {
+        let name = pair.name.clone();
 
-        for (pair_index, pair) in pairs.iter().enumerate() {
-            let name = pair.name.clone();
+        for (config_id, timeframes) in &pair.pair_data_map {
+            match config.get(*config_id) {
+                Ok(config_data) => {
+                    for (timeframe_index, another_timeframe) in timeframes.iter().enumerate() {
+                        let bars_is_empty = another_timeframe.candles.is_empty();
+                        let minutes_is_empty = another_timeframe.candles_minutes.is_empty();
 
-            for (config_id, timeframes) in &pair.pair_data_map {
-                match config.get(*config_id) {
-                    Ok(config_data) => {
-                        for (timeframe_index, another_timeframe) in timeframes.iter().enumerate() {
-                            let h4_is_empty = another_timeframe.candles_h4.is_empty();
-                            let minutes_is_empty = another_timeframe.candles_minutes.is_empty();
+                        if !load_only_minute && !bars_is_empty {
+                            continue;
+                        }
 
-                            if load_only_minute==false && !h4_is_empty {
-                                continue;
-                            }
+                        if load_only_minute && !minutes_is_empty {
+                            continue;
+                        }
 
-                            if load_only_minute==true && !minutes_is_empty {
-                                continue;
-                            }
+                        let parameter = config_data.to_timeframe_config(&another_timeframe.time_interval)?;
 
-                            let parameter = config_data.to_timeframe_config(&another_timeframe.time_interval)?;
-
-                            let limit=parameter.limit;
-                            candle_requests.push(CandleRequest {
-                                pair_index,
-                                config_id:*config_id,
-                                timeframe_index,
-                                parameter,
-                                name: name.clone(),
-                                limit,
-                            });
+                        let limit=parameter.limit;
+                        candle_requests.push(CandleRequest {
+                            pair_index,
+                            config_id:*config_id,
+                            timeframe_index,
+                            parameter,
+                            name: name.clone(),
+                            limit,
+                        });
+                        // The minutes do not depend on the interval, one request fills every timeframe
+                        if load_only_minute {
+                            break;
                         }
-                    },
-                    Err(e) => return Err(e),
-                }
+                    }
+                },
+                Err(e) => return Err(e),
             }
         }
+    }
 
-        if candle_requests.is_empty() {
-            tracing::info!("No empty candles found, skipping data loading");
-            return Ok(());
-        }
-
-        for chunk in candle_requests.chunks(chunks_in_same_time) {
-            let futures = chunk.iter().map(|request| {
-                let binance_clone = self.async_binance.clone();
-                let name = request.name.clone();
-                let parameter = request.parameter.clone();
-                let limit = request.limit;
-                let data_today = data_today;
-                let load_minutes=load_only_minute;
-                tracing::info!("GET CANDLES: {}", name);
-                async move {
-                    let result = if load_minutes {
-                        self.get_minutes_only(
-                            name.as_str(),
-                            &parameter,
-                            data_today
-                        ).await.map(|minutes| (Vec::new(), minutes))
-                    } else {
-                        self.get_candles_manual(
-                            name.as_str(),
-                            limit,
-                            &parameter,
-                            data_today,
-                            false
-                        ).await
-                    };
+    if candle_requests.is_empty() {
+        tracing::info!("No empty candles found, skipping data loading");
+        return Ok(());
+    }
 
-                    (result, request.pair_index, request.config_id, request.timeframe_index, name)
-                }
-            });
+    let mut failed = 0;
+    let mut last_error = None;
+    for chunk in candle_requests.chunks(chunks_in_same_time.max(1)) {
+        let futures = chunk.iter().map(|request| {
+            let name = request.name.clone();
+            let parameter = request.parameter.clone();
+            let limit = request.limit;
+            let load_minutes=load_only_minute;
+            tracing::info!("GET CANDLES: {}", name);
+            async move {
+                let result = if load_minutes {
+                    exchange.get_minutes_only(
+                        name.as_str(),
+                        &parameter,
+                        until
+                    ).await.map(|minutes| (Vec::new(), minutes))
+                } else {
+                    exchange.get_candles_manual(
+                        name.as_str(),
+                        limit,
+                        &parameter,
+                        until,
+                        false
+                    ).await
+                };
 
-            let results = futures::future::join_all(futures).await;
-            for (result, pair_index, config_id, timeframe_index, name) in results {
-                match result {
-                    Ok((candles_h4, candles_minutes)) => {
-                        tracing::info!("SUCCESSFUL: {}", name);
-                        let arr_h4 = convert_to_candles(&candles_h4);
-                        let arr_minutes = convert_to_candles(&candles_minutes);
-                        if let Some(timeframe) = pairs[pair_index]
-                            .pair_data_map
-                            .get_mut(&config_id)
-                            .and_then(|timeframes| timeframes.get_mut(timeframe_index))
-                        {
-                            if !arr_h4.is_empty() && timeframe.candles_h4.is_empty() {
-                                timeframe.candles_h4 = arr_h4;
+                (result, request.pair_index, request.config_id, request.timeframe_index, name)
+            }
+        });
+
+        let results = futures::future::join_all(futures).await;
+        for (result, pair_index, config_id, timeframe_index, name) in results {
+            match result {
+                Ok((candles_bars, candles_minutes)) => {
+                    tracing::info!("SUCCESSFUL: {}", name);
+                    let arr_bars = convert_to_candles(&candles_bars);
+                    let arr_minutes = convert_to_candles(&candles_minutes);
+                    if let Some(timeframes) = pairs[pair_index].pair_data_map.get_mut(&config_id) {
+                        if let Some(timeframe) = timeframes.get_mut(timeframe_index) {
+                            if !arr_bars.is_empty() && timeframe.candles.is_empty() {
+                                timeframe.candles = arr_bars;
                             }
-                            if !arr_minutes.is_empty() && timeframe.candles_minutes.is_empty() {
-                                timeframe.candles_minutes = arr_minutes;
+                        }
+                        if !arr_minutes.is_empty() {
+                            for timeframe in timeframes.iter_mut().filter(|timeframe| timeframe.candles_minutes.is_empty()) {
+                                timeframe.candles_minutes = arr_minutes.clone();
                             }
                         }
-                    },
-                    Err(e) => {
-                        return Err(anyhow::anyhow!("Failed to get candles for {}: {:?}", name, e));
                     }
+                },
+                // The pair keeps its empty candles and is left out of the day, the other pairs go on
+                Err(e) => {
+                    tracing::error!("Failed to get candles for {}: {:?}", name, e);
+                    failed += 1;
+                    last_error = Some(e);
                 }
             }
         }
+    }
 
-        tracing::info!("DONE");
-        Ok(())
+    if let Some(e) = last_error {
+        if failed == candle_requests.len() {
+            return Err(e.context(format!("Failed to get candles for all {} requests", failed)));
+        }
+        tracing::info!("Candles of {} of {} requests failed", failed, candle_requests.len());
+    }
+    tracing::info!("DONE");
+    Ok(())
+}
+const BINANCE_FUTURES_URL: &str = "https://fapi.binance.com";
+
+#[derive(serde::Deserialize)]
+struct BinanceErrorBody {
+    code: i64,
+    msg: String,
+}
+// Kline rows as GET /fapi/v1/klines returns them: open_time, open, high, low, close, volume, close_time,
+// quote_volume, count, taker_buy_volume, taker_buy_quote_volume, ignore. Prices and volumes are strings.
+type KlineRow = (i64, String, String, String, String, String, i64, String, i64, String, String, serde_json::Value);
+
+fn parse_klines_response(body:&str) -> Result<Vec<KlineSummary>> {
+    let rows: Vec<KlineRow> = serde_json::from_str(body).map_err(|e| anyhow!("invalid klines response: {}", e))?;
+    let number = |value: &str| value.parse::<f64>().map_err(|e| anyhow!("invalid kline value {}: {}", value, e));
+    rows.iter().map(|row| Ok(KlineSummary {
+        open_time: row.0,
+        open: number(&row.1)?,
+        high: number(&row.2)?,
+        low: number(&row.3)?,
+        close: number(&row.4)?,
+        volume: number(&row.5)?,
+        close_time: row.6,
+        quote_asset_volume: number(&row.7)?,
+        number_of_trades: row.8,
+        taker_buy_base_asset_volume: number(&row.9)?,
+        taker_buy_quote_asset_volume: number(&row.10)?,
+    })).collect()
+}
+
+#[derive(Clone)]
+pub struct BinanceExchange {
+    http:reqwest::Client,
+    base_url:String,
+    // Weight budget and concurrency of every REST request
+    limiter:Arc<RateLimiter>,
+    retry_policy:RetryPolicy,
+    api_key:String,
+    secret_key:String,
+    // Closed klines are served from here first, only the missing bars are requested from Binance
+    kline_store:Option<Arc<dyn KlineStore + Send + Sync>>,
+    // Live mode, closed bars kept up to date by the kline stream
+    kline_buffers:Option<SharedKlineBuffers>
+}
+impl BinanceExchange {
+    pub fn new(api_key:String,secret_key:String) ->Result<Self>{
+        Ok(BinanceExchange{http:reqwest::Client::new(),base_url:BINANCE_FUTURES_URL.to_string(),
+            limiter:Arc::new(RateLimiter::new(&RateLimitConfig::default())),retry_policy:RetryPolicy::default(),
+            api_key,secret_key,kline_store:None,kline_buffers:None})
+    }
+    pub fn with_retry_policy(mut self, retry_policy:RetryPolicy)->Self{
+        self.retry_policy=retry_policy;
+        self
+    }
+    pub fn with_rate_limits(mut self, config:&RateLimitConfig)->Self{
+        self.limiter=Arc::new(RateLimiter::new(config));
+        self
+    }
+    pub fn with_kline_store(mut self, kline_store:Arc<dyn KlineStore + Send + Sync>)->Self{
+        self.kline_store=Some(kline_store);
+        self
+    }
+    pub fn with_kline_stream(mut self, kline_buffers:SharedKlineBuffers)->Self{
+        self.kline_buffers=Some(kline_buffers);
+        self
+    }
+    // A call of the blocking client, retried like the klines requests. Its errors carry no status, every one
+    // of them counts as a transport error.
+    async fn request_blocking<T, E, F>(&self, what:&str, weight:u32, request:F) -> Result<T>
+    where
+        T: Send + 'static,
+        E: std::fmt::Display,
+        F: Fn(&FuturesGeneral) -> std::result::Result<T, E> + Clone + Send + 'static,
+    {
+        let result = self.retry_policy.run(what, || {
+            let (api_key, secret_key, request) = (self.api_key.clone(), self.secret_key.clone(), request.clone());
+            async move {
+                let _permit = self.limiter.acquire(weight).await.map_err(|e| ExchangeError::Transport(e.to_string()))?;
+                task::spawn_blocking(move || {
+                    let general: FuturesGeneral = SyncBinance::new(Some(api_key), Some(secret_key));
+                    request(&general).map_err(|e| ExchangeError::Transport(e.to_string()))
+                }).await.map_err(|e| ExchangeError::Transport(e.to_string()))?
+            }
+        }).await;
+        result.map_err(|e| anyhow::Error::new(e).context(format!("Error server: {}", what)))
+    }
+    // One request. A 429/418 pauses every request of the exchange for Retry-After.
+    async fn request_klines_once(&self, symbol:&str, limit:u16, query:&[(&str, String)]) -> Result<Vec<KlineSummary>, ExchangeError> {
+        let permit = self.limiter.acquire(klines_weight(limit)).await.map_err(|e| ExchangeError::Transport(e.to_string()))?;
+        let response = self.http.get(format!("{}/fapi/v1/klines", self.base_url)).query(query).send().await?;
+        drop(permit);
+        self.limiter.observe_headers(response.headers());
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 418 {
+            return Err(ExchangeError::RateLimited { retry_after: self.limiter.back_off(response.headers()) });
+        }
+        let body = response.text().await?;
+        if status.is_server_error() {
+            return Err(ExchangeError::Transport(format!("{} {}", status, body)));
+        }
+        if !status.is_success() {
+            let error = serde_json::from_str::<BinanceErrorBody>(&body).map_err(|_| ExchangeError::Decode(format!("{} {}", status, body)))?;
+            return Err(match error.code {
+                -1121 => ExchangeError::InvalidSymbol(symbol.to_string()),
+                -1122 if error.msg.contains("Invalid symbol status") => ExchangeError::Delisted(symbol.to_string()),
+                code => ExchangeError::Rejected { status: status.as_u16(), code, msg: error.msg },
+            });
+        }
+        parse_klines_response(&body).map_err(|e| ExchangeError::Decode(e.to_string()))
+    }
+    // None when Binance reports the symbol as not trading, the transient errors are retried by the retry policy
+    async fn request_klines(&self, symbol:&str, interval:&TimeInterval, limit:u16, start_time:Option<u64>, end_time:u64) -> Result<Option<Vec<KlineSummary>>> {
+        let mut query = vec![("symbol", symbol.to_string()), ("interval", interval.binance_name().to_string()),
+                             ("limit", limit.to_string()), ("endTime", end_time.to_string())];
+        if let Some(start_time) = start_time {
+            query.push(("startTime", start_time.to_string()));
+        }
+        let what = format!("klines {} {}", symbol, interval.binance_name());
+        match self.retry_policy.run(&what, || self.request_klines_once(symbol, limit, &query)).await {
+            Ok(klines) => Ok(Some(klines)),
+            Err(ExchangeError::Delisted(_)) => Ok(None),
+            Err(e) => Err(anyhow::Error::new(e).context(format!("Помилка при отриманні свічок {}", what))),
+        }
+    }
+    // The last `limit` klines opened before end_time, paging backwards
+    async fn fetch_klines_backwards(&self, symbol:&str, interval:&TimeInterval, limit:u16, end_time:u64) -> Result<Option<Vec<KlineSummary>>> {
+        let mut all_klines = Vec::new();
+        let mut remaining_limit = limit;
+        let mut time_timestamp = end_time;
+        while remaining_limit > 0 {
+            let limit = remaining_limit.min(1500);
+            let Some(klines_arr) = self.request_klines(symbol, interval, limit, None, time_timestamp).await? else {
+                return Ok(None);
+            };
+            if klines_arr.is_empty() {
+                break;
+            }
+            time_timestamp = (klines_arr[0].open_time - 1000) as u64;
+            all_klines.splice(0..0, klines_arr);
+            remaining_limit -= limit;
+        }
+        Ok(Some(all_klines))
+    }
+    // Klines opened from start_time to end_time, paging forwards
+    async fn fetch_klines_forwards(&self, symbol:&str, interval:&TimeInterval, start_time:u64, end_time:u64) -> Result<Option<Vec<KlineSummary>>> {
+        let mut all_klines = Vec::new();
+        let mut time_timestamp = start_time;
+        loop {
+            let Some(klines_arr) = self.request_klines(symbol, interval, 1500, Some(time_timestamp), end_time).await? else {
+                return Ok(None);
+            };
+            let is_full = klines_arr.len() == 1500;
+            if let Some(last) = klines_arr.last() {
+                time_timestamp = (last.open_time + 1) as u64;
+            }
+            all_klines.extend(klines_arr);
+            if !is_full {
+                break;
+            }
+        }
+        Ok(Some(all_klines))
+    }
+    // The last `limit` klines opened before end_time. With the kline stream they are served from its buffers while
+    // those hold the whole window, a window loaded over REST seeds the buffers for the next closes.
+    async fn fetch_klines(&self, symbol:&str, interval:&TimeInterval, limit:u16, end_time:u64) -> Result<Option<Vec<KlineSummary>>> {
+        let Some(kline_buffers) = &self.kline_buffers else {
+            return self.fetch_klines_stored(symbol, interval, limit, end_time).await;
+        };
+        let until = DateTime::from_timestamp_millis(end_time as i64).ok_or(anyhow!("invalid end time {}", end_time))?.naive_utc();
+        let window = kline_buffers.lock().map_err(|_| anyhow!("kline buffers poisoned"))?.window(symbol, interval, limit as usize, until);
+        if let Some(candles) = window {
+            return Ok(Some(convert_to_klines(&candles)));
+        }
+        let klines = self.fetch_klines_stored(symbol, interval, limit, end_time).await?;
+        if let Some(klines) = &klines {
+            let now = Utc::now().naive_utc();
+            let closed: Vec<Candle> = convert_to_candles(klines).into_iter().filter(|candle| candle.close_time < now).collect();
+            kline_buffers.lock().map_err(|_| anyhow!("kline buffers poisoned"))?.merge(symbol, interval, &closed, limit as usize);
+        }
+        Ok(klines)
+    }
+    // With a kline store only the bars missing from it are requested, and the closed ones among them are stored
+    // for the next run
+    async fn fetch_klines_stored(&self, symbol:&str, interval:&TimeInterval, limit:u16, end_time:u64) -> Result<Option<Vec<KlineSummary>>> {
+        let Some(kline_store) = &self.kline_store else {
+            return self.fetch_klines_backwards(symbol, interval, limit, end_time).await;
+        };
+        let until = DateTime::from_timestamp_millis(end_time as i64).ok_or(anyhow!("invalid end time {}", end_time))?.naive_utc();
+        let cached = kline_store.load_klines(symbol, interval, until, limit).await?;
+        let (mut klines, fetched) = match cache_lookup(&cached, interval, until, limit as usize) {
+            CacheLookup::Complete => (convert_to_klines(&cached), Vec::new()),
+            CacheLookup::Tail(last_open_time) => {
+                let start_time = last_open_time.and_utc().timestamp_millis() as u64 + 1;
+                let Some(tail) = self.fetch_klines_forwards(symbol, interval, start_time, end_time).await? else {
+                    return Ok(None);
+                };
+                (convert_to_klines(&cached), tail)
+            }
+            CacheLookup::Full => {
+                let Some(all_klines) = self.fetch_klines_backwards(symbol, interval, limit, end_time).await? else {
+                    return Ok(None);
+                };
+                (Vec::new(), all_klines)
+            }
+        };
+        // The bar still forming is requested again next time
+        let now = Utc::now().naive_utc();
+        let closed: Vec<Candle> = convert_to_candles(&fetched).into_iter().filter(|candle| candle.close_time < now).collect();
+        if !closed.is_empty() {
+            kline_store.save_klines(symbol, interval, &closed).await?;
+        }
+        klines.extend(fetched);
+        let excess = klines.len().saturating_sub(limit as usize);
+        klines.drain(..excess);
+        Ok(Some(klines))
+    }
+}
+// Bars the kline stream missed while it was disconnected
+#[async_trait::async_trait]
+impl KlineBackfill for BinanceExchange {
+    async fn closed_klines(&self, symbol:&str, interval:&TimeInterval, limit:u16, until:NaiveDateTime) -> Result<Vec<KlineSummary>> {
+        let end_time = until.and_utc().timestamp_millis() as u64;
+        let klines = self.fetch_klines_backwards(symbol, interval, limit, end_time).await?.unwrap_or_default();
+        let now = Utc::now().timestamp_millis();
+        Ok(klines.into_iter().filter(|kline| kline.close_time < now).collect())
+    }
+}
+#[async_trait::async_trait]
+impl ExchangeInterface for BinanceExchange {
+
+
+    async fn get_candles_for_pairs(&self,
+                                              pairs: &mut Vec<TradingPair>, config:&Config, until:NaiveDateTime
+                                              ,load_only_minute:bool
+    ) -> Result<()> {
+        load_candles_for_pairs(self, pairs, config, until, load_only_minute, self.limiter.max_concurrent_requests()).await
+    }
+    async fn get_streamed_candles(&self, symbol:&str, interval:&TimeInterval, after:NaiveDateTime) -> Result<Vec<Candle>> {
+        let Some(kline_buffers) = &self.kline_buffers else {
+            return Ok(Vec::new());
+        };
+        Ok(kline_buffers.lock().map_err(|_| anyhow!("kline buffers poisoned"))?.since(symbol, interval, after))
     }
      async fn set_time_from_server(&self) -> Result<()> {
-         let api_key=self.api_key.clone();
-         let secret_key=self.secret_key.clone();
-         task::spawn_blocking(|| -> Result<()> {
-             let general: FuturesGeneral = SyncBinance::new( Some(api_key),
-                                                             Some(secret_key));
-             match general.get_server_time() {
-                 Ok(response) => {
-                     let server_time = Utc.timestamp_millis(response.server_time as i64);
-                     tracing::info!("Time server: {}", server_time);
-                     let now = Utc::now();
-                     tracing::info!("Time now UTC: {}", now);
-                     Ok(())
-                 },
-                 Err(e) => {
-                     Err(anyhow!("Error server: {}", e))
-                 }
-             }
-         }).await??;
+         let response = self.request_blocking("server time", 1, |general| general.get_server_time()).await?;
+         let server_time = DateTime::from_timestamp_millis(response.server_time as i64).ok_or(anyhow!("invalid server time {}", response.server_time))?;
+         tracing::info!("Time server: {}", server_time);
+         let now = Utc::now();
+         tracing::info!("Time now UTC: {}", now);
          Ok(())
      }
     async fn get_uninitialized_pair(&self,last_configuration:TradingStrategy)->Result<Vec<TradingPair>>{
@@ -221,43 +450,29 @@ impl ExchangeInterface for BinanceExchange {
         //     pair
         // }).collect();
         let mut pairs: Vec<TradingPair>=Vec::new();
-        let api_key=self.api_key.clone();
-        let secret_key=self.secret_key.clone();
-        let api_pairs = task::spawn_blocking(move || -> Result<Vec<TradingPair>>  {
-            let general: FuturesGeneral = SyncBinance::new( Some(api_key),
-                                                            Some(secret_key));
-            match general.exchange_info() {
-                Ok(answer) => {
-                    let executions = vec![""];
-                    let mut api_pairs: Vec<TradingPair> = Vec::new();
-
-                    for i in 0..answer.symbols.len() {
-                        let mut add = true;
-                        for execution in executions.iter() {
-                            if answer.symbols[i].base_asset == *execution || answer.symbols[i].quote_asset != "USDT"|| answer.symbols[i].status != "TRADING" {
-                                add = false;
-                                break;
-                            }
-                        }
-                        if add == true {
-                            let mut pair=TradingPair {
-                                id: -1,
-                                name: format!("{}{}", answer.symbols[i].base_asset, answer.symbols[i].quote_asset),
-                                is_request_pair:None,
-                                pair_data_map: HashMap::new()
-                            };
-                            pair.init_pair_data(last_configuration.id);
-                            api_pairs.push(pair);
-                        }
-                    }
-
-                    Ok(api_pairs)
+        let answer = self.request_blocking("exchange info", 1, |general| general.exchange_info()).await?;
+        let executions = [""];
+        let mut api_pairs: Vec<TradingPair> = Vec::new();
+
+        for i in 0..answer.symbols.len() {
+            let mut add = true;
+            for execution in executions.iter() {
+                if answer.symbols[i].base_asset == *execution || answer.symbols[i].quote_asset != "USDT"|| answer.symbols[i].status != "TRADING" {
+                    add = false;
+                    break;
                 }
-                Err(e) => {
-                    Err(anyhow::anyhow!(e.to_string()))
-                },
             }
-        }).await??;
+            if add {
+                let mut pair=TradingPair {
+                    id: -1,
+                    name: format!("{}{}", answer.symbols[i].base_asset, answer.symbols[i].quote_asset),
+                    is_request_pair:None,
+                    pair_data_map: HashMap::new()
+                };
+                pair.init_pair_data(last_configuration.id, &last_configuration.intervals);
+                api_pairs.push(pair);
+            }
+        }
 
         pairs.extend(api_pairs);
         let pairs = remove_duplicate_full_pairs(pairs);
@@ -272,39 +487,9 @@ impl ExchangeInterface for BinanceExchange {
         candle_parameter: &TimeframeConfig,
         download_date: NaiveDateTime
     ) -> Result<Vec<KlineSummary>> {
-        let mut all_klines_minutes = Vec::new();
-        let mut remaining_limit = 1440;
-        let default_timestamp = download_date.and_utc().timestamp_millis() as u64 - 1000;
-        let mut time_timestamp = default_timestamp;
-
-        while remaining_limit > 0 {
-            let limit = remaining_limit.min(1500);
-
-            let klines = match self.async_binance.get_klines(para, "1m", limit as u16, None, time_timestamp).await {
-                Ok(k) => k,
-                Err(e) => {
-                    if let binance_async::errors::Error::BinanceError { response } = &e {
-                        if response.code == -1122 && response.msg.contains("Invalid symbol status") {
-                            return Ok(Vec::new());
-                        }
-                    }
-                    return Err(anyhow!("Помилка при отриманні хвилинних свічок: {:?}", e));
-                }
-            };
-            let klines_arr = match klines {
-                KlineSummaries::AllKlineSummaries(arr) => arr,
-                _ => return Err(anyhow!("Не вдалося розпакувати дані хвилинних свічок.")),
-            };
-
-            if klines_arr.is_empty() {
-                break;
-            }
-            time_timestamp = (klines_arr[0].open_time - 1000) as u64;
-            all_klines_minutes.splice(0..0, klines_arr);
-            remaining_limit -= limit;
-        }
-
-        Ok(all_klines_minutes)
+        let end_time = download_date.and_utc().timestamp_millis() as u64 - 1000;
+        let minutes = self.fetch_klines(para, &candle_parameter.intraday_interval, candle_parameter.intraday_limit, end_time).await?;
+        Ok(minutes.unwrap_or_default())
     }
     async fn get_candles_manual(
         &self,
@@ -314,78 +499,171 @@ impl ExchangeInterface for BinanceExchange {
         download_date: NaiveDateTime,
         include_minutes: bool
     ) -> Result<(Vec<KlineSummary>,Vec<KlineSummary>)> {
-        let total_limit = limit;
-        let mut remaining_limit = total_limit;
-        let mut all_klines_h4 = Vec::new();
+        let end_time = download_date.and_utc().timestamp_millis() as u64 - 1000;
+        let Some(all_klines_h4) = self.fetch_klines(para, &candle_parameter.interval, limit, end_time).await? else {
+            return Ok((Vec::new(), Vec::new())); // Повертаємо пустий масив
+        };
+        if all_klines_h4.len() < limit as usize {
+            return Ok((Vec::new(), Vec::new()));
+        }
+
         let mut all_klines_minutes = Vec::new();
+        if include_minutes {
+            let Some(minutes) = self.fetch_klines(para, &candle_parameter.intraday_interval, candle_parameter.intraday_limit, end_time).await? else {
+                return Ok((Vec::new(), Vec::new()));
+            };
+            all_klines_minutes = minutes;
+        }
 
-        let mut default_timestamp = download_date.and_utc().timestamp_millis() as u64 - 1000;
-        let mut time_timestamp = default_timestamp;
+        Ok((all_klines_h4, all_klines_minutes))
+    }
 
-        while remaining_limit > 0 {
-            let limit = remaining_limit.min(1500);
-            let klines =  match self.async_binance.get_klines(para, candle_parameter.name_interval.clone(), limit, None, time_timestamp).await {
-                Ok(k) => k,
-                Err(e) => {
-                    if let binance_async::errors::Error::BinanceError { response } = &e {
-                        if response.code == -1122 && response.msg.contains("Invalid symbol status") {
-                            return Ok((Vec::new(),Vec::new())); // Повертаємо пустий масив
-                        }
-                    }
-                    return Err(anyhow!("Помилка при отриманні хвилинних свічок: {:?}", e));
-                }
-            };
-            let klines_arr = match klines {
-                KlineSummaries::AllKlineSummaries(arr) => arr,
-                _ => return Err(anyhow!("Не вдалося розпакувати дані свічок (manual).")),
-            };
+}
 
-            if klines_arr.is_empty() {
-                break;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::storage::database::MockDatabaseInterface;
+
+    const KLINES: &str = r#"[[1725148800000,"57000.1","57500.0","56800.5","57200.3","1500.25",1725163199999,"85800000.5",120000,"700.1","40000000.2","0"]]"#;
+
+    // Answers the requests with `responses` in order, the last one repeats
+    async fn stand_in(responses: Vec<String>) -> Result<(String, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buffer = [0u8; 4096];
+                let _ = socket.read(&mut buffer).await;
+                let number = counter.fetch_add(1, Ordering::SeqCst);
+                let response = &responses[number.min(responses.len() - 1)];
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
             }
-            time_timestamp = (klines_arr[0].open_time - 1000) as u64;
-            all_klines_h4.splice(0..0, klines_arr);
-            remaining_limit -= limit;
-        }
-
-        if include_minutes && all_klines_h4.len() >= total_limit as usize {
-            remaining_limit = 1440;
-            time_timestamp = default_timestamp;
+        });
+        Ok((url, requests))
+    }
 
-            while remaining_limit > 0 {
-                let limit = remaining_limit.min(1500);
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, headers, body.len(), body)
+    }
 
-                let klines =match self.async_binance.get_klines(para, "1m", limit, None, time_timestamp).await {
-                    Ok(k) => k,
-                    Err(e) => {
+    fn exchange(url: String) -> Result<BinanceExchange> {
+        let mut exchange = BinanceExchange::new(String::new(), String::new())?
+            .with_rate_limits(&RateLimitConfig { default_backoff: std::time::Duration::from_millis(50), ..Default::default() })
+            .with_retry_policy(RetryPolicy { max_attempts: 4, base_delay: std::time::Duration::from_millis(10), ..Default::default() });
+        exchange.base_url = url;
+        Ok(exchange)
+    }
 
-                        if let binance_async::errors::Error::BinanceError { response } = &e {
-                            if response.code == -1122 && response.msg.contains("Invalid symbol status") {
-                                return Ok((Vec::new(),Vec::new())); // Повертаємо пустий масив
-                            }
-                        }
-                        return Err(anyhow!("Помилка при отриманні хвилинних свічок: {:?}", e));
-                    }
-                };
-                let klines_arr = match klines {
-                    KlineSummaries::AllKlineSummaries(arr) => arr,
-                    _ => return Err(anyhow!("Не вдалося розпакувати дані свічок (manual).")),
-                };
+    #[tokio::test]
+    async fn test_klines_wait_out_rate_limits() -> Result<()> {
+        let (url, requests) = stand_in(vec![
+            response("429 Too Many Requests", "Retry-After: 1\r\n", r#"{"code":-1003,"msg":"Too many requests"}"#),
+            response("418 I'm a teapot", "", r#"{"code":-1003,"msg":"Way too many requests"}"#),
+            response("200 OK", "X-MBX-USED-WEIGHT-1M: 1990\r\n", KLINES),
+        ]).await?;
+        let exchange = exchange(url)?;
+        let started = Instant::now();
+        let klines = exchange.request_klines("BTCUSDT", &TimeInterval::h4, 1, None, 1725163199999).await?.unwrap();
+        assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(klines.len(), 1);
+        assert_eq!((klines[0].open, klines[0].high, klines[0].low, klines[0].close), (57000.1, 57500.0, 56800.5, 57200.3));
+        assert_eq!((klines[0].volume, klines[0].quote_asset_volume, klines[0].number_of_trades), (1500.25, 85800000.5, 120000));
+        assert_eq!(klines[0].close_time, 1725163199999);
+        Ok(())
+    }
 
-                if klines_arr.is_empty() {
-                    break;
-                }
-                time_timestamp = (klines_arr[0].open_time - 1000) as u64;
-                all_klines_minutes.splice(0..0, klines_arr);
-                remaining_limit -= limit;
-            }
-        }
+    fn exchange_error(result: Result<Option<Vec<KlineSummary>>>) -> Option<ExchangeError> {
+        result.err()?.downcast_ref::<ExchangeError>().cloned()
+    }
 
-        if all_klines_h4.len() < total_limit as usize {
-            return Ok((Vec::new(), Vec::new()));
-        }
+    #[tokio::test]
+    async fn test_klines_errors() -> Result<()> {
+        let (url, _) = stand_in(vec![response("400 Bad Request", "", r#"{"code":-1122,"msg":"Invalid symbol status."}"#)]).await?;
+        assert!(exchange(url)?.request_klines("OLDUSDT", &TimeInterval::h4, 1, None, 1725163199999).await?.is_none());
+
+        // Not retried
+        let (url, requests) = stand_in(vec![response("400 Bad Request", "", r#"{"code":-1121,"msg":"Invalid symbol."}"#)]).await?;
+        let result = exchange(url)?.request_klines("NOPEUSDT", &TimeInterval::h4, 1, None, 1725163199999).await;
+        assert_eq!(exchange_error(result), Some(ExchangeError::InvalidSymbol("NOPEUSDT".to_string())));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let (url, _) = stand_in(vec![response("200 OK", "", r#"{"unexpected":true}"#)]).await?;
+        let result = exchange(url)?.request_klines("BTCUSDT", &TimeInterval::h4, 1, None, 1725163199999).await;
+        assert!(matches!(exchange_error(result), Some(ExchangeError::Decode(_))));
+
+        // A failing server is retried until it answers
+        let (url, requests) = stand_in(vec![response("502 Bad Gateway", "", ""), response("503 Service Unavailable", "", ""), response("200 OK", "", KLINES)]).await?;
+        assert_eq!(exchange(url)?.request_klines("BTCUSDT", &TimeInterval::h4, 1, None, 1725163199999).await?.unwrap().len(), 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // Rate limited on every attempt
+        let (url, requests) = stand_in(vec![response("429 Too Many Requests", "", "{}")]).await?;
+        let result = exchange(url)?.request_klines("BTCUSDT", &TimeInterval::h4, 1, None, 1725163199999).await;
+        assert!(matches!(exchange_error(result), Some(ExchangeError::RateLimited { .. })));
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
 
-        Ok((all_klines_h4, all_klines_minutes))
+    #[tokio::test]
+    async fn test_failing_symbol_does_not_fail_the_day() -> Result<()> {
+        const CONFIG_ID: ConfigId = 1;
+        let strategy = TradingStrategy { id: CONFIG_ID, h4: 100, ..Default::default() };
+        let mut mock_db = MockDatabaseInterface::new();
+        let configured = strategy.clone();
+        mock_db.expect_get_active_strategies().returning(move || Ok((HashMap::from([(CONFIG_ID, configured.clone())]), CONFIG_ID)));
+        let config = Config::new(&mock_db).await?;
+        let pairs = || ["BTCUSDT", "BADUSDT"].iter().map(|name| {
+            let mut pair = TradingPair::new();
+            pair.name = name.to_string();
+            pair.init_pair_data(CONFIG_ID, &strategy.intervals);
+            pair
+        }).collect::<Vec<_>>();
+        let day = NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+
+        let mut exchange = MockExchangeInterface::new();
+        exchange.expect_get_candles_manual().returning(|name, _, _, _, _| match name {
+            "BTCUSDT" => Ok((parse_klines_response(KLINES)?, Vec::new())),
+            _ => Err(ExchangeError::Transport("connection reset".to_string()).into()),
+        });
+        let mut loaded = pairs();
+        load_candles_for_pairs(&exchange, &mut loaded, &config, day, false, 2).await?;
+        assert_eq!(loaded[0].pair_data_map[&CONFIG_ID][0].candles.len(), 1);
+        assert!(loaded[1].pair_data_map[&CONFIG_ID][0].candles.is_empty());
+
+        // Nothing loaded at all is an error of the day
+        let mut exchange = MockExchangeInterface::new();
+        exchange.expect_get_candles_manual().returning(|_, _, _, _, _| Err(ExchangeError::Transport("connection refused".to_string()).into()));
+        assert!(load_candles_for_pairs(&exchange, &mut pairs(), &config, day, false, 2).await.is_err());
+        Ok(())
     }
 
+    #[tokio::test]
+    async fn test_streamed_windows_skip_rest() -> Result<()> {
+        let (url, requests) = stand_in(vec![response("200 OK", "", KLINES)]).await?;
+        let buffers = SharedKlineBuffers::default();
+        let exchange = exchange(url)?.with_kline_stream(buffers.clone());
+        let close = 1725163199999u64;
+        assert_eq!(exchange.fetch_klines("BTCUSDT", &TimeInterval::h4, 1, close - 1000).await?.unwrap().len(), 1);
+        assert_eq!(exchange.fetch_klines("BTCUSDT", &TimeInterval::h4, 1, close - 1000).await?.unwrap().len(), 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // The next bar comes from the stream
+        let mut next = convert_to_candles(&parse_klines_response(KLINES)?).remove(0);
+        next.open_time += Duration::hours(4);
+        next.close_time += Duration::hours(4);
+        next.price = 58000.0;
+        assert_eq!(buffers.lock().unwrap().push("BTCUSDT", &TimeInterval::h4, next), None);
+        let klines = exchange.fetch_klines("BTCUSDT", &TimeInterval::h4, 1, close + 4 * 3_600_000 - 1000).await?.unwrap();
+        assert_eq!((klines[0].open_time, klines[0].close), (1725148800000 + 4 * 3_600_000, 58000.0));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
 }
@@ -0,0 +1,43 @@
+use std::fmt;
+use std::time::Duration;
+
+// Failures of the exchange requests. RateLimited and Transport are worth another attempt, the rest
+// are answers about the request itself and do not change on a retry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExchangeError {
+    // 429/418, requests are paused for retry_after
+    RateLimited { retry_after: Duration },
+    // -1121, the symbol does not exist
+    InvalidSymbol(String),
+    // -1122 Invalid symbol status, the symbol exists but is not trading
+    Delisted(String),
+    // Connection, timeout or a 5xx answer
+    Transport(String),
+    // An answer that does not parse
+    Decode(String),
+    // Any other error answer of the API
+    Rejected { status: u16, code: i64, msg: String },
+}
+impl ExchangeError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ExchangeError::RateLimited { .. } | ExchangeError::Transport(_))
+    }
+}
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExchangeError::RateLimited { retry_after } => write!(f, "rate limited, retry after {:?}", retry_after),
+            ExchangeError::InvalidSymbol(symbol) => write!(f, "invalid symbol {}", symbol),
+            ExchangeError::Delisted(symbol) => write!(f, "symbol {} is not trading", symbol),
+            ExchangeError::Transport(message) => write!(f, "transport error: {}", message),
+            ExchangeError::Decode(message) => write!(f, "decode error: {}", message),
+            ExchangeError::Rejected { status, code, msg } => write!(f, "rejected with {} ({}): {}", status, code, msg),
+        }
+    }
+}
+impl std::error::Error for ExchangeError {}
+impl From<reqwest::Error> for ExchangeError {
+    fn from(e: reqwest::Error) -> Self {
+        ExchangeError::Transport(e.to_string())
+    }
+}
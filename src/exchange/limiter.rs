@@ -0,0 +1,190 @@
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+use tracing::info;
+
+// Request weight budget shared by every REST call of the exchange. Binance counts weight per IP and
+// minute; going over it answers 429 and, if the client keeps going, 418 with an IP ban.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    // Below the 2400 of the futures API, the rest is left to whatever else shares the IP
+    pub weight_per_minute: u32,
+    // Requests in flight at the same time
+    pub max_concurrent_requests: usize,
+    // Pause after a 429/418 that carries no Retry-After
+    pub default_backoff: Duration,
+}
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig { weight_per_minute: 2000, max_concurrent_requests: 4, default_backoff: Duration::from_secs(60) }
+    }
+}
+impl RateLimitConfig {
+    // BINANCE_WEIGHT_PER_MINUTE and BINANCE_MAX_CONCURRENT_REQUESTS, unset or invalid values keep the defaults
+    pub fn from_env() -> Self {
+        let mut config = RateLimitConfig::default();
+        if let Some(weight) = env::var("BINANCE_WEIGHT_PER_MINUTE").ok().and_then(|weight| weight.parse().ok()).filter(|&weight| weight > 0) {
+            config.weight_per_minute = weight;
+        }
+        if let Some(requests) = env::var("BINANCE_MAX_CONCURRENT_REQUESTS").ok().and_then(|requests| requests.parse().ok()).filter(|&requests| requests > 0) {
+            config.max_concurrent_requests = requests;
+        }
+        config
+    }
+}
+
+// Weight of GET /fapi/v1/klines by the requested limit
+pub fn klines_weight(limit: u16) -> u32 {
+    match limit {
+        0..=99 => 1,
+        100..=499 => 2,
+        500..=1000 => 5,
+        _ => 10,
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+    // Set by a 429/418, nothing is sent before it
+    blocked_until: Option<Instant>,
+}
+impl Bucket {
+    fn new(capacity: u32, now: Instant) -> Self {
+        Bucket { capacity: capacity as f64, tokens: capacity as f64, updated: now, blocked_until: None }
+    }
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated = now;
+    }
+    // Takes the weight, or tells how long to wait before asking again
+    fn take(&mut self, weight: u32, now: Instant) -> Option<Duration> {
+        if let Some(blocked_until) = self.blocked_until {
+            if now < blocked_until {
+                return Some(blocked_until - now);
+            }
+            self.blocked_until = None;
+        }
+        self.refill(now);
+        // A request heavier than the whole budget waits for a full bucket
+        let weight = (weight as f64).min(self.capacity);
+        if self.tokens >= weight {
+            self.tokens -= weight;
+            return None;
+        }
+        Some(Duration::from_secs_f64((weight - self.tokens) * 60.0 / self.capacity))
+    }
+    // The weight Binance reports as used in the current minute, which also counts other clients on the IP
+    fn observe_used_weight(&mut self, used: u32, now: Instant) {
+        self.refill(now);
+        self.tokens = self.tokens.min((self.capacity - used as f64).max(0.0));
+    }
+    fn block(&mut self, until: Instant) {
+        self.tokens = 0.0;
+        self.blocked_until = Some(self.blocked_until.map_or(until, |blocked_until| blocked_until.max(until)));
+    }
+}
+
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+    requests: Semaphore,
+    max_concurrent_requests: usize,
+    default_backoff: Duration,
+}
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        RateLimiter {
+            bucket: Mutex::new(Bucket::new(config.weight_per_minute.max(1), Instant::now())),
+            requests: Semaphore::new(config.max_concurrent_requests.max(1)),
+            max_concurrent_requests: config.max_concurrent_requests.max(1),
+            default_backoff: config.default_backoff,
+        }
+    }
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests
+    }
+    // Waits for a free request slot and for the weight, the request is sent while the permit is held
+    pub async fn acquire(&self, weight: u32) -> Result<SemaphorePermit<'_>> {
+        let permit = self.requests.acquire().await?;
+        loop {
+            let wait = self.bucket.lock().map_err(|_| anyhow!("rate limiter poisoned"))?.take(weight, Instant::now());
+            match wait {
+                None => return Ok(permit),
+                Some(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+    // X-MBX-USED-WEIGHT-1M of the futures API, X-MBX-USED-WEIGHT of the older endpoints
+    pub fn observe_headers(&self, headers: &HeaderMap) {
+        let used = ["x-mbx-used-weight-1m", "x-mbx-used-weight"].iter()
+            .find_map(|name| headers.get(*name)?.to_str().ok()?.parse::<u32>().ok());
+        if let (Some(used), Ok(mut bucket)) = (used, self.bucket.lock()) {
+            bucket.observe_used_weight(used, Instant::now());
+        }
+    }
+    // After a 429/418 every request waits for Retry-After, or default_backoff without it. Returns the wait.
+    pub fn back_off(&self, headers: &HeaderMap) -> Duration {
+        let wait = headers.get(RETRY_AFTER).and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .map(Duration::from_secs).unwrap_or(self.default_backoff);
+        if let Ok(mut bucket) = self.bucket.lock() {
+            bucket.block(Instant::now() + wait);
+        }
+        info!("rate limited, requests paused for {:?}", wait);
+        wait
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_bucket_refills_by_weight() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(60, start);
+        assert_eq!(bucket.take(50, start), None);
+        // One weight per second comes back
+        assert_eq!(bucket.take(20, start), Some(Duration::from_secs(10)));
+        assert_eq!(bucket.take(20, start + Duration::from_secs(10)), None);
+        // A request heavier than the budget waits for the full bucket instead of forever
+        assert_eq!(bucket.take(100, start + Duration::from_secs(10)), Some(Duration::from_secs(60)));
+
+        // Binance counts 55 of 60 used, more than the bucket knows about
+        let mut bucket = Bucket::new(60, start);
+        bucket.observe_used_weight(55, start);
+        assert_eq!(bucket.take(10, start), Some(Duration::from_secs(5)));
+
+        bucket.block(start + Duration::from_secs(30));
+        assert_eq!(bucket.take(1, start + Duration::from_secs(10)), Some(Duration::from_secs(20)));
+        assert_eq!(bucket.take(1, start + Duration::from_secs(31)), None);
+    }
+
+    #[tokio::test]
+    async fn test_limiter_reads_headers() -> Result<()> {
+        let limiter = RateLimiter::new(&RateLimitConfig { weight_per_minute: 600, max_concurrent_requests: 2, default_backoff: Duration::from_secs(7) });
+        let _first = limiter.acquire(klines_weight(1500)).await?;
+        assert_eq!((limiter.max_concurrent_requests(), limiter.requests.available_permits()), (2, 1));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mbx-used-weight-1m", HeaderValue::from_static("595"));
+        limiter.observe_headers(&headers);
+        let tokens = limiter.bucket.lock().unwrap().tokens;
+        assert!(tokens <= 5.0 + 1e-6, "{}", tokens);
+
+        assert_eq!(limiter.back_off(&headers), Duration::from_secs(7));
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(limiter.back_off(&headers), Duration::from_secs(120));
+        let blocked_until = limiter.bucket.lock().unwrap().blocked_until.unwrap();
+        assert!(blocked_until - Instant::now() > Duration::from_secs(119));
+        Ok(())
+    }
+}
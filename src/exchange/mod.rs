@@ -1 +1,6 @@
-pub mod binance;
\ No newline at end of file
+pub mod binance;
+pub mod error;
+pub mod limiter;
+pub mod replay;
+pub mod retry;
+pub mod stream;
@@ -0,0 +1,343 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Result};
+use binance_async::rest_model::KlineSummary;
+use chrono::NaiveDateTime;
+use tokio::task;
+use tracing::info;
+use crate::core::config::Config;
+use crate::core::types::{Candle, TimeInterval, TimeframeConfig, TradingPair, TradingStrategy};
+use crate::exchange::binance::{load_candles_for_pairs, ExchangeInterface};
+
+// Exchange served from a directory of Binance Vision kline archives (data.binance.vision layout or flat),
+// named `SYMBOL-INTERVAL-YYYY-MM.zip` for the monthly and `SYMBOL-INTERVAL-YYYY-MM-DD.zip` for the daily
+// files, unpacked `.csv` files work as well. Only candles closed before the download date are served,
+// so a replayed day sees exactly what Binance had at that moment. The collector trades USD-M futures:
+// archives of another market are left out, a flat directory is taken as USD-M futures.
+
+// Market directory of the data.binance.vision layout
+pub(crate) const FUTURES_UM: &str = "futures/um";
+
+type SeriesKey = (String, TimeInterval);
+
+pub struct FileReplayExchange {
+    files: HashMap<SeriesKey, Vec<PathBuf>>,
+    // Parsed series, sorted by open_time and deduplicated across overlapping monthly and daily files
+    series: Mutex<HashMap<SeriesKey, Arc<Vec<KlineSummary>>>>,
+}
+
+impl FileReplayExchange {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        let mut files: HashMap<SeriesKey, Vec<PathBuf>> = HashMap::new();
+        for path in archive_files(&root)? {
+            // Spot and futures archives share their file names, their bars must not fill each other's gaps
+            if archive_market(path.strip_prefix(&root).unwrap_or(&path)).is_some_and(|market| market != FUTURES_UM) {
+                continue;
+            }
+            if let Some(key) = series_key(&path) {
+                files.entry(key).or_default().push(path);
+            }
+        }
+        if files.is_empty() {
+            return Err(anyhow!("no kline archives in {}", root.display()));
+        }
+        info!("replay: {} series in {}", files.len(), root.display());
+        Ok(FileReplayExchange { files, series: Mutex::new(HashMap::new()) })
+    }
+    pub fn symbols(&self) -> Vec<String> {
+        self.files.keys().map(|(symbol, _)| symbol.clone()).collect::<BTreeSet<_>>().into_iter().collect()
+    }
+    async fn series(&self, symbol: &str, interval: &TimeInterval) -> Result<Arc<Vec<KlineSummary>>> {
+        let key = (symbol.to_string(), interval.clone());
+        if let Some(series) = self.series.lock().map_err(|_| anyhow!("replay cache poisoned"))?.get(&key) {
+            return Ok(series.clone());
+        }
+        let paths = self.files.get(&key).cloned().unwrap_or_default();
+        let series = Arc::new(task::spawn_blocking(move || read_series(&paths)).await??);
+        self.series.lock().map_err(|_| anyhow!("replay cache poisoned"))?.insert(key, series.clone());
+        Ok(series)
+    }
+    // The last `limit` klines closed before download_date
+    async fn klines(&self, symbol: &str, interval: &TimeInterval, limit: u16, download_date: NaiveDateTime) -> Result<Vec<KlineSummary>> {
+        let series = self.series(symbol, interval).await?;
+        Ok(closed_window(&series, limit as usize, download_date))
+    }
+}
+
+fn closed_window(series: &[KlineSummary], limit: usize, download_date: NaiveDateTime) -> Vec<KlineSummary> {
+    let cut_off = download_date.and_utc().timestamp_millis();
+    let end = series.partition_point(|kline| kline.close_time < cut_off);
+    series[end.saturating_sub(limit)..end].to_vec()
+}
+
+pub(crate) fn archive_files(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory).map_err(|e| anyhow!("read {}: {}", directory.display(), e))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if matches!(path.extension().and_then(|extension| extension.to_str()), Some("zip") | Some("csv")) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// The market directory `relative` lies in, e.g. `spot` or `futures/cm`, None in a flat directory
+pub(crate) fn archive_market(relative: &Path) -> Option<String> {
+    let parts: Vec<&str> = relative.components().filter_map(|component| component.as_os_str().to_str()).collect();
+    let position = parts.iter().position(|part| matches!(*part, "spot" | "futures" | "option"))?;
+    match parts[position] {
+        "futures" => Some(format!("futures/{}", parts.get(position + 1).copied().unwrap_or_default())),
+        market => Some(market.to_string()),
+    }
+}
+
+// `BTCUSDT-4h-2024-09.zip` -> (BTCUSDT, h4), None for names or intervals that are not modelled
+pub(crate) fn series_key(path: &Path) -> Option<SeriesKey> {
+    let stem = path.file_stem()?.to_str()?;
+    let mut parts = stem.splitn(3, '-');
+    let symbol = parts.next()?;
+    let interval = TimeInterval::from_binance_name(parts.next()?)?;
+    parts.next()?;
+    Some((symbol.to_string(), interval))
+}
+
+pub(crate) fn read_archive(path: &Path) -> Result<Vec<KlineSummary>> {
+    let mut klines = Vec::new();
+    if path.extension().and_then(|extension| extension.to_str()) == Some("csv") {
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+        parse_klines(&content, &mut klines).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        return Ok(klines);
+    }
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if !file.name().ends_with(".csv") {
+            continue;
+        }
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        parse_klines(&content, &mut klines).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    }
+    Ok(klines)
+}
+
+fn read_series(paths: &[PathBuf]) -> Result<Vec<KlineSummary>> {
+    let mut series = Vec::new();
+    for path in paths {
+        series.extend(read_archive(path)?);
+    }
+    series.sort_by_key(|kline| kline.open_time);
+    series.dedup_by_key(|kline| kline.open_time);
+    Ok(series)
+}
+
+// Rows of a klines CSV: open_time, open, high, low, close, volume, close_time, quote_volume, count,
+// taker_buy_volume, taker_buy_quote_volume, ignore. The header row of the newer files is skipped, and
+// timestamps in microseconds (spot files from 2025) are brought to milliseconds.
+pub(crate) fn parse_klines(content: &str, klines: &mut Vec<KlineSummary>) -> Result<()> {
+    for (number, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields.len() < 11 || fields[0].parse::<i64>().is_err() {
+            continue;
+        }
+        let parse_f64 = |index: usize| fields[index].parse::<f64>().map_err(|e| anyhow!("line {}: {}", number + 1, e));
+        let parse_i64 = |index: usize| fields[index].parse::<i64>().map_err(|e| anyhow!("line {}: {}", number + 1, e));
+        let to_millis = |time: i64| if time > 100_000_000_000_000 { time / 1000 } else { time };
+        klines.push(KlineSummary {
+            open_time: to_millis(parse_i64(0)?),
+            open: parse_f64(1)?,
+            high: parse_f64(2)?,
+            low: parse_f64(3)?,
+            close: parse_f64(4)?,
+            volume: parse_f64(5)?,
+            close_time: to_millis(parse_i64(6)?),
+            quote_asset_volume: parse_f64(7)?,
+            number_of_trades: parse_i64(8)?,
+            taker_buy_base_asset_volume: parse_f64(9)?,
+            taker_buy_quote_asset_volume: parse_f64(10)?,
+        });
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl ExchangeInterface for FileReplayExchange {
+    async fn get_candles_for_pairs(&self, pairs: &mut Vec<TradingPair>, config: &Config, until: NaiveDateTime, load_only_minute: bool) -> Result<()> {
+        load_candles_for_pairs(self, pairs, config, until, load_only_minute, 1).await
+    }
+    async fn set_time_from_server(&self) -> Result<()> {
+        info!("replay, no server time");
+        Ok(())
+    }
+    // Every USDT symbol with archives, in the role of the trading symbols of exchange_info
+    async fn get_uninitialized_pair(&self, last_configuration: TradingStrategy) -> Result<Vec<TradingPair>> {
+        Ok(self.symbols().into_iter().filter(|symbol| symbol.ends_with("USDT")).map(|symbol| {
+            let mut pair = TradingPair::new();
+            pair.name = symbol;
+            pair.init_pair_data(last_configuration.id, &last_configuration.intervals);
+            pair
+        }).collect())
+    }
+    async fn get_minutes_only(&self, para: &str, candle_parameter: &TimeframeConfig, download_date: NaiveDateTime) -> Result<Vec<KlineSummary>> {
+        self.klines(para, &candle_parameter.intraday_interval, candle_parameter.intraday_limit, download_date).await
+    }
+    async fn get_candles_manual(&self, para: &str, limit: u16, candle_parameter: &TimeframeConfig, download_date: NaiveDateTime, include_minutes: bool) -> Result<(Vec<KlineSummary>, Vec<KlineSummary>)> {
+        let bars = self.klines(para, &candle_parameter.interval, limit, download_date).await?;
+        if bars.len() < limit as usize {
+            return Ok((Vec::new(), Vec::new()));
+        }
+        let minutes = if include_minutes { self.get_minutes_only(para, candle_parameter, download_date).await? } else { Vec::new() };
+        Ok((bars, minutes))
+    }
+    // A replay has no live stream
+    async fn get_streamed_candles(&self, _symbol: &str, _interval: &TimeInterval, _after: NaiveDateTime) -> Result<Vec<Candle>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDate};
+    use crate::core::types::ConfigId;
+    use crate::storage::database::MockDatabaseInterface;
+    use crate::test_util::{september_1 as start, write_zip};
+
+    const CONFIG_ID: ConfigId = 1;
+
+    // Klines CSV of `count` candles from `first_open`, the close price is the candle number
+    fn csv(first_open: NaiveDateTime, interval: &TimeInterval, count: i64, header: bool) -> String {
+        let step = interval.minutes() * 60_000;
+        let mut content = String::new();
+        if header {
+            content.push_str("open_time,open,high,low,close,volume,close_time,quote_volume,count,taker_buy_volume,taker_buy_quote_volume,ignore\n");
+        }
+        for k in 0..count {
+            let open_time = first_open.and_utc().timestamp_millis() + k * step;
+            content.push_str(&format!("{},{}.5,{}.9,{}.1,{},10.0,{},100.0,7,4.0,40.0,0\n", open_time, k, k, k, k, open_time + step - 1));
+        }
+        content
+    }
+
+    // Two months of H4 and one day of M1 for BTCUSDT, in the data.binance.vision layout. The daily
+    // H4 file of September 1 overlaps the monthly one, the spot archives must stay out.
+    fn archives(name: &str) -> Result<PathBuf> {
+        let root = std::env::temp_dir().join(format!("replay-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let monthly = root.join("data/futures/um/monthly/klines/BTCUSDT/4h");
+        let daily = root.join("data/futures/um/daily/klines/BTCUSDT");
+        std::fs::create_dir_all(&monthly)?;
+        std::fs::create_dir_all(daily.join("4h"))?;
+        std::fs::create_dir_all(daily.join("1m"))?;
+        let august = NaiveDate::from_ymd_opt(2024, 8, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        write_zip(&monthly.join("BTCUSDT-4h-2024-08.zip"), &csv(august, &TimeInterval::h4, 31 * 6, false))?;
+        write_zip(&monthly.join("BTCUSDT-4h-2024-09.zip"), &csv(start(), &TimeInterval::h4, 30 * 6, true))?;
+        write_zip(&daily.join("4h/BTCUSDT-4h-2024-09-01.zip"), &csv(start(), &TimeInterval::h4, 6, true))?;
+        std::fs::write(daily.join("1m/BTCUSDT-1m-2024-09-10.csv"), csv(start() + Duration::days(9), &TimeInterval::m1, 1440, true))?;
+        std::fs::write(daily.join("1m/BTCUSDT-1m-2024-09-10.zip.CHECKSUM"), "ignored")?;
+        let spot = root.join("data/spot/daily/klines");
+        std::fs::create_dir_all(spot.join("BTCUSDT/4h"))?;
+        std::fs::create_dir_all(spot.join("ETHUSDT/4h"))?;
+        write_zip(&spot.join("BTCUSDT/4h/BTCUSDT-4h-2024-10-01.zip"), &csv(start() + Duration::days(30), &TimeInterval::h4, 6, true))?;
+        write_zip(&spot.join("ETHUSDT/4h/ETHUSDT-4h-2024-09-01.zip"), &csv(start(), &TimeInterval::h4, 6, true))?;
+        Ok(root)
+    }
+
+    fn timeframe(limit: u16) -> TimeframeConfig {
+        TimeframeConfig { interval: TimeInterval::h4, limit, intraday_interval: TimeInterval::m1, intraday_limit: 120 }
+    }
+
+    #[test]
+    fn test_parse_klines() -> Result<()> {
+        let mut klines = Vec::new();
+        parse_klines(&csv(start(), &TimeInterval::h4, 3, true), &mut klines)?;
+        // Spot archives from 2025 carry microseconds
+        parse_klines("1735689600000000,1.0,2.0,0.5,1.5,3.0,1735703999999999,4.5,9,1.0,1.5,0", &mut klines)?;
+        assert_eq!(klines.len(), 4);
+        assert_eq!((klines[1].open, klines[1].high, klines[1].low, klines[1].close), (1.5, 1.9, 1.1, 1.0));
+        assert_eq!((klines[1].volume, klines[1].quote_asset_volume, klines[1].number_of_trades), (10.0, 100.0, 7));
+        assert_eq!(klines[1].close_time - klines[1].open_time, 4 * 3_600_000 - 1);
+        assert_eq!((klines[3].open_time, klines[3].close_time), (1735689600000, 1735703999999));
+        assert!(parse_klines("1725148800000,x,1,1,1,1,1725163199999,1,1,1,1,0", &mut klines).is_err());
+
+        assert_eq!(series_key(Path::new("a/BTCUSDT-1m-2024-09-10.zip")), Some(("BTCUSDT".to_string(), TimeInterval::m1)));
+        assert_eq!(series_key(Path::new("BTCUSDT-1M-2024-09.zip")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_market() {
+        assert_eq!(archive_market(Path::new("data/futures/um/monthly/klines/BTCUSDT/1m/BTCUSDT-1m-2024-09.zip")).as_deref(), Some(FUTURES_UM));
+        assert_eq!(archive_market(Path::new("data/futures/cm/daily/klines/BTCUSD_PERP/1m/BTCUSD_PERP-1m-2024-09-01.zip")).as_deref(), Some("futures/cm"));
+        assert_eq!(archive_market(Path::new("data/spot/monthly/klines/BTCUSDT/1m/BTCUSDT-1m-2024-09.zip")).as_deref(), Some("spot"));
+        assert_eq!(archive_market(Path::new("BTCUSDT-1m-2024-09.zip")), None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_honors_download_date() -> Result<()> {
+        let root = archives("download-date")?;
+        let exchange = FileReplayExchange::new(&root)?;
+        assert_eq!(exchange.symbols(), vec!["BTCUSDT".to_string()]);
+
+        // The collector asks at midnight after the simulated day
+        let download_date = start() + Duration::days(10);
+        let (bars, minutes) = exchange.get_candles_manual("BTCUSDT", 100, &timeframe(100), download_date, true).await?;
+        assert_eq!(bars.len(), 100);
+        assert!(bars.windows(2).all(|pair| pair[1].open_time - pair[0].open_time == 4 * 3_600_000));
+        assert_eq!(bars.last().unwrap().close_time, download_date.and_utc().timestamp_millis() - 1);
+        // The window crosses from the August archive into September and the daily file adds no duplicates
+        assert_eq!(bars.first().unwrap().open_time, (download_date - Duration::hours(400)).and_utc().timestamp_millis());
+        assert_eq!(minutes.len(), 120);
+        assert_eq!(minutes.last().unwrap().close_time, download_date.and_utc().timestamp_millis() - 1);
+
+        // Half a bar before midnight the last bar has not closed yet
+        let (bars, _) = exchange.get_candles_manual("BTCUSDT", 100, &timeframe(100), download_date - Duration::hours(2), false).await?;
+        assert_eq!(bars.last().unwrap().close_time, (download_date - Duration::hours(4)).and_utc().timestamp_millis() - 1);
+        // Not enough history, and a symbol without archives
+        assert!(exchange.get_candles_manual("BTCUSDT", 1000, &timeframe(1000), download_date, false).await?.0.is_empty());
+        assert!(exchange.get_candles_manual("ETHUSDT", 100, &timeframe(100), download_date, false).await?.0.is_empty());
+        // The futures archives end with September, the spot bars of October are not served
+        let (bars, _) = exchange.get_candles_manual("BTCUSDT", 100, &timeframe(100), start() + Duration::days(31), false).await?;
+        assert_eq!(bars.last().unwrap().open_time, (start() + Duration::days(30) - Duration::hours(4)).and_utc().timestamp_millis());
+
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_fills_pairs() -> Result<()> {
+        let root = archives("fill-pairs")?;
+        let exchange = FileReplayExchange::new(&root)?;
+        let mut strategy = TradingStrategy { id: CONFIG_ID, h4: 100, ..Default::default() };
+        strategy.intraday_window_minutes = 60;
+        let mut mock_db = MockDatabaseInterface::new();
+        let configured = strategy.clone();
+        mock_db.expect_get_active_strategies().returning(move || Ok((HashMap::from([(CONFIG_ID, configured.clone())]), CONFIG_ID)));
+        let config = Config::new(&mock_db).await?;
+
+        let mut pairs = exchange.get_uninitialized_pair(strategy).await?;
+        assert_eq!(pairs.len(), 1);
+        // The collector of the simulated September 10 asks for the candles closed before its end
+        exchange.get_candles_for_pairs(&mut pairs, &config, start() + Duration::days(10), false).await?;
+        exchange.get_candles_for_pairs(&mut pairs, &config, start() + Duration::days(10), true).await?;
+
+        let pair_data = pairs[0].get_timeframe_data(CONFIG_ID, &TimeInterval::h4).unwrap();
+        assert_eq!((pair_data.candles.len(), pair_data.candles_minutes.len()), (100, 60));
+        assert_eq!(pair_data.candles.last().unwrap().open_time, start() + Duration::days(10) - Duration::hours(4));
+        assert_eq!(pair_data.candles_minutes.last().unwrap().price, 1439.0);
+
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tracing::info;
+use crate::exchange::error::ExchangeError;
+
+// Retries the transient exchange errors with a jittered exponential backoff. The waits after a 429/418
+// come from the rate limiter, the backoff only spreads the retries of the parallel requests.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    // Attempts including the first one, 1 turns retries off
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 5, base_delay: Duration::from_millis(500), max_delay: Duration::from_secs(30) }
+    }
+}
+impl RetryPolicy {
+    // BINANCE_RETRY_ATTEMPTS and BINANCE_RETRY_BASE_DELAY_MS, unset or invalid values keep the defaults
+    pub fn from_env() -> Self {
+        let mut policy = RetryPolicy::default();
+        if let Some(attempts) = env::var("BINANCE_RETRY_ATTEMPTS").ok().and_then(|attempts| attempts.parse().ok()).filter(|&attempts| attempts > 0) {
+            policy.max_attempts = attempts;
+        }
+        if let Some(delay) = env::var("BINANCE_RETRY_BASE_DELAY_MS").ok().and_then(|delay| delay.parse().ok()) {
+            policy.base_delay = Duration::from_millis(delay);
+        }
+        policy
+    }
+    // Between a half and the whole of base_delay * 2^attempt, capped at max_delay. `jitter` is in [0, 1).
+    pub fn delay(&self, attempt: u32, jitter: f64) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        exponential / 2 + exponential.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+    }
+    pub async fn run<T, F, Fut>(&self, what: &str, mut operation: F) -> Result<T, ExchangeError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ExchangeError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Err(e) if e.is_transient() && attempt + 1 < self.max_attempts => {
+                    let delay = self.delay(attempt, jitter());
+                    attempt += 1;
+                    info!("{} failed: {}, attempt {} of {} in {:?}", what, e, attempt + 1, self.max_attempts, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Uniform in [0, 1), RandomState is seeded randomly for each instance
+pub(crate) fn jitter() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_delay_is_jittered_and_capped() {
+        let policy = RetryPolicy { max_attempts: 5, base_delay: Duration::from_millis(100), max_delay: Duration::from_secs(1) };
+        assert_eq!(policy.delay(0, 0.0), Duration::from_millis(50));
+        assert_eq!(policy.delay(0, 0.5), Duration::from_millis(75));
+        assert_eq!(policy.delay(3, 0.0), Duration::from_millis(400));
+        assert_eq!(policy.delay(20, 0.999), policy.delay(4, 0.999));
+        assert!(policy.delay(20, 0.999) < Duration::from_secs(1));
+        assert!((0..100).map(|_| jitter()).all(|jitter| (0.0..1.0).contains(&jitter)));
+    }
+
+    #[tokio::test]
+    async fn test_only_transient_errors_are_retried() {
+        let policy = RetryPolicy { max_attempts: 3, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(5) };
+        let attempts = AtomicU32::new(0);
+        let result = policy.run("klines", || async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(ExchangeError::Transport("connection reset".to_string())),
+                1 => Err(ExchangeError::RateLimited { retry_after: Duration::ZERO }),
+                _ => Ok(7),
+            }
+        }).await;
+        assert_eq!((result, attempts.load(Ordering::SeqCst)), (Ok(7), 3));
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = policy.run("klines", || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(ExchangeError::Transport("timeout".to_string()))
+        }).await;
+        assert_eq!((result, attempts.load(Ordering::SeqCst)), (Err(ExchangeError::Transport("timeout".to_string())), 3));
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = policy.run("klines", || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(ExchangeError::Delisted("OLDUSDT".to_string()))
+        }).await;
+        assert_eq!((result, attempts.load(Ordering::SeqCst)), (Err(ExchangeError::Delisted("OLDUSDT".to_string())), 1));
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use anyhow::{anyhow, Result};
+use binance_async::rest_model::KlineSummary;
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use mockall::automock;
+use serde::Deserialize;
+use serde_json::json;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, info};
+use crate::core::types::{Candle, TimeInterval};
+use crate::exchange::retry::{jitter, RetryPolicy};
+use crate::logic::convert_to_candles;
+
+// Closed klines of the live mode from the Binance futures combined stream. The buffers keep the last bars of
+// every symbol and interval the exchange has loaded once over REST, later requests are served from them.
+// Between two closes the intraday bars that arrived are fed to the analysis of the close, see AssetAnalyzer::push_minutes.
+
+pub const BINANCE_FUTURES_STREAM_URL: &str = "wss://fstream.binance.com/stream";
+// Streams per SUBSCRIBE message, Binance accepts 10 messages a second on a connection
+const STREAMS_PER_SUBSCRIBE: usize = 100;
+
+type SeriesKey = (String, TimeInterval);
+pub type SharedKlineBuffers = Arc<Mutex<KlineBuffers>>;
+
+#[automock]
+#[async_trait::async_trait]
+pub trait KlineBackfill {
+    // Up to `limit` closed klines opened at or before `until`, oldest first
+    async fn closed_klines(&self, symbol: &str, interval: &TimeInterval, limit: u16, until: NaiveDateTime) -> Result<Vec<KlineSummary>>;
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    candles: VecDeque<Candle>,
+    capacity: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct KlineBuffers {
+    series: HashMap<SeriesKey, Series>,
+}
+impl KlineBuffers {
+    // Adds closed candles loaded over REST, the buffer keeps at least the last `capacity` of them
+    pub fn merge(&mut self, symbol: &str, interval: &TimeInterval, candles: &[Candle], capacity: usize) {
+        let series = self.series.entry((symbol.to_string(), interval.clone())).or_default();
+        series.capacity = series.capacity.max(capacity);
+        let mut merged: Vec<Candle> = series.candles.drain(..).chain(candles.iter().cloned()).collect();
+        merged.sort_by_key(|candle| candle.open_time);
+        merged.dedup_by_key(|candle| candle.open_time);
+        let excess = merged.len().saturating_sub(series.capacity);
+        series.candles = merged.into_iter().skip(excess).collect();
+    }
+    // A closed candle from the stream, ignored for series nobody loaded. Returns the open_time of the last
+    // buffered candle when bars are missing in front of it.
+    pub fn push(&mut self, symbol: &str, interval: &TimeInterval, candle: Candle) -> Option<NaiveDateTime> {
+        let series = self.series.get_mut(&(symbol.to_string(), interval.clone()))?;
+        let mut gap = None;
+        if let Some(last) = series.candles.back_mut() {
+            if candle.open_time == last.open_time {
+                *last = candle;
+                return None;
+            }
+            if candle.open_time < last.open_time {
+                return None;
+            }
+            if candle.open_time > last.open_time + Duration::minutes(interval.minutes()) {
+                gap = Some(last.open_time);
+            }
+        }
+        series.candles.push_back(candle);
+        while series.candles.len() > series.capacity {
+            series.candles.pop_front();
+        }
+        gap
+    }
+    pub fn last_open_time(&self, symbol: &str, interval: &TimeInterval) -> Option<NaiveDateTime> {
+        Some(self.series.get(&(symbol.to_string(), interval.clone()))?.candles.back()?.open_time)
+    }
+    // The last `limit` candles opened at or before `until`, None unless the buffer holds all of them without
+    // gaps up to the bar opened last before `until`
+    pub fn window(&self, symbol: &str, interval: &TimeInterval, limit: usize, until: NaiveDateTime) -> Option<Vec<Candle>> {
+        let series = self.series.get(&(symbol.to_string(), interval.clone()))?;
+        let end = series.candles.partition_point(|candle| candle.open_time <= until);
+        if limit == 0 || end < limit || series.candles[end - 1].open_time != interval.last_close(until) {
+            return None;
+        }
+        let window: Vec<Candle> = series.candles.range(end - limit..end).cloned().collect();
+        let span = (window[limit - 1].open_time - window[0].open_time).num_minutes();
+        (span == (limit as i64 - 1) * interval.minutes()).then_some(window)
+    }
+    // The candles opened after `after`, up to the first bar the buffer is missing
+    pub fn since(&self, symbol: &str, interval: &TimeInterval, after: NaiveDateTime) -> Vec<Candle> {
+        let Some(series) = self.series.get(&(symbol.to_string(), interval.clone())) else {
+            return Vec::new();
+        };
+        let start = series.candles.partition_point(|candle| candle.open_time <= after);
+        let step = Duration::minutes(interval.minutes());
+        let mut expected = after + step;
+        series.candles.range(start..).take_while(|candle| {
+            let next = candle.open_time == expected;
+            expected += step;
+            next
+        }).cloned().collect()
+    }
+    fn keys(&self) -> Vec<SeriesKey> {
+        self.series.keys().cloned().collect()
+    }
+}
+
+fn stream_name(symbol: &str, interval: &TimeInterval) -> String {
+    format!("{}@kline_{}", symbol.to_lowercase(), interval.binance_name())
+}
+
+#[derive(Deserialize)]
+struct StreamEnvelope {
+    data: Option<StreamEvent>,
+}
+#[derive(Deserialize)]
+struct StreamEvent {
+    #[serde(rename = "e")]
+    event: String,
+    #[serde(rename = "k")]
+    kline: Option<StreamKline>,
+}
+#[derive(Deserialize)]
+struct StreamKline {
+    #[serde(rename = "t")]
+    open_time: i64,
+    #[serde(rename = "T")]
+    close_time: i64,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "i")]
+    interval: String,
+    #[serde(rename = "o")]
+    open: String,
+    #[serde(rename = "h")]
+    high: String,
+    #[serde(rename = "l")]
+    low: String,
+    #[serde(rename = "c")]
+    close: String,
+    #[serde(rename = "v")]
+    volume: String,
+    #[serde(rename = "q")]
+    quote_volume: String,
+    #[serde(rename = "n")]
+    trades: i64,
+    #[serde(rename = "x")]
+    is_closed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClosedKline {
+    pub symbol: String,
+    pub interval: TimeInterval,
+    pub candle: Candle,
+}
+
+// None for subscription replies, other events, bars still forming and intervals that are not modelled
+pub(crate) fn parse_stream_message(text: &str) -> Result<Option<ClosedKline>> {
+    let envelope: StreamEnvelope = serde_json::from_str(text).map_err(|e| anyhow!("invalid stream message: {}", e))?;
+    let Some(StreamEvent { event, kline: Some(kline) }) = envelope.data else {
+        return Ok(None);
+    };
+    let Some(interval) = TimeInterval::from_binance_name(&kline.interval) else {
+        return Ok(None);
+    };
+    if event != "kline" || !kline.is_closed {
+        return Ok(None);
+    }
+    let number = |value: &str| value.parse::<f64>().map_err(|e| anyhow!("invalid kline value {}: {}", value, e));
+    let summary = KlineSummary {
+        open_time: kline.open_time,
+        open: number(&kline.open)?,
+        high: number(&kline.high)?,
+        low: number(&kline.low)?,
+        close: number(&kline.close)?,
+        volume: number(&kline.volume)?,
+        close_time: kline.close_time,
+        quote_asset_volume: number(&kline.quote_volume)?,
+        number_of_trades: kline.trades,
+        taker_buy_base_asset_volume: 0.0,
+        taker_buy_quote_asset_volume: 0.0,
+    };
+    let candle = convert_to_candles(&vec![summary]).pop().ok_or(anyhow!("empty kline"))?;
+    Ok(Some(ClosedKline { symbol: kline.symbol, interval, candle }))
+}
+
+pub struct KlineStream {
+    url: String,
+    buffers: SharedKlineBuffers,
+    backfill: Arc<dyn KlineBackfill + Send + Sync>,
+    retry_policy: RetryPolicy,
+}
+impl KlineStream {
+    pub fn new(url: impl Into<String>, buffers: SharedKlineBuffers, backfill: Arc<dyn KlineBackfill + Send + Sync>) -> Self {
+        KlineStream { url: url.into(), buffers, backfill, retry_policy: RetryPolicy::default() }
+    }
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+    // Runs until the task is dropped. A lost connection is opened again after the retry policy backoff, and the
+    // bars closed meanwhile are backfilled over REST.
+    pub async fn run(&self) {
+        let mut failures = 0;
+        loop {
+            match self.session().await {
+                Ok(()) => {
+                    info!("kline stream closed by the server");
+                    failures = 0;
+                }
+                Err(e) => {
+                    error!("kline stream failed: {:?}", e);
+                    failures += 1;
+                }
+            }
+            tokio::time::sleep(self.retry_policy.delay(failures, jitter())).await;
+        }
+    }
+    async fn session(&self) -> Result<()> {
+        let (mut socket, _) = connect_async(self.url.as_str()).await?;
+        info!("kline stream connected to {}", self.url);
+        let mut subscribed = HashSet::new();
+        self.subscribe(&mut socket, &mut subscribed).await?;
+        self.backfill_all().await;
+        // Series loaded after the connection was opened are subscribed on the next tick
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                message = socket.next() => {
+                    match message {
+                        Some(Ok(Message::Text(text))) => self.on_message(&text).await,
+                        Some(Ok(Message::Close(_))) | None => return Ok(()),
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => return Err(e.into()),
+                    }
+                }
+                _ = ticker.tick() => self.subscribe(&mut socket, &mut subscribed).await?,
+            }
+        }
+    }
+    async fn subscribe<S>(&self, socket: &mut S, subscribed: &mut HashSet<String>) -> Result<()>
+    where
+        S: SinkExt<Message> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let keys = self.buffers.lock().map_err(|_| anyhow!("kline buffers poisoned"))?.keys();
+        let mut streams: Vec<String> = keys.iter().map(|(symbol, interval)| stream_name(symbol, interval))
+            .filter(|stream| !subscribed.contains(stream)).collect();
+        streams.sort();
+        for chunk in streams.chunks(STREAMS_PER_SUBSCRIBE) {
+            let request = json!({ "method": "SUBSCRIBE", "params": chunk, "id": subscribed.len() + 1 });
+            socket.send(Message::Text(request.to_string())).await?;
+            subscribed.extend(chunk.iter().cloned());
+        }
+        Ok(())
+    }
+    async fn on_message(&self, text: &str) {
+        let kline = match parse_stream_message(text) {
+            Ok(Some(kline)) => kline,
+            Ok(None) => return,
+            Err(e) => {
+                error!("kline stream: {:?}", e);
+                return;
+            }
+        };
+        let open_time = kline.candle.open_time;
+        let gap = match self.buffers.lock() {
+            Ok(mut buffers) => buffers.push(&kline.symbol, &kline.interval, kline.candle),
+            Err(_) => return,
+        };
+        if let Some(last_open_time) = gap {
+            info!("kline stream: {} {} missed the bars after {}", kline.symbol, kline.interval.binance_name(), last_open_time);
+            self.backfill(&kline.symbol, &kline.interval, last_open_time, open_time).await;
+        }
+    }
+    // Loads the closed bars opened after `after` and at or before `until`. A failed backfill leaves the gap,
+    // the exchange then loads the window over REST.
+    async fn backfill(&self, symbol: &str, interval: &TimeInterval, after: NaiveDateTime, until: NaiveDateTime) {
+        let missing = ((until - after).num_minutes() / interval.minutes()).clamp(0, 1500) as u16;
+        if missing == 0 {
+            return;
+        }
+        match self.backfill.closed_klines(symbol, interval, missing, until).await {
+            Ok(klines) => {
+                let candles: Vec<Candle> = convert_to_candles(&klines).into_iter().filter(|candle| candle.open_time > after).collect();
+                if let Ok(mut buffers) = self.buffers.lock() {
+                    buffers.merge(symbol, interval, &candles, 0);
+                }
+            }
+            Err(e) => error!("kline stream: backfill of {} {} failed: {:?}", symbol, interval.binance_name(), e),
+        }
+    }
+    async fn backfill_all(&self) {
+        let keys = match self.buffers.lock() {
+            Ok(buffers) => buffers.keys(),
+            Err(_) => return,
+        };
+        let now = Utc::now().naive_utc();
+        for (symbol, interval) in keys {
+            let last_open_time = self.buffers.lock().ok().and_then(|buffers| buffers.last_open_time(&symbol, &interval));
+            if let Some(last_open_time) = last_open_time {
+                self.backfill(&symbol, &interval, last_open_time, now).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+    use crate::logic::convert_to_klines;
+
+    fn start() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    // The minute bar number k, its close is k
+    fn minute(k: i64) -> Candle {
+        let open_time = start() + Duration::minutes(k);
+        Candle { price: k as f64, open_time, close_time: open_time + Duration::seconds(60) - Duration::milliseconds(1), ..Candle::default() }
+    }
+
+    fn message(k: i64, is_closed: bool) -> String {
+        let candle = minute(k);
+        json!({
+            "stream": "btcusdt@kline_1m",
+            "data": { "e": "kline", "E": 0, "s": "BTCUSDT", "k": {
+                "t": candle.open_time.and_utc().timestamp_millis(), "T": candle.close_time.and_utc().timestamp_millis(),
+                "s": "BTCUSDT", "i": "1m", "f": 1, "L": 2, "o": "1.0", "c": format!("{}", k), "h": "2.0", "l": "0.5",
+                "v": "10.0", "n": 7, "x": is_closed, "q": "100.0", "V": "4.0", "Q": "40.0", "B": "0" } }
+        }).to_string()
+    }
+
+    fn opens(candles: &[Candle]) -> Vec<i64> {
+        candles.iter().map(|candle| (candle.open_time - start()).num_minutes()).collect()
+    }
+
+    #[test]
+    fn test_buffers_serve_complete_windows() -> Result<()> {
+        let mut buffers = KlineBuffers::default();
+        let candles: Vec<Candle> = (0..6).map(minute).collect();
+        buffers.merge("BTCUSDT", &TimeInterval::m1, &candles, 5);
+        assert_eq!(buffers.last_open_time("BTCUSDT", &TimeInterval::m1), Some(start() + Duration::minutes(5)));
+
+        // Bars 1..5 closed by the sixth minute
+        let until = start() + Duration::minutes(6) - Duration::seconds(1);
+        assert_eq!(opens(&buffers.window("BTCUSDT", &TimeInterval::m1, 5, until).unwrap()), vec![1, 2, 3, 4, 5]);
+        assert_eq!(opens(&buffers.window("BTCUSDT", &TimeInterval::m1, 2, until - Duration::minutes(2)).unwrap()), vec![2, 3]);
+        // Bar 6 has not arrived, more than the buffer holds, a series nobody loaded
+        assert!(buffers.window("BTCUSDT", &TimeInterval::m1, 5, until + Duration::minutes(1)).is_none());
+        assert!(buffers.window("BTCUSDT", &TimeInterval::m1, 6, until).is_none());
+        assert!(buffers.window("ETHUSDT", &TimeInterval::m1, 5, until).is_none());
+        assert_eq!(buffers.push("ETHUSDT", &TimeInterval::m1, minute(6)), None);
+
+        // Bar 7 arrives before bar 6
+        assert_eq!(buffers.push("BTCUSDT", &TimeInterval::m1, minute(7)), Some(start() + Duration::minutes(5)));
+        assert!(buffers.window("BTCUSDT", &TimeInterval::m1, 5, until + Duration::minutes(2)).is_none());
+        buffers.merge("BTCUSDT", &TimeInterval::m1, &[minute(6)], 0);
+        assert_eq!(opens(&buffers.window("BTCUSDT", &TimeInterval::m1, 5, until + Duration::minutes(2)).unwrap()), vec![3, 4, 5, 6, 7]);
+        // Bars 8 and 10 arrive, 9 is missing
+        buffers.push("BTCUSDT", &TimeInterval::m1, minute(8));
+        buffers.push("BTCUSDT", &TimeInterval::m1, minute(10));
+        assert_eq!(opens(&buffers.since("BTCUSDT", &TimeInterval::m1, minute(5).open_time)), vec![6, 7, 8]);
+        assert!(buffers.since("BTCUSDT", &TimeInterval::m1, minute(10).open_time).is_empty());
+        assert!(buffers.since("ETHUSDT", &TimeInterval::m1, minute(5).open_time).is_empty());
+
+        assert!(parse_stream_message(&message(8, false))?.is_none());
+        assert!(parse_stream_message(r#"{"result":null,"id":1}"#)?.is_none());
+        let kline = parse_stream_message(&message(8, true))?.unwrap();
+        assert_eq!((kline.symbol.as_str(), &kline.interval, kline.candle.price, kline.candle.open), ("BTCUSDT", &TimeInterval::m1, 8.0, 1.0));
+        assert_eq!((kline.candle.open_time, kline.candle.close_time), (minute(8).open_time, minute(8).close_time));
+        Ok(())
+    }
+
+    // Serves one connection per session, the subscription requests are collected. The last connection stays open.
+    async fn stand_in(sessions: Vec<Vec<String>>) -> Result<(String, Arc<Mutex<Vec<String>>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("ws://{}/stream", listener.local_addr()?);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            let count = sessions.len();
+            for (number, messages) in sessions.into_iter().enumerate() {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut socket = accept_async(tcp).await.unwrap();
+                if let Some(Ok(Message::Text(request))) = socket.next().await {
+                    received.lock().unwrap().push(request);
+                }
+                for message in messages {
+                    socket.send(Message::Text(message)).await.unwrap();
+                }
+                if number + 1 < count {
+                    socket.close(None).await.unwrap();
+                } else {
+                    while socket.next().await.is_some() {}
+                }
+            }
+        });
+        Ok((url, requests))
+    }
+
+    #[tokio::test]
+    async fn test_stream_reconnects_and_backfills() -> Result<()> {
+        let buffers = SharedKlineBuffers::default();
+        buffers.lock().unwrap().merge("BTCUSDT", &TimeInterval::m1, &(0..6).map(minute).collect::<Vec<_>>(), 10);
+        // Bars 0..9 are on the exchange, 7 is missed by the stream and 9 while it reconnects
+        let mut backfill = MockKlineBackfill::new();
+        backfill.expect_closed_klines().returning(|_, _, limit, until| {
+            let closed: Vec<Candle> = (0..10).map(minute).filter(|candle| candle.open_time <= until).collect();
+            Ok(convert_to_klines(&closed[closed.len().saturating_sub(limit as usize)..]))
+        });
+        let (url, requests) = stand_in(vec![
+            vec![message(5, true), message(6, true), message(8, false), message(8, true)],
+            vec![message(10, false), message(10, true)],
+        ]).await?;
+        let stream = KlineStream::new(url, buffers.clone(), Arc::new(backfill))
+            .with_retry_policy(RetryPolicy { base_delay: std::time::Duration::from_millis(10), ..Default::default() });
+        let task = tokio::spawn(async move { stream.run().await });
+
+        let until = start() + Duration::minutes(11) - Duration::seconds(1);
+        let mut window = None;
+        for _ in 0..500 {
+            window = buffers.lock().unwrap().window("BTCUSDT", &TimeInterval::m1, 10, until);
+            if window.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        task.abort();
+        assert_eq!(opens(&window.unwrap()), (1..=10).collect::<Vec<_>>());
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.contains(r#""params":["btcusdt@kline_1m"]"#)));
+        Ok(())
+    }
+}
@@ -4,12 +4,19 @@ use anyhow::anyhow;
 use binance_async::rest_model::{ KlineSummary};
 use chrono::{NaiveDateTime};
 use binance_sync::api::*;
-use crate::core::types::{SyntheticPairFullData, TradingPair, Candle, BaseAsset};
+use crate::core::types::{SyntheticPairFullData, TradingPair, Candle, BaseAsset, SyntheticBasket, TradingStrategy};
+use crate::mathematics::johansen::JOHANSEN_MAX_SERIES;
 use anyhow::Result;
 pub fn convert_to_candles(array:&Vec<KlineSummary>) ->Vec<Candle> {
     let candles: Vec<Candle> = array.iter().map(|candle|
 
-    Candle {price:candle.close as f32,
+    Candle {price:candle.close,
+        open:candle.open,
+        high:candle.high,
+        low:candle.low,
+        volume:candle.volume,
+        quote_volume:candle.quote_asset_volume,
+        trades:candle.number_of_trades,
         close_time:NaiveDateTime::from_timestamp(candle.close_time / 1000, (candle.close_time % 1000 * 1_000_000) as u32),
         open_time:NaiveDateTime::from_timestamp(candle.open_time / 1000, (candle.open_time % 1000 * 1_000_000) as u32)}
 
@@ -17,6 +24,22 @@ pub fn convert_to_candles(array:&Vec<KlineSummary>) ->Vec<Candle> {
 
     candles
 }
+// Back to the exchange representation, for candles served from the kline store. Candle does not keep the taker volumes
+pub fn convert_to_klines(candles:&[Candle]) ->Vec<KlineSummary> {
+    candles.iter().map(|candle| KlineSummary {
+        open_time:candle.open_time.and_utc().timestamp_millis(),
+        open:candle.open,
+        high:candle.high,
+        low:candle.low,
+        close:candle.price,
+        volume:candle.volume,
+        close_time:candle.close_time.and_utc().timestamp_millis(),
+        quote_asset_volume:candle.quote_volume,
+        number_of_trades:candle.trades,
+        taker_buy_base_asset_volume:0.0,
+        taker_buy_quote_asset_volume:0.0,
+    }).collect()
+}
 
 pub fn remove_duplicate_full_pairs(vec: Vec<TradingPair>) -> Vec<TradingPair> {
     let mut seen = HashSet::new();
@@ -26,7 +49,7 @@ pub fn remove_duplicate_full_pairs(vec: Vec<TradingPair>) -> Vec<TradingPair> {
 }
 
 
-pub fn generate_synthetic_pair(pairs: &Vec<TradingPair>, last_configuration_id:i32) -> Result<Vec<SyntheticPairFullData>> {
+pub fn generate_synthetic_pair(pairs: &Vec<TradingPair>, strategy:&TradingStrategy) -> Result<Vec<SyntheticPairFullData>> {
     let mut synthetic_pairs = Vec::new();
     let n = pairs.len();
     let mut num=0;
@@ -44,7 +67,7 @@ pub fn generate_synthetic_pair(pairs: &Vec<TradingPair>, last_configuration_id:i
                 is_request_pair:None,
                 synthetic_data: HashMap::new()
             };
-            syn_pair.init_pair_data(last_configuration_id);
+            syn_pair.init_pair_data(strategy.id, &strategy.intervals);
             synthetic_pairs.push(syn_pair);
 
             num+=1;
@@ -52,4 +75,80 @@ pub fn generate_synthetic_pair(pairs: &Vec<TradingPair>, last_configuration_id:i
     }
     Ok(synthetic_pairs)
 }
+// Every combination of 3..=max_basket_size legs drawn from the first basket_universe pairs.
+// Both limits come from the strategy so the number of baskets stays bounded.
+pub fn generate_synthetic_baskets(pairs: &Vec<TradingPair>, strategy:&TradingStrategy) -> Result<Vec<SyntheticBasket>> {
+    let mut baskets = Vec::new();
+    let max_size=(strategy.max_basket_size.max(0) as usize).min(JOHANSEN_MAX_SERIES);
+    if max_size<3 {
+        return Ok(baskets);
+    }
+    let universe:Vec<&TradingPair>=pairs.iter()
+        .filter(|pair| pair.is_request_pair!=Some(false))
+        .take(strategy.basket_universe.max(0) as usize)
+        .collect();
+
+    for size in 3..=max_size.min(universe.len()) {
+        let mut indices:Vec<usize>=(0..size).collect();
+        loop {
+            let legs=indices.iter().map(|&i| BaseAsset { id: universe[i].id, name: universe[i].name.clone() }).collect();
+            let mut basket=SyntheticBasket::new(legs);
+            basket.init_pair_data(strategy.id, &[strategy.primary_interval()]);
+            baskets.push(basket);
+
+            // Next combination in lexicographic order
+            let mut position=size;
+            while position>0 && indices[position-1]==universe.len()-size+position-1 {
+                position-=1;
+            }
+            if position==0 {
+                break;
+            }
+            indices[position-1]+=1;
+            for k in position..size {
+                indices[k]=indices[k-1]+1;
+            }
+        }
+    }
+    Ok(baskets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn test_klines_round_trip() {
+        let open_time = NaiveDateTime::parse_from_str("2024-09-01 04:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let candle = Candle { price: 101.25, open: 100.0, high: 102.5, low: 99.5, volume: 12.25, quote_volume: 1236.5, trades: 42,
+            open_time, close_time: open_time + chrono::Duration::hours(4) - chrono::Duration::milliseconds(1) };
+
+        let klines = convert_to_klines(std::slice::from_ref(&candle));
+        assert_eq!((klines[0].open_time, klines[0].close_time), (1725163200000, 1725177599999));
+        let back = convert_to_candles(&klines);
+        assert_eq!((back[0].price, back[0].open, back[0].high, back[0].low), (101.25, 100.0, 102.5, 99.5));
+        assert_eq!((back[0].volume, back[0].quote_volume, back[0].trades), (12.25, 1236.5, 42));
+        assert_eq!((back[0].open_time, back[0].close_time), (candle.open_time, candle.close_time));
+    }
+
+    #[test]
+    fn test_generate_synthetic_baskets_is_bounded() -> Result<()> {
+        let pairs: Vec<TradingPair> = ["BTCUSDT", "ETHUSDT", "SOLUSDT", "BNBUSDT", "XRPUSDT", "ADAUSDT"].iter().enumerate()
+            .map(|(i, name)| TradingPair { id: i as i32, name: name.to_string(), pair_data_map: HashMap::new(), is_request_pair: None })
+            .collect();
+        let mut strategy = TradingStrategy { id: 1, max_basket_size: 4, basket_universe: 5, ..Default::default() };
+
+        let baskets = generate_synthetic_baskets(&pairs, &strategy)?;
+
+        // C(5,3) + C(5,4), ADAUSDT is outside the universe
+        assert_eq!(baskets.len(), 15);
+        assert_eq!(baskets[0].name, "BTCUSDT+ETHUSDT+SOLUSDT");
+        assert!(baskets.iter().all(|basket| !basket.name.contains("ADAUSDT")));
+        assert_eq!(SyntheticBasket::parse_name(&baskets[14].name).unwrap(), vec!["ETHUSDT", "SOLUSDT", "BNBUSDT", "XRPUSDT"]);
+        assert!(SyntheticBasket::parse_name("BTCUSDT/ETHUSDT").is_none());
+
+        strategy.max_basket_size = 2;
+        assert!(generate_synthetic_baskets(&pairs, &strategy)?.is_empty());
+        Ok(())
+    }
+}
@@ -7,16 +7,18 @@ mod storage;
 mod binance_collector;
 mod mathematics;
 mod logic;
+mod cli;
+#[cfg(test)]
+mod test_util;
 
 use std::collections::HashMap;
-use std::{env, process};
 use std::error::Error;
 use std::io::{Write, BufWriter};
 use std::sync::{Arc};
 use std::time::{Duration, Instant};
 use binance_sync::api::*;
 use binance_sync::futures::market::*;
-use chrono::{Local, NaiveDateTime, Utc};
+use chrono::Local;
 use dotenvy::dotenv;
 use sqlx::postgres::PgPoolOptions;
 use tokio::task;
@@ -31,25 +33,82 @@ use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::fmt::Layer;
 use tracing_error::ErrorLayer;
-use crate::binance_collector::BinanceCollector;
+use clap::Parser;
+use crate::binance_collector::{BinanceCollector, CollectorMode};
+use crate::cli::{Cli, CollectorArgs, Command};
+use crate::core::types::ReplayWindow;
 use crate::exchange::binance::{BinanceExchange, ExchangeInterface};
+use crate::exchange::limiter::RateLimitConfig;
+use crate::exchange::replay::FileReplayExchange;
+use crate::exchange::retry::RetryPolicy;
+use crate::exchange::stream::{KlineStream, SharedKlineBuffers, BINANCE_FUTURES_STREAM_URL};
 use crate::storage::database::{DatabaseInterface, PostgresDataBase};
 
 async  fn start()->Result<()>{
-    let api_key = env::var("API_KEY")
-        .expect("DATABASE_URL must be set in .env file or environment");
-    let secret_key = env::var("SECRET_KEY")
-        .expect("DATABASE_URL must be set in .env file or environment");
-    let database_url = env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set in .env file or environment");
+    let Cli{database_url,command,run}=Cli::parse();
+    let database_url=database_url.ok_or(anyhow!("DATABASE_URL must be set in .env file or environment, or passed with --database-url"))?;
     let db=PostgresDataBase::new(database_url.as_str()).await?;
-    let binance=BinanceExchange::new(api_key,secret_key)?;
-    let mut bot = BinanceCollector::new(db,binance).await?;
-
+    match command.unwrap_or(Command::Run(run)) {
+        Command::Run(args)=>{
+            let mode=CollectorMode::parse(&args.mode,&args.live_interval)?;
+            collect(db,mode,&args.stream_url,&args.collector,None).await
+        }
+        Command::Replay(args)=>collect(db,CollectorMode::Replay,BINANCE_FUTURES_STREAM_URL,&args.collector,args.window.window()?).await,
+        Command::Migrate=>cli::migrate(&db).await,
+        Command::Status(args)=>cli::status(&db,&args).await,
+        Command::Reset(args)=>cli::reset(&db,&args).await,
+        Command::Runs{command}=>cli::runs(&db,&command).await,
+        Command::Export(args)=>cli::export(&db,&args).await,
+        Command::AddStrategy(args)=>cli::add_strategy(&db,&args).await,
+        Command::ImportKlines{dir}=>cli::import(&db,&dir).await,
+    }
+}
+// The collector loop, offline on the archives of --replay-dir or on Binance
+async fn collect(db:PostgresDataBase,mode:CollectorMode,stream_url:&str,args:&CollectorArgs,replay_window:Option<ReplayWindow>)->Result<()>{
+    let run_id=db.resolve_run(args.run).await?;
+    if let Some(replay_dir)=&args.replay_dir {
+        let replay=FileReplayExchange::new(replay_dir)?;
+        return run(configure(BinanceCollector::new(db,replay,run_id).await?,args,replay_window).await?).await;
+    }
+    let api_key=args.api_key.clone().ok_or(anyhow!("API_KEY must be set in .env file or environment, or passed with --api-key"))?;
+    let secret_key=args.secret_key.clone().ok_or(anyhow!("SECRET_KEY must be set in .env file or environment, or passed with --secret-key"))?;
+    let mut binance=BinanceExchange::new(api_key,secret_key)?
+        .with_rate_limits(&RateLimitConfig::from_env()).with_retry_policy(RetryPolicy::from_env()).with_kline_store(Arc::new(db.clone()));
+    // Live mode keeps the closed bars of the loaded pairs up to date from the kline stream, see exchange/stream.rs
+    if let CollectorMode::Live{..}=mode {
+        let buffers=SharedKlineBuffers::default();
+        binance=binance.with_kline_stream(buffers.clone());
+        let stream=KlineStream::new(stream_url,buffers,Arc::new(binance.clone())).with_retry_policy(RetryPolicy::from_env());
+        tokio::spawn(async move { stream.run().await });
+    }
+    run(configure(BinanceCollector::new(db,binance,run_id).await?.with_mode(mode),args,replay_window).await?).await
+}
+// The flags of the collector over what BinanceCollector::new read from the environment
+async fn configure<D: DatabaseInterface,C: ExchangeInterface>(mut bot:BinanceCollector<D,C>,args:&CollectorArgs,replay_window:Option<ReplayWindow>)->Result<BinanceCollector<D,C>>{
+    if let Some(workers)=args.workers {
+        bot=bot.with_analyzer_workers(workers)?;
+    }
+    if let Some(export_url)=&args.export_url {
+        bot=bot.with_export_url(export_url.clone());
+    }
+    if let Some(window)=replay_window {
+        bot=bot.with_replay_window(window).await?;
+    }
+    Ok(bot)
+}
+async fn run<D: DatabaseInterface,C: ExchangeInterface>(mut bot:BinanceCollector<D,C>)->Result<()>{
     loop{
+        // A replay ends with its window, only the live mode keeps running
+        if bot.is_finished() {
+            info!("replay window finished");
+            break;
+        }
         if bot.is_stationarity_time().await? {
 
             bot.run_stationarity().await?;
+        } else {
+            // Live between two closes, the intraday candles of the stream update the last analysis
+            bot.update_live().await?;
         }
         tokio::time::sleep(Duration::from_millis(5000)).await;
     }
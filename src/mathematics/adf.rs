@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Result};
+use ndarray::Array2;
+use crate::mathematics::ols::{ols, OlsFit};
+
+// Augmented Dickey-Fuller test, a port of statsmodels.tsa.stattools.adfuller (N = 1).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdfRegression {
+    NoConstant,
+    Constant,
+    ConstantTrend,
+}
+impl AdfRegression {
+    fn trend_columns(&self) -> usize {
+        match self {
+            AdfRegression::NoConstant => 0,
+            AdfRegression::Constant => 1,
+            AdfRegression::ConstantTrend => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdfAutoLag {
+    Aic,
+    Bic,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdfResult {
+    pub statistic: f64,
+    pub p_value: f64,
+    pub used_lag: usize,
+    pub nobs: usize,
+    // 1%, 5%, 10%
+    pub critical_values: [f64; 3],
+    pub ic_best: Option<f64>,
+}
+
+pub fn adfuller(series: &[f64], max_lag: Option<usize>, regression: AdfRegression, autolag: Option<AdfAutoLag>) -> Result<AdfResult> {
+    let n = series.len();
+    if n < 4 {
+        return Err(anyhow!("adfuller: series is too short ({})", n));
+    }
+    let min = series.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = series.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if !min.is_finite() || !max.is_finite() {
+        return Err(anyhow!("adfuller: series contains non-finite values"));
+    }
+    if min == max {
+        return Err(anyhow!("adfuller: series is constant"));
+    }
+
+    let ntrend = regression.trend_columns() as i64;
+    let lag_limit = n as i64 / 2 - ntrend - 1;
+    let max_lag = match max_lag {
+        Some(lag) => {
+            if lag as i64 > lag_limit {
+                return Err(anyhow!("adfuller: max_lag must be at most {} for {} observations", lag_limit, n));
+            }
+            lag
+        }
+        None => {
+            // Schwert (1989) rule of thumb, as in statsmodels
+            let lag = (12.0 * (n as f64 / 100.0).powf(0.25)).ceil() as i64;
+            let lag = lag.min(lag_limit);
+            if lag < 0 {
+                return Err(anyhow!("adfuller: series is too short ({})", n));
+            }
+            lag as usize
+        }
+    };
+
+    let diff: Vec<f64> = series.windows(2).map(|w| w[1] - w[0]).collect();
+
+    let (used_lag, ic_best) = match autolag {
+        Some(method) => {
+            // Compare every lag on the same sample so the information criteria are comparable
+            let mut best: Option<(f64, usize)> = None;
+            for lag in 0..=max_lag {
+                let (y, x) = adf_design(series, &diff, max_lag, lag, regression);
+                let fit = ols(&y, &x)?;
+                let ic = match method {
+                    AdfAutoLag::Aic => fit.aic(),
+                    AdfAutoLag::Bic => fit.bic(),
+                };
+                let improves = match best {
+                    Some((best_ic, _)) => ic < best_ic,
+                    None => true,
+                };
+                if improves {
+                    best = Some((ic, lag));
+                }
+            }
+            let (ic, lag) = best.ok_or(anyhow!("adfuller: lag search failed"))?;
+            (lag, Some(ic))
+        }
+        None => (max_lag, None),
+    };
+
+    let (y, x) = adf_design(series, &diff, used_lag, used_lag, regression);
+    let fit: OlsFit = ols(&y, &x)?;
+    let statistic = fit.t_values()[0];
+    let nobs = y.len();
+
+    Ok(AdfResult {
+        statistic,
+        p_value: mackinnon_p_value(statistic, regression),
+        used_lag,
+        nobs,
+        critical_values: mackinnon_critical_values(regression, nobs),
+        ic_best,
+    })
+}
+
+// Regression of diff[t] on level[t], diff[t-1..t-lags] and the deterministic terms.
+// The first `sample_lag` differences are dropped so different lag counts share one sample.
+fn adf_design(series: &[f64], diff: &[f64], sample_lag: usize, lags: usize, regression: AdfRegression) -> (Vec<f64>, Array2<f64>) {
+    let rows = diff.len() - sample_lag;
+    let columns = 1 + lags + regression.trend_columns();
+    let mut x = Array2::<f64>::zeros((rows, columns));
+    let mut y = Vec::with_capacity(rows);
+    for r in 0..rows {
+        let t = sample_lag + r;
+        y.push(diff[t]);
+        x[[r, 0]] = series[t];
+        for l in 1..=lags {
+            x[[r, l]] = diff[t - l];
+        }
+        match regression {
+            AdfRegression::NoConstant => {}
+            AdfRegression::Constant => {
+                x[[r, lags + 1]] = 1.0;
+            }
+            AdfRegression::ConstantTrend => {
+                x[[r, lags + 1]] = 1.0;
+                x[[r, lags + 2]] = (r + 1) as f64;
+            }
+        }
+    }
+    (y, x)
+}
+
+// MacKinnon (1994) approximate p-value surface for a single unit-root series.
+pub fn mackinnon_p_value(statistic: f64, regression: AdfRegression) -> f64 {
+    let (tau_max, tau_min, tau_star, small_p, large_p): (f64, f64, f64, [f64; 3], [f64; 4]) = match regression {
+        AdfRegression::NoConstant => (f64::INFINITY, -19.04, -1.04,
+                                      [0.6344, 1.2378, 3.2496e-2],
+                                      [0.4797, 9.3557e-1, -0.6999e-1, 3.3066e-2]),
+        AdfRegression::Constant => (2.74, -18.83, -1.61,
+                                    [2.1659, 1.4412, 3.8269e-2],
+                                    [1.7339, 9.3202e-1, -1.2745e-1, -1.0368e-2]),
+        AdfRegression::ConstantTrend => (0.7, -16.18, -2.89,
+                                         [3.2512, 1.6047, 4.9588e-2],
+                                         [2.5261, 6.1654e-1, -3.7956e-1, -6.0285e-2]),
+    };
+    if statistic > tau_max {
+        return 1.0;
+    }
+    if statistic < tau_min {
+        return 0.0;
+    }
+    let value = if statistic <= tau_star {
+        polyval(&small_p, statistic)
+    } else {
+        polyval(&large_p, statistic)
+    };
+    normal_cdf(value)
+}
+
+// MacKinnon (2010) finite-sample critical values at 1%, 5% and 10%.
+pub fn mackinnon_critical_values(regression: AdfRegression, nobs: usize) -> [f64; 3] {
+    let table: [[f64; 4]; 3] = match regression {
+        AdfRegression::NoConstant => [
+            [-2.56574, -2.2358, -3.627, 0.0],
+            [-1.94100, -0.2686, -3.365, 31.223],
+            [-1.61682, 0.2656, -2.714, 25.364],
+        ],
+        AdfRegression::Constant => [
+            [-3.43035, -6.5393, -16.786, -79.433],
+            [-2.86154, -2.8903, -4.234, -40.040],
+            [-2.56677, -1.5384, -2.809, 0.0],
+        ],
+        AdfRegression::ConstantTrend => [
+            [-3.95877, -9.0531, -28.428, -134.155],
+            [-3.41049, -4.3904, -9.036, -45.374],
+            [-3.12705, -2.5856, -3.925, -22.380],
+        ],
+    };
+    let inv_n = 1.0 / nobs as f64;
+    [polyval(&table[0], inv_n), polyval(&table[1], inv_n), polyval(&table[2], inv_n)]
+}
+
+// Coefficients in increasing order of power
+fn polyval(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+pub fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+// Chebyshev fit of erfc, fractional error below 1.2e-7 everywhere
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.26551223
+        + t * (1.00002368
+        + t * (0.37409196
+        + t * (0.09678418
+        + t * (-0.18628806
+        + t * (0.27886807
+        + t * (-1.13520398
+        + t * (1.48851587
+        + t * (-0.82215223
+        + t * 0.17087277))))))))).exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const SUNSPOTS: &str = include_str!("../../tests/fixtures/statsmodels_sunspots.json");
+
+    fn fixture_series(fixture: &Value) -> Vec<f64> {
+        fixture["series"].as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_adfuller_matches_statsmodels_sunspots() -> Result<()> {
+        let fixture: Value = serde_json::from_str(SUNSPOTS)?;
+        let expected = &fixture["adfuller"];
+        let series = fixture_series(&fixture);
+
+        let result = adfuller(&series, None, AdfRegression::Constant, Some(AdfAutoLag::Aic))?;
+
+        assert!((result.statistic - expected["statistic"].as_f64().unwrap()).abs() < 1e-6);
+        assert!((result.p_value - expected["p_value"].as_f64().unwrap()).abs() < 1e-6);
+        assert_eq!(result.used_lag as u64, expected["used_lag"].as_u64().unwrap());
+        assert_eq!(result.nobs as u64, expected["nobs"].as_u64().unwrap());
+        for (value, expected) in result.critical_values.iter().zip(expected["critical_values"].as_array().unwrap()) {
+            assert!((value - expected.as_f64().unwrap()).abs() < 1e-6);
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs tests/fixtures/statsmodels_adfuller_cases.json, generate it with tests/fixtures/statsmodels_golden.py"]
+    fn test_adfuller_matches_statsmodels_cases() -> Result<()> {
+        let series = fixture_series(&serde_json::from_str(SUNSPOTS)?);
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/statsmodels_adfuller_cases.json");
+        let fixture: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+        for expected in fixture["cases"].as_array().unwrap() {
+            let regression = match expected["regression"].as_str().unwrap() {
+                "n" => AdfRegression::NoConstant,
+                "c" => AdfRegression::Constant,
+                "ct" => AdfRegression::ConstantTrend,
+                other => panic!("unknown regression {}", other),
+            };
+            let autolag = match expected["autolag"].as_str() {
+                Some("AIC") => Some(AdfAutoLag::Aic),
+                Some("BIC") => Some(AdfAutoLag::Bic),
+                None => None,
+                Some(other) => panic!("unknown autolag {}", other),
+            };
+            let max_lag = expected["maxlag"].as_u64().map(|lag| lag as usize);
+
+            let result = adfuller(&series, max_lag, regression, autolag)?;
+
+            assert!((result.statistic - expected["statistic"].as_f64().unwrap()).abs() < 1e-6, "{}", expected);
+            assert!((result.p_value - expected["p_value"].as_f64().unwrap()).abs() < 1e-6, "{}", expected);
+            assert_eq!(result.used_lag as u64, expected["used_lag"].as_u64().unwrap(), "{}", expected);
+            assert_eq!(result.nobs as u64, expected["nobs"].as_u64().unwrap(), "{}", expected);
+            for (value, critical) in result.critical_values.iter().zip(expected["critical_values"].as_array().unwrap()) {
+                assert!((value - critical.as_f64().unwrap()).abs() < 1e-6, "{}", expected);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_mackinnon_p_value_at_asymptotic_critical_values() {
+        let cases = [
+            (AdfRegression::NoConstant, -1.94100),
+            (AdfRegression::Constant, -2.86154),
+            (AdfRegression::ConstantTrend, -3.41049),
+        ];
+        for (regression, critical) in cases {
+            assert!((mackinnon_p_value(critical, regression) - 0.05).abs() < 1e-3);
+        }
+        assert_eq!(mackinnon_p_value(5.0, AdfRegression::Constant), 1.0);
+        assert_eq!(mackinnon_p_value(-30.0, AdfRegression::Constant), 0.0);
+    }
+
+    #[test]
+    fn test_adfuller_rejects_constant_series() {
+        let series = vec![1.5; 100];
+        assert!(adfuller(&series, None, AdfRegression::Constant, Some(AdfAutoLag::Aic)).is_err());
+    }
+}
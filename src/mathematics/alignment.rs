@@ -0,0 +1,248 @@
+use std::cmp::Ordering;
+use anyhow::{anyhow, Result};
+use crate::core::types::{Candle, GapPolicy};
+
+// Joins the two legs of a synthetic pair, or the legs of a basket, on open_time, so a missing candle on
+// one leg can not shift the other legs against it. The legs must be sorted by open_time, as the exchange
+// returns them.
+
+#[derive(Debug, Clone)]
+pub struct AlignedLegs {
+    pub first: Vec<Candle>,
+    pub second: Vec<Candle>,
+    // Timestamps present in one leg only, dropped or forward-filled
+    pub repaired: usize,
+    // Their share among all timestamps of both legs, in percent
+    pub gap_percent: f32,
+}
+
+pub fn align_legs(first: &[Candle], second: &[Candle], policy: GapPolicy) -> Result<AlignedLegs> {
+    let capacity = first.len().max(second.len());
+    let mut aligned_first = Vec::with_capacity(capacity);
+    let mut aligned_second = Vec::with_capacity(capacity);
+    let (mut i, mut j) = (0, 0);
+    let (mut last_first, mut last_second): (Option<&Candle>, Option<&Candle>) = (None, None);
+    let mut timestamps = 0;
+    let mut repaired = 0;
+
+    while i < first.len() || j < second.len() {
+        timestamps += 1;
+        let order = match (first.get(i), second.get(j)) {
+            (Some(a), Some(b)) => a.open_time.cmp(&b.open_time),
+            (Some(_), None) => Ordering::Less,
+            (None, _) => Ordering::Greater,
+        };
+        match order {
+            Ordering::Equal => {
+                aligned_first.push(first[i].clone());
+                aligned_second.push(second[j].clone());
+                (last_first, last_second) = (Some(&first[i]), Some(&second[j]));
+                i += 1;
+                j += 1;
+            }
+            Ordering::Less => {
+                repaired += 1;
+                if let (GapPolicy::ForwardFill, Some(last)) = (policy, last_second) {
+                    aligned_first.push(first[i].clone());
+                    aligned_second.push(last.flat(first[i].open_time, first[i].close_time));
+                }
+                last_first = Some(&first[i]);
+                i += 1;
+            }
+            Ordering::Greater => {
+                repaired += 1;
+                if let (GapPolicy::ForwardFill, Some(last)) = (policy, last_first) {
+                    aligned_first.push(last.flat(second[j].open_time, second[j].close_time));
+                    aligned_second.push(second[j].clone());
+                }
+                last_second = Some(&second[j]);
+                j += 1;
+            }
+        }
+    }
+
+    if aligned_first.is_empty() {
+        return Err(anyhow!("align_legs: the legs have no common candles"));
+    }
+    Ok(AlignedLegs {
+        first: aligned_first,
+        second: aligned_second,
+        repaired,
+        gap_percent: (repaired as f64 / timestamps as f64 * 100.0) as f32,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct AlignedBasket {
+    // One series per leg, in the order of the basket legs
+    pub legs: Vec<Vec<Candle>>,
+    // Timestamps missing in at least one leg, dropped or forward-filled
+    pub repaired: usize,
+    // Their share among all timestamps of the legs, in percent
+    pub gap_percent: f32,
+}
+
+// align_legs for any number of legs. A timestamp is forward-filled only when every leg missing it has an
+// earlier candle.
+pub fn align_basket_legs(legs: &[&[Candle]], policy: GapPolicy) -> Result<AlignedBasket> {
+    if legs.is_empty() {
+        return Err(anyhow!("align_basket_legs: the basket has no legs"));
+    }
+    let mut aligned: Vec<Vec<Candle>> = vec![Vec::new(); legs.len()];
+    let mut next = vec![0; legs.len()];
+    let mut last: Vec<Option<&Candle>> = vec![None; legs.len()];
+    let mut timestamps = 0;
+    let mut repaired = 0;
+
+    // The earliest open_time not joined yet, every leg with a candle at it moves on
+    while let Some(open_time) = legs.iter().zip(&next).filter_map(|(leg, &i)| leg.get(i)).map(|candle| candle.open_time).min() {
+        timestamps += 1;
+        let present: Vec<Option<&Candle>> = legs.iter().zip(next.iter_mut()).map(|(leg, i)| {
+            let candle = leg.get(*i).filter(|candle| candle.open_time == open_time);
+            if candle.is_some() {
+                *i += 1;
+            }
+            candle
+        }).collect();
+        if present.iter().all(Option::is_some) {
+            for (leg, candle) in aligned.iter_mut().zip(&present) {
+                leg.extend(candle.cloned());
+            }
+        } else {
+            repaired += 1;
+            let times = present.iter().flatten().next().map(|candle| (candle.open_time, candle.close_time));
+            let filled = present.iter().zip(&last).all(|(candle, last)| candle.is_some() || last.is_some());
+            if let (GapPolicy::ForwardFill, Some((open_time, close_time)), true) = (policy, times, filled) {
+                for ((leg, candle), last) in aligned.iter_mut().zip(&present).zip(&last) {
+                    leg.push(match (candle, last) {
+                        (Some(candle), _) => (*candle).clone(),
+                        (None, Some(last)) => last.flat(open_time, close_time),
+                        (None, None) => unreachable!(),
+                    });
+                }
+            }
+        }
+        for (last, candle) in last.iter_mut().zip(&present) {
+            if candle.is_some() {
+                *last = *candle;
+            }
+        }
+    }
+
+    if aligned[0].is_empty() {
+        return Err(anyhow!("align_basket_legs: the legs have no common candles"));
+    }
+    Ok(AlignedBasket {
+        legs: aligned,
+        repaired,
+        gap_percent: (repaired as f64 / timestamps as f64 * 100.0) as f32,
+    })
+}
+
+fn within_gap_limit(gap_percent: f32, max_gap_percent: Option<f32>) -> bool {
+    match max_gap_percent {
+        Some(limit) => gap_percent <= limit,
+        None => true,
+    }
+}
+
+// Strategy limit on the gaps, None means the gaps do not reject the pair
+pub fn passes_gap_limit(legs: &AlignedLegs, max_gap_percent: Option<f32>) -> bool {
+    within_gap_limit(legs.gap_percent, max_gap_percent)
+}
+
+pub fn passes_basket_gap_limit(basket: &AlignedBasket, max_gap_percent: Option<f32>) -> bool {
+    within_gap_limit(basket.gap_percent, max_gap_percent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::h4_candles;
+
+    // H4 candles at the given bar numbers, bar k priced base + k
+    fn leg(base: f64, bars: &[i64]) -> Vec<Candle> {
+        h4_candles(&bars.iter().map(|&k| (k, base + k as f64)).collect::<Vec<_>>())
+    }
+
+    fn bars(aligned: &[Candle], base: f64) -> Vec<i64> {
+        aligned.iter().map(|candle| (candle.price - base) as i64).collect()
+    }
+
+    #[test]
+    fn test_drop_keeps_common_timestamps() -> Result<()> {
+        let first = leg(100.0, &(0..10).collect::<Vec<_>>());
+        let second = leg(200.0, &[0, 1, 2, 4, 5, 6, 8, 9]);
+
+        let legs = align_legs(&first, &second, GapPolicy::Drop)?;
+
+        assert_eq!(bars(&legs.first, 100.0), vec![0, 1, 2, 4, 5, 6, 8, 9]);
+        assert_eq!(bars(&legs.second, 200.0), vec![0, 1, 2, 4, 5, 6, 8, 9]);
+        assert!(legs.first.iter().zip(legs.second.iter()).all(|(a, b)| a.open_time == b.open_time));
+        assert_eq!(legs.repaired, 2);
+        assert!((legs.gap_percent - 20.0).abs() < 1e-4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_forward_fill_repeats_the_last_price() -> Result<()> {
+        // Gaps on both legs, and a leading gap on the second one that can not be filled
+        let first = leg(100.0, &[0, 1, 2, 3, 5, 6, 7]);
+        let second = leg(200.0, &[1, 2, 4, 5, 7]);
+
+        let legs = align_legs(&first, &second, GapPolicy::ForwardFill)?;
+
+        assert_eq!(bars(&legs.first, 100.0), vec![1, 2, 3, 3, 5, 6, 7]);
+        assert_eq!(bars(&legs.second, 200.0), vec![1, 2, 2, 4, 5, 5, 7]);
+        assert!(legs.first.iter().zip(legs.second.iter()).all(|(a, b)| a.open_time == b.open_time && a.close_time == b.close_time));
+        assert_eq!(legs.repaired, 4);
+        assert!((legs.gap_percent - 50.0).abs() < 1e-4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_gap_limit_rejects_sparse_legs() -> Result<()> {
+        let first = leg(100.0, &(0..10).collect::<Vec<_>>());
+        let second = leg(200.0, &[0, 1, 2, 4, 5, 6, 8, 9]);
+        let legs = align_legs(&first, &second, GapPolicy::ForwardFill)?;
+
+        assert!(passes_gap_limit(&legs, None));
+        assert!(passes_gap_limit(&legs, Some(20.0)));
+        assert!(!passes_gap_limit(&legs, Some(10.0)));
+
+        let complete = align_legs(&first, &first, GapPolicy::Drop)?;
+        assert_eq!((complete.repaired, complete.first.len()), (0, 10));
+        assert!(passes_gap_limit(&complete, Some(0.0)));
+
+        assert!(align_legs(&first, &[], GapPolicy::ForwardFill).is_err());
+        assert!(align_legs(&leg(1.0, &[0, 1]), &leg(2.0, &[2, 3]), GapPolicy::Drop).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_basket_legs_are_joined_on_open_time() -> Result<()> {
+        let (first, second, third) = (leg(100.0, &(0..8).collect::<Vec<_>>()), leg(200.0, &[1, 2, 3, 5, 6, 7]), leg(300.0, &[0, 1, 2, 3, 4, 5, 7]));
+        let legs = [first.as_slice(), second.as_slice(), third.as_slice()];
+
+        // The missing bar 4 of the second leg does not shift its later bars against the others
+        let basket = align_basket_legs(&legs, GapPolicy::Drop)?;
+        assert_eq!(bars(&basket.legs[0], 100.0), vec![1, 2, 3, 5, 7]);
+        assert_eq!(bars(&basket.legs[1], 200.0), vec![1, 2, 3, 5, 7]);
+        assert_eq!(bars(&basket.legs[2], 300.0), vec![1, 2, 3, 5, 7]);
+        assert_eq!(basket.repaired, 3);
+        assert!((basket.gap_percent - 37.5).abs() < 1e-4);
+        assert!(passes_basket_gap_limit(&basket, Some(40.0)));
+        assert!(!passes_basket_gap_limit(&basket, Some(30.0)));
+
+        // Bar 0 stays out, the second leg has nothing to repeat yet
+        let basket = align_basket_legs(&legs, GapPolicy::ForwardFill)?;
+        assert_eq!(bars(&basket.legs[0], 100.0), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(bars(&basket.legs[1], 200.0), vec![1, 2, 3, 3, 5, 6, 7]);
+        assert_eq!(bars(&basket.legs[2], 300.0), vec![1, 2, 3, 4, 5, 5, 7]);
+        assert!(basket.legs[1].iter().zip(basket.legs[0].iter()).all(|(a, b)| a.open_time == b.open_time && a.close_time == b.close_time));
+
+        assert!(align_basket_legs(&[], GapPolicy::Drop).is_err());
+        assert!(align_basket_legs(&[first.as_slice(), &[]], GapPolicy::Drop).is_err());
+        Ok(())
+    }
+}
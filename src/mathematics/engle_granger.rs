@@ -0,0 +1,67 @@
+use anyhow::{anyhow, Result};
+use ndarray::Array2;
+use crate::mathematics::ols::ols;
+
+// First step of the Engle-Granger procedure: ln(first) = intercept + hedge_ratio * ln(second) + spread.
+// The residual spread is what goes to the stationarity test.
+
+#[derive(Debug, Clone)]
+pub struct EngleGrangerFit {
+    pub hedge_ratio: f64,
+    pub intercept: f64,
+    pub spread: Vec<f64>,
+}
+
+pub fn engle_granger(first: &[f64], second: &[f64]) -> Result<EngleGrangerFit> {
+    if first.len() != second.len() {
+        return Err(anyhow!("engle_granger: legs have different lengths ({} and {})", first.len(), second.len()));
+    }
+    if first.iter().chain(second.iter()).any(|price| *price <= 0.0 || !price.is_finite()) {
+        return Err(anyhow!("engle_granger: prices must be positive and finite"));
+    }
+
+    let log_first: Vec<f64> = first.iter().map(|price| price.ln()).collect();
+    let mut x = Array2::<f64>::zeros((second.len(), 2));
+    for (i, price) in second.iter().enumerate() {
+        x[[i, 0]] = 1.0;
+        x[[i, 1]] = price.ln();
+    }
+    let fit = ols(&log_first, &x)?;
+
+    Ok(EngleGrangerFit {
+        hedge_ratio: fit.params[1],
+        intercept: fit.params[0],
+        spread: fit.residuals,
+    })
+}
+
+pub fn log_spread(first: f64, second: f64, hedge_ratio: f64, intercept: f64) -> f64 {
+    first.ln() - hedge_ratio * second.ln() - intercept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engle_granger_recovers_hedge_ratio() -> Result<()> {
+        let second: Vec<f64> = (0..300).map(|i| 100.0 + 20.0 * (i as f64 / 17.0).sin() + 0.1 * i as f64).collect();
+        let first: Vec<f64> = second.iter().enumerate()
+            .map(|(i, price)| (0.3 + 1.7 * price.ln() + 0.001 * (i as f64 * 1.3).cos()).exp())
+            .collect();
+
+        let fit = engle_granger(&first, &second)?;
+
+        assert!((fit.hedge_ratio - 1.7).abs() < 1e-2);
+        assert!((fit.intercept - 0.3).abs() < 5e-2);
+        let last = first.len() - 1;
+        assert!((log_spread(first[last], second[last], fit.hedge_ratio, fit.intercept) - fit.spread[last]).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_engle_granger_rejects_non_positive_prices() {
+        assert!(engle_granger(&[1.0, 2.0, 0.0, 4.0], &[1.0, 2.0, 3.0, 4.0]).is_err());
+        assert!(engle_granger(&[1.0, 2.0, 3.0], &[1.0, 2.0]).is_err());
+    }
+}
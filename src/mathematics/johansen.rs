@@ -0,0 +1,330 @@
+use anyhow::{anyhow, Result};
+use ndarray::{s, Array1, Array2, Axis};
+use crate::mathematics::ols::ols;
+
+// Johansen cointegration test with an unrestricted constant, a port of
+// statsmodels.tsa.vector_ar.vecm.coint_johansen(endog, det_order=0, k_ar_diff).
+
+// MacKinnon, Haug and Michelis (1999) critical values at 90%, 95% and 99%, indexed by n - r - 1.
+const TRACE_CRITICAL_VALUES: [[f64; 3]; 6] = [
+    [2.7055, 3.8415, 6.6349],
+    [13.4294, 15.4943, 19.9349],
+    [27.0669, 29.7961, 35.4628],
+    [44.4929, 47.8545, 54.6815],
+    [65.8202, 69.8189, 77.8202],
+    [91.1090, 95.7542, 104.9637],
+];
+const MAX_EIGEN_CRITICAL_VALUES: [[f64; 3]; 6] = [
+    [2.7055, 3.8415, 6.6349],
+    [12.2971, 14.2639, 18.5200],
+    [18.8928, 21.1314, 25.8650],
+    [25.1236, 27.5858, 32.7172],
+    [31.2379, 33.8777, 39.3693],
+    [37.2786, 40.0763, 45.8662],
+];
+pub const JOHANSEN_MAX_SERIES: usize = 6;
+
+#[derive(Debug, Clone)]
+pub struct JohansenResult {
+    // Descending, one per series
+    pub eigenvalues: Vec<f64>,
+    // eigenvectors[i] is the cointegrating vector for eigenvalues[i], normalised so v' S_kk v = 1
+    pub eigenvectors: Vec<Vec<f64>>,
+    // trace_statistics[r] tests rank <= r against rank = n
+    pub trace_statistics: Vec<f64>,
+    // max_eigen_statistics[r] tests rank = r against rank = r + 1
+    pub max_eigen_statistics: Vec<f64>,
+    // 90%, 95%, 99% for each r
+    pub trace_critical_values: Vec<[f64; 3]>,
+    pub max_eigen_critical_values: Vec<[f64; 3]>,
+    pub nobs: usize,
+}
+
+impl JohansenResult {
+    // Number of cointegrating relations accepted by the sequential trace test at 95%
+    pub fn trace_rank(&self) -> usize {
+        (0..self.trace_statistics.len())
+            .find(|&r| self.trace_statistics[r] <= self.trace_critical_values[r][1])
+            .unwrap_or(self.trace_statistics.len())
+    }
+    pub fn max_eigen_rank(&self) -> usize {
+        (0..self.max_eigen_statistics.len())
+            .find(|&r| self.max_eigen_statistics[r] <= self.max_eigen_critical_values[r][1])
+            .unwrap_or(self.max_eigen_statistics.len())
+    }
+    // Strongest cointegrating vector scaled so the first series has weight 1
+    pub fn normalized_vector(&self) -> Vec<f64> {
+        let vector = &self.eigenvectors[0];
+        vector.iter().map(|v| v / vector[0]).collect()
+    }
+}
+
+// `series` is nobs x n, one column per asset.
+pub fn johansen(series: &Array2<f64>, k_ar_diff: usize) -> Result<JohansenResult> {
+    let (nobs, n) = series.dim();
+    if !(2..=JOHANSEN_MAX_SERIES).contains(&n) {
+        return Err(anyhow!("johansen: supports 2 to {} series, got {}", JOHANSEN_MAX_SERIES, n));
+    }
+    if nobs < n * (k_ar_diff + 1) + 10 {
+        return Err(anyhow!("johansen: series is too short ({})", nobs));
+    }
+    if series.iter().any(|x| !x.is_finite()) {
+        return Err(anyhow!("johansen: series contains non-finite values"));
+    }
+
+    let levels = demean(series);
+    let diff = &levels.slice(s![1.., ..]) - &levels.slice(s![..-1, ..]);
+
+    // Rows t = k_ar_diff..diff.len(): dy[t], y[t-1] and the lagged differences dy[t-1..t-k_ar_diff]
+    let rows = diff.nrows() - k_ar_diff;
+    let dy = demean(&diff.slice(s![k_ar_diff.., ..]).to_owned());
+    let lagged_level = demean(&levels.slice(s![k_ar_diff..k_ar_diff + rows, ..]).to_owned());
+    let mut lags = Array2::<f64>::zeros((rows, n * k_ar_diff));
+    for r in 0..rows {
+        for l in 1..=k_ar_diff {
+            for c in 0..n {
+                lags[[r, (l - 1) * n + c]] = diff[[k_ar_diff + r - l, c]];
+            }
+        }
+    }
+    let lags = demean(&lags);
+
+    let r0 = residualize(&dy, &lags)?;
+    let rk = residualize(&lagged_level, &lags)?;
+
+    let t = rows as f64;
+    let s00 = r0.t().dot(&r0) / t;
+    let s0k = r0.t().dot(&rk) / t;
+    let skk = rk.t().dot(&rk) / t;
+
+    // S_kk^-1 S_k0 S_00^-1 S_0k made symmetric through the Cholesky factor of S_kk
+    let l00 = cholesky(&s00)?;
+    let lkk = cholesky(&skk)?;
+    let a = lower_solve(&l00, &s0k);
+    let sig = a.t().dot(&a);
+    let lkk_inv = lower_solve(&lkk, &Array2::eye(n));
+    let c = lkk_inv.dot(&sig).dot(&lkk_inv.t());
+    let (values, vectors) = jacobi_eigen(&c);
+    let vectors = lkk_inv.t().dot(&vectors);
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| values[j].total_cmp(&values[i]));
+    let eigenvalues: Vec<f64> = order.iter().map(|&i| values[i].clamp(0.0, 1.0 - 1e-12)).collect();
+    let eigenvectors: Vec<Vec<f64>> = order.iter().map(|&i| vectors.column(i).to_vec()).collect();
+
+    let mut trace_statistics = Vec::with_capacity(n);
+    let mut max_eigen_statistics = Vec::with_capacity(n);
+    let mut trace_critical_values = Vec::with_capacity(n);
+    let mut max_eigen_critical_values = Vec::with_capacity(n);
+    for r in 0..n {
+        trace_statistics.push(-t * eigenvalues[r..].iter().map(|l| (1.0 - l).ln()).sum::<f64>());
+        max_eigen_statistics.push(-t * (1.0 - eigenvalues[r]).ln());
+        trace_critical_values.push(TRACE_CRITICAL_VALUES[n - r - 1]);
+        max_eigen_critical_values.push(MAX_EIGEN_CRITICAL_VALUES[n - r - 1]);
+    }
+
+    Ok(JohansenResult {
+        eigenvalues,
+        eigenvectors,
+        trace_statistics,
+        max_eigen_statistics,
+        trace_critical_values,
+        max_eigen_critical_values,
+        nobs: rows,
+    })
+}
+
+fn demean(x: &Array2<f64>) -> Array2<f64> {
+    match x.mean_axis(Axis(0)) {
+        Some(mean) => x - &mean,
+        None => x.clone(),
+    }
+}
+
+// Residuals of every column of y regressed on x
+fn residualize(y: &Array2<f64>, x: &Array2<f64>) -> Result<Array2<f64>> {
+    if x.ncols() == 0 {
+        return Ok(y.clone());
+    }
+    let mut residuals = Array2::<f64>::zeros(y.dim());
+    for c in 0..y.ncols() {
+        let fit = ols(&y.column(c).to_vec(), x)?;
+        residuals.column_mut(c).assign(&Array1::from(fit.residuals));
+    }
+    Ok(residuals)
+}
+
+fn cholesky(a: &Array2<f64>) -> Result<Array2<f64>> {
+    let n = a.nrows();
+    let mut l = Array2::<f64>::zeros((n, n));
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[[i, k]] * l[[j, k]]).sum();
+            if i == j {
+                let d = a[[i, i]] - sum;
+                if d <= 0.0 {
+                    return Err(anyhow!("johansen: covariance matrix is not positive definite"));
+                }
+                l[[i, j]] = d.sqrt();
+            } else {
+                l[[i, j]] = (a[[i, j]] - sum) / l[[j, j]];
+            }
+        }
+    }
+    Ok(l)
+}
+
+// L^-1 B for lower triangular L
+fn lower_solve(l: &Array2<f64>, b: &Array2<f64>) -> Array2<f64> {
+    let n = l.nrows();
+    let mut x = Array2::<f64>::zeros(b.dim());
+    for c in 0..b.ncols() {
+        for i in 0..n {
+            let sum: f64 = (0..i).map(|k| l[[i, k]] * x[[k, c]]).sum();
+            x[[i, c]] = (b[[i, c]] - sum) / l[[i, i]];
+        }
+    }
+    x
+}
+
+// Cyclic Jacobi rotations for a small symmetric matrix, eigenvectors in columns
+fn jacobi_eigen(a: &Array2<f64>) -> (Vec<f64>, Array2<f64>) {
+    let n = a.nrows();
+    let mut a = a.clone();
+    let mut v = Array2::<f64>::eye(n);
+    for _ in 0..100 {
+        let off: f64 = (0..n).flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[[i, j]] * a[[i, j]])
+            .sum();
+        if off < 1e-22 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[[p, q]].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[[q, q]] - a[[p, p]]) / (2.0 * a[[p, q]]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let akp = a[[k, p]];
+                    let akq = a[[k, q]];
+                    a[[k, p]] = c * akp - s * akq;
+                    a[[k, q]] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let apk = a[[p, k]];
+                    let aqk = a[[q, k]];
+                    a[[p, k]] = c * apk - s * aqk;
+                    a[[q, k]] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let vkp = v[[k, p]];
+                    let vkq = v[[k, q]];
+                    v[[k, p]] = c * vkp - s * vkq;
+                    v[[k, q]] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[[i, i]]).collect(), v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathematics::adf::{adfuller, AdfAutoLag, AdfRegression};
+
+    fn noise(seed: u64, len: usize) -> Vec<f64> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        }).collect()
+    }
+
+    fn random_walk(seed: u64, len: usize) -> Vec<f64> {
+        noise(seed, len).iter().scan(0.0, |level, step| {
+            *level += step;
+            Some(*level)
+        }).collect()
+    }
+
+    fn columns(series: &[Vec<f64>]) -> Array2<f64> {
+        let mut x = Array2::<f64>::zeros((series[0].len(), series.len()));
+        for (c, column) in series.iter().enumerate() {
+            x.column_mut(c).assign(&Array1::from(column.clone()));
+        }
+        x
+    }
+
+    #[test]
+    fn test_johansen_finds_one_relation_in_basket() -> Result<()> {
+        let len = 600;
+        let a = random_walk(1, len);
+        let b = random_walk(2, len);
+        let stationary = noise(3, len);
+        // c - 0.5 a - 2 b is stationary, i.e. (1, 4, -2) after normalising on a
+        let c: Vec<f64> = (0..len).map(|i| 0.5 * a[i] + 2.0 * b[i] + stationary[i]).collect();
+
+        let result = johansen(&columns(&[a.clone(), b.clone(), c.clone()]), 1)?;
+
+        assert_eq!(result.trace_rank(), 1);
+        assert_eq!(result.max_eigen_rank(), 1);
+        let weights = result.normalized_vector();
+        assert!((weights[1] - 4.0).abs() < 0.1);
+        assert!((weights[2] + 2.0).abs() < 0.1);
+
+        let spread: Vec<f64> = (0..len).map(|i| weights[0] * a[i] + weights[1] * b[i] + weights[2] * c[i]).collect();
+        assert!(adfuller(&spread, None, AdfRegression::Constant, Some(AdfAutoLag::Aic))?.p_value < 0.01);
+        Ok(())
+    }
+
+    #[test]
+    fn test_johansen_independent_walks_have_rank_zero() -> Result<()> {
+        let result = johansen(&columns(&[random_walk(11, 500), random_walk(12, 500), random_walk(13, 500)]), 1)?;
+
+        assert_eq!(result.trace_rank(), 0);
+        assert!(result.eigenvalues.windows(2).all(|w| w[0] >= w[1]));
+        // With a single remaining eigenvalue both statistics are the same test
+        assert!((result.trace_statistics[2] - result.max_eigen_statistics[2]).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs tests/fixtures/statsmodels_johansen.json, generate it with tests/fixtures/statsmodels_golden.py"]
+    fn test_johansen_matches_statsmodels() -> Result<()> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/statsmodels_johansen.json");
+        let fixture: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let floats = |value: &serde_json::Value| -> Vec<f64> { value.as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect() };
+        let close = |actual: &[f64], expected: &[f64]| {
+            actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-6 * e.abs().max(1.0))
+        };
+
+        for expected in fixture["cases"].as_array().unwrap() {
+            let series: Vec<Vec<f64>> = expected["series"].as_array().unwrap().iter().map(floats).collect();
+            let result = johansen(&columns(&series), expected["k_ar_diff"].as_u64().unwrap() as usize)?;
+            let name = expected["name"].as_str().unwrap();
+
+            assert!(close(&result.eigenvalues, &floats(&expected["eigenvalues"])), "{} eigenvalues {:?}", name, result.eigenvalues);
+            assert!(close(&result.trace_statistics, &floats(&expected["trace_statistics"])), "{} trace {:?}", name, result.trace_statistics);
+            assert!(close(&result.max_eigen_statistics, &floats(&expected["max_eigen_statistics"])), "{} max-eig {:?}", name, result.max_eigen_statistics);
+            for (actual, critical) in result.trace_critical_values.iter().zip(expected["trace_critical_values"].as_array().unwrap()) {
+                assert!(close(actual, &floats(critical)), "{} trace critical {:?}", name, actual);
+            }
+            for (actual, critical) in result.max_eigen_critical_values.iter().zip(expected["max_eigen_critical_values"].as_array().unwrap()) {
+                assert!(close(actual, &floats(critical)), "{} max-eig critical {:?}", name, actual);
+            }
+            assert!(close(&result.normalized_vector(), &floats(&expected["normalized_vector"])), "{} vector {:?}", name, result.normalized_vector());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_johansen_rejects_unsupported_shapes() {
+        assert!(johansen(&columns(&[random_walk(1, 200)]), 1).is_err());
+        assert!(johansen(&columns(&[random_walk(1, 10), random_walk(2, 10)]), 1).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Result};
+use ndarray::Array2;
+use crate::mathematics::ols::ols;
+
+// Kwiatkowski-Phillips-Schmidt-Shin test, a port of statsmodels.tsa.stattools.kpss.
+// The null hypothesis is stationarity, so a small p-value argues against it.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KpssRegression {
+    Constant,
+    ConstantTrend,
+}
+
+#[derive(Debug, Clone)]
+pub struct KpssResult {
+    pub statistic: f64,
+    // Interpolated from the critical value table, so it is clamped to [0.01, 0.10]
+    pub p_value: f64,
+    pub lags: usize,
+    // 10%, 5%, 2.5%, 1%
+    pub critical_values: [f64; 4],
+}
+
+const KPSS_P_VALUES: [f64; 4] = [0.10, 0.05, 0.025, 0.01];
+
+pub fn kpss(series: &[f64], regression: KpssRegression, lags: Option<usize>) -> Result<KpssResult> {
+    let nobs = series.len();
+    if nobs < 3 {
+        return Err(anyhow!("kpss: series is too short ({})", nobs));
+    }
+    if series.iter().any(|x| !x.is_finite()) {
+        return Err(anyhow!("kpss: series contains non-finite values"));
+    }
+
+    let (residuals, critical_values) = match regression {
+        KpssRegression::Constant => {
+            let mean = series.iter().sum::<f64>() / nobs as f64;
+            (series.iter().map(|x| x - mean).collect::<Vec<f64>>(), [0.347, 0.463, 0.574, 0.739])
+        }
+        KpssRegression::ConstantTrend => {
+            let mut x = Array2::<f64>::zeros((nobs, 2));
+            for i in 0..nobs {
+                x[[i, 0]] = 1.0;
+                x[[i, 1]] = (i + 1) as f64;
+            }
+            (ols(series, &x)?.residuals, [0.119, 0.146, 0.176, 0.216])
+        }
+    };
+    if residuals.iter().all(|e| *e == 0.0) {
+        return Err(anyhow!("kpss: series has no variation around the deterministic terms"));
+    }
+
+    let lags = match lags {
+        Some(lags) => lags.min(nobs - 1),
+        None => kpss_autolag(&residuals).min(nobs - 1),
+    };
+
+    let mut partial_sum = 0.0;
+    let mut eta = 0.0;
+    for e in &residuals {
+        partial_sum += e;
+        eta += partial_sum * partial_sum;
+    }
+    eta /= (nobs * nobs) as f64;
+
+    let statistic = eta / long_run_variance(&residuals, lags);
+
+    Ok(KpssResult {
+        statistic,
+        p_value: interpolate_p_value(statistic, &critical_values),
+        lags,
+        critical_values,
+    })
+}
+
+// Newey-West estimate with Bartlett weights
+fn long_run_variance(residuals: &[f64], lags: usize) -> f64 {
+    let nobs = residuals.len();
+    let mut s_hat: f64 = residuals.iter().map(|e| e * e).sum();
+    for i in 1..=lags {
+        s_hat += 2.0 * autocovariance_sum(residuals, i) * (1.0 - i as f64 / (lags as f64 + 1.0));
+    }
+    s_hat / nobs as f64
+}
+
+// Data-dependent bandwidth of Hobijn, Franses and Ooms (1998)
+fn kpss_autolag(residuals: &[f64]) -> usize {
+    let nobs = residuals.len();
+    let cov_lags = (nobs as f64).powf(2.0 / 9.0) as usize;
+    let mut s0 = residuals.iter().map(|e| e * e).sum::<f64>() / nobs as f64;
+    let mut s1 = 0.0;
+    for i in 1..=cov_lags.min(nobs - 1) {
+        let product = autocovariance_sum(residuals, i) / (nobs as f64 / 2.0);
+        s0 += product;
+        s1 += i as f64 * product;
+    }
+    let s_hat = s1 / s0;
+    let gamma_hat = 1.1447 * (s_hat * s_hat).powf(1.0 / 3.0);
+    (gamma_hat * (nobs as f64).powf(1.0 / 3.0)) as usize
+}
+
+fn autocovariance_sum(residuals: &[f64], lag: usize) -> f64 {
+    residuals[lag..].iter().zip(residuals.iter()).map(|(a, b)| a * b).sum()
+}
+
+// Linear interpolation in the critical value table, clamped at the ends like numpy.interp
+fn interpolate_p_value(statistic: f64, critical_values: &[f64; 4]) -> f64 {
+    if statistic <= critical_values[0] {
+        return KPSS_P_VALUES[0];
+    }
+    for i in 1..critical_values.len() {
+        if statistic <= critical_values[i] {
+            let weight = (statistic - critical_values[i - 1]) / (critical_values[i] - critical_values[i - 1]);
+            return KPSS_P_VALUES[i - 1] + weight * (KPSS_P_VALUES[i] - KPSS_P_VALUES[i - 1]);
+        }
+    }
+    KPSS_P_VALUES[KPSS_P_VALUES.len() - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const SUNSPOTS: &str = include_str!("../../tests/fixtures/statsmodels_sunspots.json");
+
+    #[test]
+    fn test_kpss_matches_statsmodels_sunspots() -> Result<()> {
+        let fixture: Value = serde_json::from_str(SUNSPOTS)?;
+        let expected = &fixture["kpss"];
+        let series: Vec<f64> = fixture["series"].as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect();
+
+        let result = kpss(&series, KpssRegression::Constant, None)?;
+
+        assert!((result.statistic - expected["statistic"].as_f64().unwrap()).abs() < 1e-6);
+        assert!((result.p_value - expected["p_value"].as_f64().unwrap()).abs() < 1e-6);
+        assert_eq!(result.lags as u64, expected["lags"].as_u64().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_kpss_p_value_is_clamped_to_table() {
+        let critical_values = [0.347, 0.463, 0.574, 0.739];
+        assert_eq!(interpolate_p_value(0.1, &critical_values), 0.10);
+        assert_eq!(interpolate_p_value(5.0, &critical_values), 0.01);
+        assert!((interpolate_p_value(0.463, &critical_values) - 0.05).abs() < 1e-12);
+    }
+}
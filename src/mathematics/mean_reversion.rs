@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Result};
+use ndarray::Array2;
+use crate::core::types::PairData;
+use crate::mathematics::ols::ols;
+
+// Ornstein-Uhlenbeck half-life in bars from the AR(1) fit y[t] = c + phi * y[t-1].
+// A series without mean reversion (phi >= 1) has an infinite half-life.
+pub fn half_life(series: &[f64]) -> Result<f64> {
+    let n = series.len();
+    if n < 3 {
+        return Err(anyhow!("half_life: series is too short ({})", n));
+    }
+    let mut x = Array2::<f64>::zeros((n - 1, 2));
+    for t in 0..n - 1 {
+        x[[t, 0]] = 1.0;
+        x[[t, 1]] = series[t];
+    }
+    let phi = ols(&series[1..], &x)?.params[1];
+    if phi >= 1.0 {
+        return Ok(f64::INFINITY);
+    }
+    if phi <= 0.0 {
+        // Overshoots the mean every bar
+        return Ok(0.0);
+    }
+    Ok(-std::f64::consts::LN_2 / phi.ln())
+}
+
+// Hurst exponent from the scaling of lagged differences, std(y[t+lag] - y[t]) ~ lag^H.
+// About 0.5 for a random walk, below 0.5 for a mean-reverting series.
+pub fn hurst_exponent(series: &[f64]) -> Result<f64> {
+    let n = series.len();
+    let max_lag = (n / 2).min(100);
+    if max_lag < 4 {
+        return Err(anyhow!("hurst_exponent: series is too short ({})", n));
+    }
+    let mut log_lags = Vec::with_capacity(max_lag);
+    let mut log_std = Vec::with_capacity(max_lag);
+    for lag in 2..max_lag {
+        let diffs: Vec<f64> = series[lag..].iter().zip(series.iter()).map(|(a, b)| a - b).collect();
+        let mean = diffs.iter().sum::<f64>() / diffs.len() as f64;
+        let std = (diffs.iter().map(|d| (d - mean) * (d - mean)).sum::<f64>() / diffs.len() as f64).sqrt();
+        if std > 0.0 {
+            log_lags.push((lag as f64).ln());
+            log_std.push(std.ln());
+        }
+    }
+    if log_lags.len() < 2 {
+        return Err(anyhow!("hurst_exponent: series has no variation"));
+    }
+    let mut x = Array2::<f64>::zeros((log_lags.len(), 2));
+    for (i, log_lag) in log_lags.iter().enumerate() {
+        x[[i, 0]] = 1.0;
+        x[[i, 1]] = *log_lag;
+    }
+    Ok(ols(&log_std, &x)?.params[1])
+}
+
+// Strategy limits, None means the metric does not filter
+pub fn passes_mean_reversion_limits(pair_data: &PairData, max_half_life: Option<f32>, max_hurst: Option<f32>) -> bool {
+    let half_life_ok = match max_half_life {
+        Some(limit) => pair_data.half_life <= limit,
+        None => true,
+    };
+    let hurst_ok = match max_hurst {
+        Some(limit) => pair_data.hurst <= limit,
+        None => true,
+    };
+    half_life_ok && hurst_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::TimeInterval;
+
+    fn noise(len: usize) -> Vec<f64> {
+        let mut state: u64 = 7;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        }).collect()
+    }
+
+    #[test]
+    fn test_half_life_of_ar1_process() -> Result<()> {
+        // phi = 0.9 gives a half-life of ln(2) / -ln(0.9) ~ 6.58 bars
+        let shocks = noise(5000);
+        let series: Vec<f64> = shocks.iter().scan(0.0, |level, shock| {
+            *level = 0.9 * *level + shock;
+            Some(*level)
+        }).collect();
+        let walk: Vec<f64> = shocks.iter().scan(0.0, |level, shock| {
+            *level += shock + 0.01;
+            Some(*level)
+        }).collect();
+
+        assert!((half_life(&series)? - 6.58).abs() < 0.5);
+        assert!(half_life(&walk)? > 100.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hurst_separates_walk_from_noise() -> Result<()> {
+        let shocks = noise(3000);
+        let walk: Vec<f64> = shocks.iter().scan(0.0, |level, shock| {
+            *level += shock;
+            Some(*level)
+        }).collect();
+
+        assert!((hurst_exponent(&walk)? - 0.5).abs() < 0.1);
+        assert!(hurst_exponent(&shocks)? < 0.1);
+        assert!(hurst_exponent(&[1.0; 300]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mean_reversion_limits() {
+        let mut pair_data = PairData::new(TimeInterval::h4, 1);
+        pair_data.half_life = 30.0;
+        pair_data.hurst = 0.35;
+
+        assert!(passes_mean_reversion_limits(&pair_data, None, None));
+        assert!(passes_mean_reversion_limits(&pair_data, Some(40.0), Some(0.4)));
+        assert!(!passes_mean_reversion_limits(&pair_data, Some(20.0), None));
+        assert!(!passes_mean_reversion_limits(&pair_data, None, Some(0.3)));
+    }
+}
@@ -0,0 +1,529 @@
+pub mod adf;
+pub mod alignment;
+pub mod engle_granger;
+pub mod johansen;
+pub mod kpss;
+pub mod mean_reversion;
+mod ols;
+pub mod phillips_perron;
+pub mod rolling;
+pub mod stationarity;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use crate::core::types::{ConfigId, PairData, Candle, TimeInterval, TradingStrategy, HedgeMode, SpreadKind};
+use ndarray::{Array1, Array2, s};
+use crate::mathematics::adf::AdfRegression;
+use crate::mathematics::alignment::{align_basket_legs, align_legs, passes_basket_gap_limit, passes_gap_limit};
+use crate::mathematics::engle_granger::{engle_granger, log_spread};
+use crate::mathematics::johansen::johansen;
+use crate::mathematics::kpss::{kpss, KpssRegression};
+use crate::mathematics::mean_reversion::{half_life, hurst_exponent};
+use crate::mathematics::phillips_perron::phillips_perron;
+use crate::mathematics::rolling::{rolling_z_scores, RunningStats};
+use crate::mathematics::stationarity::{NativeAdf, StationarityTest};
+
+
+pub fn dickey_fuller_test(series: Vec<f32>) -> Result<f32> {
+    NativeAdf::default().p_value(&series)
+}
+pub fn calculate_z_score_ndarray(data: &[f32], period: usize) -> Result<Vec<f32>> {
+    rolling_z_scores(data, period)
+}
+// Each intraday candle stands in for the bar whose open/close times contain it, the other period - 1 bars
+// of the window are the same for every candle of that bar, so their statistics are computed once per bar.
+// A candle after the close of the last bar belongs to the bar that is still forming.
+pub fn calculate_z_score_minutes_from_bars(bars: &[Candle], minutes: &[Candle], period: usize) -> Result<Vec<f32>> {
+    if period == 0 {
+        return Err(anyhow!("z-score period must be positive"));
+    }
+    if bars.is_empty() {
+        return Err(anyhow!("the bar array is empty"));
+    }
+    let mut z_score_minutes: Vec<f32> = vec![0.0; minutes.len()];
+    let mut current_position = None;
+    let mut base = RunningStats::default();
+
+    for (i, minute) in minutes.iter().enumerate() {
+        let opened = bars.partition_point(|bar| bar.open_time <= minute.open_time);
+        if opened == 0 {
+            return Err(anyhow!("intraday candle at {} is older than the first bar", minute.open_time));
+        }
+        let position = if minute.open_time <= bars[opened - 1].close_time { opened - 1 } else { opened };
+        if current_position != Some(position) {
+            if position + 1 < period {
+                return Err(anyhow!("the array length is less than the period"));
+            }
+            let window: Vec<f64> = bars[position + 1 - period..position].iter().map(|bar| bar.price).collect();
+            base = RunningStats::from_values(&window);
+            current_position = Some(position);
+        }
+
+        let price = minute.price;
+        z_score_minutes[i] = base.with(price).z_score(price) as f32;
+    }
+
+    Ok(z_score_minutes)
+}
+pub fn calculate_z_score_minutes(data_h4: &[f32], period: usize) -> Result<f32> {
+    if data_h4.len() < period {
+        return Err(anyhow!("the array length is less than the period"));
+    }
+
+    // Convert from f32 to f64 for better precision
+    let arr_f64: Array1<f64> = Array1::from(data_h4.iter().map(|&x| x as f64).collect::<Vec<_>>());
+
+    // Take the last 'period' elements for calculation
+    let window = arr_f64.slice(s![arr_f64.len() - period..]);
+    let mean = window.mean().ok_or(anyhow!("Error with mean"))?;
+    let std_dev = window.std(0.0); // Population std dev
+
+    let last_value = arr_f64[arr_f64.len() - 1];
+
+    // Calculate Z-score
+    let zscore_f64 = if std_dev != 0.0 {
+        (last_value - mean) / std_dev
+    } else {
+        0.0
+    };
+
+    // Convert back to f32
+    Ok(zscore_f64 as f32)
+}
+
+pub fn calculate_z_score(data: &[f32], period: usize) -> Result<Vec<f32>> {
+    if data.len() < period {
+        return Err(anyhow!("the array length is less than the period"));
+    }
+    rolling_z_scores(data, period)
+}
+// Synthetic series of the two legs in f64, see SpreadKind.
+// `bases` are the leg prices at the start of the h4 window, only the normalized spread uses them
+fn synthetic_series(first_market:&[Candle], second_market:&[Candle], spread_kind:SpreadKind, hedge_ratio:f64, hedge_intercept:f64, bases:(f64, f64)) -> Vec<f64> {
+    first_market.iter().zip(second_market.iter()).map(|(first, second)| {
+        let (first, second) = (first.price, second.price);
+        match spread_kind {
+            SpreadKind::PriceRatio => first / second,
+            SpreadKind::LogRatio => first.ln() - second.ln(),
+            SpreadKind::LogSpread => log_spread(first, second, hedge_ratio, hedge_intercept),
+            SpreadKind::NormalizedSpread => first / bases.0 - hedge_ratio * second / bases.1,
+        }
+    }).collect()
+}
+fn spread_bases(spread_kind:SpreadKind, first_market_h4:&[Candle], second_market_h4:&[Candle]) -> Result<(f64, f64)> {
+    if spread_kind != SpreadKind::NormalizedSpread {
+        return Ok((1.0, 1.0));
+    }
+    match (first_market_h4.first(), second_market_h4.first()) {
+        (Some(first), Some(second)) if first.price > 0.0 && second.price > 0.0 => Ok((first.price, second.price)),
+        _ => Err(anyhow!("no positive h4 price to rebase the spread to")),
+    }
+}
+// The legs are joined on open_time first, None when they have more gaps than the strategy allows
+pub fn calculate_synthetic_pair_data_h4(first_market_h4:&[Candle], second_market_h4:&[Candle],previous:&PairData, strategy:&TradingStrategy, time_frame: TimeInterval, config_id: ConfigId) -> Result<Option<PairData>> {
+    let legs=align_legs(first_market_h4, second_market_h4, strategy.gap_policy)?;
+    if !passes_gap_limit(&legs, strategy.max_gap_percent) {
+        return Ok(None);
+    }
+    let spread_kind=strategy.spread_kind;
+    let bases=spread_bases(spread_kind, &legs.first, &legs.second)?;
+    let series=synthetic_series(&legs.first, &legs.second, spread_kind, previous.hedge_ratio as f64, previous.hedge_intercept as f64, bases);
+    let result_h4:Vec<f32>=series.iter().map(|&x| x as f32).collect();
+    let z_score_h4 = calculate_z_score(&result_h4,strategy.z_score_period as usize)?;
+    let candles_h4=series_candles(&result_h4, &legs.first);
+    Ok(Some(PairData {spread_kind, repaired_bars:legs.repaired as i32, candles:candles_h4, z_score:z_score_h4, candles_minutes:Vec::new(), z_score_minutes:Vec::new(), time_interval:time_frame, configuration_id:config_id, ..previous.clone()}))
+}
+pub fn calculate_synthetic_pair_data_with_dickeyfuller(first_market_h4:&[Candle], second_market_h4:&[Candle], stationarity_test: &dyn StationarityTest, strategy:&TradingStrategy, time_frame: TimeInterval, config_id: ConfigId) -> Result<Option<PairData>> {
+    let legs=align_legs(first_market_h4, second_market_h4, strategy.gap_policy)?;
+    if !passes_gap_limit(&legs, strategy.max_gap_percent) {
+        return Ok(None);
+    }
+    let spread_kind=strategy.spread_kind;
+    let (hedge_ratio, hedge_intercept) = match strategy.hedge_mode {
+        HedgeMode::EngleGranger => {
+            strategy.check_hedge()?;
+            let first:Vec<f64>=legs.first.iter().map(|candle| candle.price).collect();
+            let second:Vec<f64>=legs.second.iter().map(|candle| candle.price).collect();
+            let fit=engle_granger(&first, &second)?;
+            (fit.hedge_ratio, fit.intercept)
+        }
+        HedgeMode::Ratio => (1.0, 0.0),
+    };
+    let bases=spread_bases(spread_kind, &legs.first, &legs.second)?;
+    let series=synthetic_series(&legs.first, &legs.second, spread_kind, hedge_ratio, hedge_intercept, bases);
+    let result_h4:Vec<f32>=series.iter().map(|&x| x as f32).collect();
+    // With Engle-Granger the test runs on the regression residuals. The p-value is the plain unit-root one,
+    // without the cointegration correction for the estimated hedge ratio, so it is somewhat optimistic.
+    let dickey=stationarity_test.p_value(&result_h4)?;
+    let kpss_p_value=kpss(&series, KpssRegression::Constant, None)?.p_value as f32;
+    let pp_p_value=phillips_perron(&series, AdfRegression::Constant, None)?.p_value as f32;
+    let half_life=half_life(&series)? as f32;
+    let hurst=hurst_exponent(&series)? as f32;
+    let z_score_h4 = calculate_z_score(&result_h4,strategy.z_score_period as usize)?;
+    let candles_h4=series_candles(&result_h4, &legs.first);
+    Ok(Some(PairData {stationarity:(1.0 - dickey) * 100.0, kpss_p_value, pp_p_value, hedge_ratio:hedge_ratio as f32, hedge_intercept:hedge_intercept as f32, spread_kind, half_life, hurst, repaired_bars:legs.repaired as i32, candles:candles_h4, z_score:z_score_h4, candles_minutes:Vec::new(),z_score_minutes:Vec::new(), time_interval:time_frame,configuration_id:config_id}))
+}
+pub fn calculate_synthetic_pair_data_minutes(pair_data:&PairData, first_leg:&PairData, second_leg:&PairData, strategy:&TradingStrategy, time_frame: TimeInterval, config_id: ConfigId) -> Result<Option<PairData>> {
+    if first_leg.candles_minutes.is_empty() || second_leg.candles_minutes.is_empty(){
+        return Err(anyhow!("all empty all candels"));
+    }
+    let minutes=align_legs(&first_leg.candles_minutes, &second_leg.candles_minutes, strategy.gap_policy)?;
+    if !passes_gap_limit(&minutes, strategy.max_gap_percent) {
+        return Ok(None);
+    }
+    // Same representation and rebasing as the bar series the minutes are scored against
+    let bars=align_legs(&first_leg.candles, &second_leg.candles, strategy.gap_policy)?;
+    let bases=spread_bases(pair_data.spread_kind, &bars.first, &bars.second)?;
+    let series=synthetic_series(&minutes.first, &minutes.second, pair_data.spread_kind, pair_data.hedge_ratio as f64, pair_data.hedge_intercept as f64, bases);
+    let result_minutes:Vec<f32>=series.iter().map(|&x| x as f32).collect();
+    let candles_minutes=series_candles(&result_minutes, &minutes.first);
+    let z_score_minutes = calculate_z_score_minutes_from_bars(&pair_data.candles, &candles_minutes, strategy.z_score_period as usize)?;
+    Ok(Some(PairData {candles_minutes, z_score_minutes, time_interval:time_frame, configuration_id:config_id, ..pair_data.clone()}))
+}
+// Weighted sum of log prices of the basket legs, joined on open_time
+fn basket_series(legs:&[Vec<Candle>], weights:&[f32]) -> Vec<f32> {
+    let len=legs.iter().map(|leg| leg.len()).min().unwrap_or(0);
+    let mut result:Vec<f32>=Vec::with_capacity(len);
+    for i in 0..len{
+        let value:f64=legs.iter().zip(weights.iter()).map(|(leg, weight)| *weight as f64 * leg[i].price.ln()).sum();
+        result.push(value as f32);
+    }
+    result
+}
+// Synthetic candles only carry the series value as the price, OHLCV of a spread is not defined
+fn series_candles(series:&[f32], times:&[Candle]) -> Vec<Candle> {
+    let mut candles: Vec<Candle> = Vec::with_capacity(series.len());
+    for i in 0..series.len(){
+        candles.push(Candle {price:series[i] as f64, close_time:times[i].close_time,open_time:times[i].open_time, ..Candle::default() });
+    }
+    candles
+}
+// Johansen over the log prices of the legs joined on open_time. Returns None when the trace test finds no
+// cointegrating relation or the legs have more gaps than the strategy allows.
+pub fn calculate_basket_data_with_johansen(legs_h4:&[&[Candle]], stationarity_test: &dyn StationarityTest, strategy:&TradingStrategy, time_frame: TimeInterval, config_id: ConfigId) -> Result<Option<(Vec<f32>, PairData)>> {
+    let aligned=align_basket_legs(legs_h4, strategy.gap_policy)?;
+    if !passes_basket_gap_limit(&aligned, strategy.max_gap_percent) {
+        return Ok(None);
+    }
+    let legs_h4=&aligned.legs;
+    let mut log_prices=Array2::<f64>::zeros((legs_h4[0].len(), legs_h4.len()));
+    for (c, leg) in legs_h4.iter().enumerate(){
+        for (i, candle) in leg.iter().enumerate(){
+            if candle.price<=0.0{
+                return Err(anyhow!("non-positive price in basket leg"));
+            }
+            log_prices[[i, c]]=candle.price.ln();
+        }
+    }
+    let result=johansen(&log_prices, 1)?;
+    if result.trace_rank()==0{
+        return Ok(None);
+    }
+    let weights:Vec<f32>=result.normalized_vector().iter().map(|&w| w as f32).collect();
+
+    let result_h4=basket_series(legs_h4, &weights);
+    let dickey=stationarity_test.p_value(&result_h4)?;
+    let series_f64:Vec<f64>=result_h4.iter().map(|&x| x as f64).collect();
+    let kpss_p_value=kpss(&series_f64, KpssRegression::Constant, None)?.p_value as f32;
+    let pp_p_value=phillips_perron(&series_f64, AdfRegression::Constant, None)?.p_value as f32;
+    let z_score_h4 = calculate_z_score(&result_h4,strategy.z_score_period as usize)?;
+    let candles_h4=series_candles(&result_h4, &legs_h4[0]);
+    let mut pair_data=PairData::new(time_frame, config_id);
+    pair_data.repaired_bars=aligned.repaired as i32;
+    pair_data.stationarity=(1.0 - dickey) * 100.0;
+    pair_data.spread_kind=SpreadKind::LogSpread;
+    pair_data.kpss_p_value=kpss_p_value;
+    pair_data.pp_p_value=pp_p_value;
+    pair_data.half_life=half_life(&series_f64)? as f32;
+    pair_data.hurst=hurst_exponent(&series_f64)? as f32;
+    pair_data.candles=candles_h4;
+    pair_data.z_score=z_score_h4;
+    Ok(Some((weights, pair_data)))
+}
+// None when the legs have more gaps than the strategy allows
+pub fn calculate_basket_data_h4(legs_h4:&[&[Candle]], weights:&[f32], previous:&PairData, strategy:&TradingStrategy, time_frame: TimeInterval, config_id: ConfigId) -> Result<Option<PairData>> {
+    let aligned=align_basket_legs(legs_h4, strategy.gap_policy)?;
+    if !passes_basket_gap_limit(&aligned, strategy.max_gap_percent) {
+        return Ok(None);
+    }
+    let result_h4=basket_series(&aligned.legs, weights);
+    let z_score_h4 = calculate_z_score(&result_h4,strategy.z_score_period as usize)?;
+    let candles_h4=series_candles(&result_h4, &aligned.legs[0]);
+    Ok(Some(PairData {repaired_bars:aligned.repaired as i32, candles:candles_h4, z_score:z_score_h4, candles_minutes:Vec::new(), z_score_minutes:Vec::new(), time_interval:time_frame, configuration_id:config_id, ..previous.clone()}))
+}
+pub fn calculate_basket_data_minutes(pair_data:&PairData, legs_minutes:&[&[Candle]], weights:&[f32], strategy:&TradingStrategy, time_frame: TimeInterval, config_id: ConfigId) -> Result<Option<PairData>> {
+    if legs_minutes.iter().any(|leg| leg.is_empty()){
+        return Err(anyhow!("all empty all candels"));
+    }
+    let aligned=align_basket_legs(legs_minutes, strategy.gap_policy)?;
+    if !passes_basket_gap_limit(&aligned, strategy.max_gap_percent) {
+        return Ok(None);
+    }
+    let result_minutes=basket_series(&aligned.legs, weights);
+    let candles_minutes=series_candles(&result_minutes, &aligned.legs[0]);
+    let z_score_minutes = calculate_z_score_minutes_from_bars(&pair_data.candles, &candles_minutes, strategy.z_score_period as usize)?;
+    Ok(Some(PairData {candles_minutes, z_score_minutes, time_interval:time_frame, configuration_id:config_id, ..pair_data.clone()}))
+}
+
+// Live between two closes the intraday candles are fed one by one: each new candle joins the window and is
+// scored against the bars of the close like in calculate_z_score_minutes_from_bars, the oldest one leaves.
+// Candles not after the last one of the window are skipped, an empty window is left empty.
+// Returns the number of appended candles.
+pub fn push_minute_candles(pair_data:&mut PairData, minutes:&[Candle], period:usize) -> Result<usize> {
+    let window=pair_data.candles_minutes.len();
+    let mut pushed=0;
+    for minute in minutes {
+        match pair_data.candles_minutes.last() {
+            Some(last) if minute.open_time > last.open_time => {}
+            _ => continue,
+        }
+        let z_score=calculate_z_score_minutes_from_bars(&pair_data.candles, std::slice::from_ref(minute), period)?[0];
+        pair_data.candles_minutes.push(minute.clone());
+        pair_data.z_score_minutes.push(z_score);
+        pushed+=1;
+    }
+    let excess=pair_data.candles_minutes.len().saturating_sub(window);
+    pair_data.candles_minutes.drain(..excess);
+    let excess=pair_data.z_score_minutes.len().saturating_sub(window);
+    pair_data.z_score_minutes.drain(..excess);
+    Ok(pushed)
+}
+// The candles of every leg opened after `after` and joined on open_time, up to the first bar one leg is missing
+fn leg_minutes_after(after:NaiveDateTime, legs:&[&[Candle]]) -> Vec<Vec<Candle>> {
+    let mut joined:Vec<Vec<Candle>>=vec![Vec::new(); legs.len()];
+    let Some(first)=legs.first() else {
+        return joined;
+    };
+    let start=first.partition_point(|candle| candle.open_time <= after);
+    for candle in &first[start..] {
+        let found:Option<Vec<&Candle>>=legs.iter().map(|leg| {
+            leg.binary_search_by_key(&candle.open_time, |other| other.open_time).ok().map(|i| &leg[i])
+        }).collect();
+        let Some(found)=found else {
+            break;
+        };
+        for (column, candle) in joined.iter_mut().zip(found) {
+            column.push(candle.clone());
+        }
+    }
+    joined
+}
+// Synthetic intraday candles of the leg candles opened after the last one of `pair_data`, priced with the hedge
+// and the bases of the close
+pub fn synthetic_minutes_since(pair_data:&PairData, first_leg:&PairData, second_leg:&PairData, strategy:&TradingStrategy) -> Result<Vec<Candle>> {
+    let Some(last)=pair_data.candles_minutes.last() else {
+        return Ok(Vec::new());
+    };
+    let minutes=leg_minutes_after(last.open_time, &[&first_leg.candles_minutes, &second_leg.candles_minutes]);
+    if minutes[0].is_empty() {
+        return Ok(Vec::new());
+    }
+    let bars=align_legs(&first_leg.candles, &second_leg.candles, strategy.gap_policy)?;
+    let bases=spread_bases(pair_data.spread_kind, &bars.first, &bars.second)?;
+    let series=synthetic_series(&minutes[0], &minutes[1], pair_data.spread_kind, pair_data.hedge_ratio as f64, pair_data.hedge_intercept as f64, bases);
+    let result_minutes:Vec<f32>=series.iter().map(|&x| x as f32).collect();
+    Ok(series_candles(&result_minutes, &minutes[0]))
+}
+// Basket intraday candles of the leg candles opened after the last one of `pair_data`, with the weights of the close
+pub fn basket_minutes_since(pair_data:&PairData, legs_minutes:&[&[Candle]], weights:&[f32]) -> Vec<Candle> {
+    let Some(last)=pair_data.candles_minutes.last() else {
+        return Vec::new();
+    };
+    let minutes=leg_minutes_after(last.open_time, legs_minutes);
+    if minutes.is_empty() {
+        return Vec::new();
+    }
+    series_candles(&basket_series(&minutes, weights), &minutes[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::GapPolicy;
+    use crate::mathematics::stationarity::StubStationarity;
+    use crate::test_util::h4_candles;
+
+    // Consecutive H4 candles
+    fn candles(prices: &[f64]) -> Vec<Candle> {
+        h4_candles(&prices.iter().enumerate().map(|(i, &price)| (i as i64, price)).collect::<Vec<_>>())
+    }
+
+    fn legs(len: usize) -> (Vec<Candle>, Vec<Candle>) {
+        let second: Vec<f64> = (0..len).map(|i| 50.0 + 10.0 * (i as f64 / 13.0).sin() + 0.05 * i as f64).collect();
+        let first: Vec<f64> = second.iter().enumerate().map(|(i, price)| 3.0 * price.powf(1.4) * (1.0 + 0.01 * (i as f64 * 0.7).cos())).collect();
+        (candles(&first), candles(&second))
+    }
+
+    #[test]
+    fn test_synthetic_series_kinds() {
+        let (first, second) = (candles(&[10.0, 12.0]), candles(&[4.0, 2.0]));
+        let bases = (10.0, 4.0);
+
+        assert_eq!(synthetic_series(&first, &second, SpreadKind::PriceRatio, 1.0, 0.0, bases), vec![2.5, 6.0]);
+        let log_ratio = synthetic_series(&first, &second, SpreadKind::LogRatio, 2.0, 1.0, bases);
+        assert!((log_ratio[1] - 6.0f64.ln()).abs() < 1e-12);
+        let log_spread = synthetic_series(&first, &second, SpreadKind::LogSpread, 2.0, 1.0, bases);
+        assert!((log_spread[1] - (12.0f64.ln() - 2.0 * 2.0f64.ln() - 1.0)).abs() < 1e-12);
+        let normalized = synthetic_series(&first, &second, SpreadKind::NormalizedSpread, 2.0, 1.0, bases);
+        assert!((normalized[0] + 1.0).abs() < 1e-12);
+        assert!((normalized[1] - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_spread_kind_is_stored_and_hedged() -> Result<()> {
+        let (first, second) = legs(300);
+        let stub = StubStationarity::default();
+        let mut strategy = TradingStrategy::new();
+        strategy.hedge_mode = HedgeMode::EngleGranger;
+        strategy.z_score_period = 60;
+        // Minute candles equal to the last h4 ones, so both series must agree bar for bar
+        let mut first_leg = PairData::new(TimeInterval::h4, 1);
+        let mut second_leg = PairData::new(TimeInterval::h4, 1);
+        (first_leg.candles, first_leg.candles_minutes) = (first.clone(), first[200..].to_vec());
+        (second_leg.candles, second_leg.candles_minutes) = (second.clone(), second[200..].to_vec());
+
+        for spread_kind in [SpreadKind::PriceRatio, SpreadKind::LogRatio, SpreadKind::LogSpread, SpreadKind::NormalizedSpread] {
+            strategy.spread_kind = spread_kind;
+            strategy.hedge_mode = if spread_kind.is_hedged() { HedgeMode::EngleGranger } else { HedgeMode::Ratio };
+            let pair_data = calculate_synthetic_pair_data_with_dickeyfuller(&first, &second, &stub, &strategy, TimeInterval::h4, 1)?.unwrap();
+            assert_eq!(pair_data.spread_kind, spread_kind);
+            assert_eq!(pair_data.hedge_ratio != 1.0, spread_kind.is_hedged());
+            // Engle-Granger on a ratio would fit a hedge ratio the series never uses
+            strategy.hedge_mode = HedgeMode::EngleGranger;
+            assert_eq!(calculate_synthetic_pair_data_with_dickeyfuller(&first, &second, &stub, &strategy, TimeInterval::h4, 1).is_err(), !spread_kind.is_hedged());
+
+            let minutes = calculate_synthetic_pair_data_minutes(&pair_data, &first_leg, &second_leg, &strategy, TimeInterval::h4, 1)?.unwrap();
+            assert_eq!(minutes.candles_minutes.len(), 100);
+            for (minute, bar) in minutes.candles_minutes.iter().zip(pair_data.candles[200..].iter()) {
+                assert!((minute.price - bar.price).abs() < 1e-5);
+            }
+        }
+        strategy.hedge_mode = HedgeMode::Ratio;
+        strategy.spread_kind = SpreadKind::LogSpread;
+        let ratio = calculate_synthetic_pair_data_with_dickeyfuller(&first, &second, &stub, &strategy, TimeInterval::h4, 1)?.unwrap();
+        assert_eq!((ratio.hedge_ratio, ratio.hedge_intercept), (1.0, 0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gapped_legs_are_joined_on_open_time() -> Result<()> {
+        let (first, mut second) = legs(300);
+        // A listing gap of 5 bars and a single missing candle on the second leg
+        second.drain(100..105);
+        second.remove(200);
+        let mut strategy = TradingStrategy::new();
+        strategy.z_score_period = 60;
+        let previous = PairData::new(TimeInterval::h4, 1);
+
+        for (gap_policy, len) in [(GapPolicy::Drop, 294), (GapPolicy::ForwardFill, 300)] {
+            strategy.gap_policy = gap_policy;
+            let pair_data = calculate_synthetic_pair_data_h4(&first, &second, &previous, &strategy, TimeInterval::h4, 1)?.unwrap();
+            assert_eq!((pair_data.candles.len(), pair_data.z_score.len(), pair_data.repaired_bars), (len, len, 6));
+            for candle in &pair_data.candles {
+                let first_price = first.iter().find(|leg| leg.open_time == candle.open_time).unwrap().price;
+                let second_price = second.iter().rev().find(|leg| leg.open_time <= candle.open_time).unwrap().price;
+                assert!((candle.price - first_price / second_price).abs() < 1e-5);
+            }
+        }
+
+        strategy.max_gap_percent = Some(1.0);
+        assert!(calculate_synthetic_pair_data_h4(&first, &second, &previous, &strategy, TimeInterval::h4, 1)?.is_none());
+        assert!(calculate_synthetic_pair_data_with_dickeyfuller(&first, &second, &StubStationarity::default(), &strategy, TimeInterval::h4, 1)?.is_none());
+        strategy.max_gap_percent = Some(2.5);
+        assert!(calculate_synthetic_pair_data_h4(&first, &second, &previous, &strategy, TimeInterval::h4, 1)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_gapped_basket_legs_are_joined_on_open_time() -> Result<()> {
+        let (first, mut second) = legs(300);
+        let third = candles(&(0..300).map(|i| 20.0 + (i as f64 / 7.0).cos()).collect::<Vec<_>>());
+        // One missing candle on the second leg must not shift its later bars against the other legs
+        second.remove(150);
+        let weights = [1.0, -1.4, 0.5];
+        let mut strategy = TradingStrategy::new();
+        strategy.z_score_period = 60;
+        let previous = PairData::new(TimeInterval::h4, 1);
+        let price = |leg: &[Candle], candle: &Candle| leg.iter().rev().find(|bar| bar.open_time <= candle.open_time).unwrap().price.ln();
+
+        for (gap_policy, len) in [(GapPolicy::Drop, 299), (GapPolicy::ForwardFill, 300)] {
+            strategy.gap_policy = gap_policy;
+            let pair_data = calculate_basket_data_h4(&[&first, &second, &third], &weights, &previous, &strategy, TimeInterval::h4, 1)?.unwrap();
+            assert_eq!((pair_data.candles.len(), pair_data.repaired_bars), (len, 1));
+            for candle in &pair_data.candles {
+                let expected = price(&first, candle) - 1.4 * price(&second, candle) + 0.5 * price(&third, candle);
+                assert!((candle.price - expected).abs() < 1e-4);
+            }
+        }
+        strategy.max_gap_percent = Some(0.1);
+        assert!(calculate_basket_data_h4(&[&first, &second, &third], &weights, &previous, &strategy, TimeInterval::h4, 1)?.is_none());
+        assert!(calculate_basket_data_with_johansen(&[&first, &second, &third], &StubStationarity::default(), &strategy, TimeInterval::h4, 1)?.is_none());
+
+        // A basket without legs is an error, not a panic
+        assert!(calculate_basket_data_h4(&[], &[], &previous, &strategy, TimeInterval::h4, 1).is_err());
+        assert!(calculate_basket_data_with_johansen(&[], &StubStationarity::default(), &strategy, TimeInterval::h4, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fed_minutes_match_the_full_window() -> Result<()> {
+        let (first, second) = legs(300);
+        let third = candles(&(0..300).map(|i| 20.0 + (i as f64 / 7.0).cos()).collect::<Vec<_>>());
+        let mut strategy = TradingStrategy::new();
+        strategy.z_score_period = 60;
+        // The close sees 200 bars and a window of 50 intraday candles, 10 more arrive after it
+        let leg = |candles: &[Candle], from: usize, to: usize| {
+            let mut data = PairData::new(TimeInterval::h4, 1);
+            (data.candles, data.candles_minutes) = (candles[..200].to_vec(), candles[from..to].to_vec());
+            data
+        };
+        let close_legs = [leg(&first, 150, 200), leg(&second, 150, 200), leg(&third, 150, 200)];
+        let later_legs = [leg(&first, 160, 210), leg(&second, 160, 210), leg(&third, 160, 210)];
+
+        for spread_kind in [SpreadKind::PriceRatio, SpreadKind::NormalizedSpread] {
+            strategy.spread_kind = spread_kind;
+            strategy.hedge_mode = if spread_kind.is_hedged() { HedgeMode::EngleGranger } else { HedgeMode::Ratio };
+            let pair_data = calculate_synthetic_pair_data_with_dickeyfuller(&first[..200], &second[..200], &StubStationarity::default(), &strategy, TimeInterval::h4, 1)?.unwrap();
+            let mut fed = calculate_synthetic_pair_data_minutes(&pair_data, &close_legs[0], &close_legs[1], &strategy, TimeInterval::h4, 1)?.unwrap();
+            let expected = calculate_synthetic_pair_data_minutes(&pair_data, &later_legs[0], &later_legs[1], &strategy, TimeInterval::h4, 1)?.unwrap();
+
+            // The second leg is 4 candles behind, the synthetic pair waits for them
+            let mut legs = close_legs.clone();
+            assert_eq!(push_minute_candles(&mut legs[0], &first[200..210], 60)?, 10);
+            assert_eq!(push_minute_candles(&mut legs[1], &second[200..206], 60)?, 6);
+            let minutes = synthetic_minutes_since(&fed, &legs[0], &legs[1], &strategy)?;
+            assert_eq!(push_minute_candles(&mut fed, &minutes, 60)?, 6);
+            push_minute_candles(&mut legs[1], &second[200..210], 60)?;
+            let minutes = synthetic_minutes_since(&fed, &legs[0], &legs[1], &strategy)?;
+            assert_eq!(push_minute_candles(&mut fed, &minutes, 60)?, 4);
+            // Nothing new, nothing appended
+            assert!(synthetic_minutes_since(&fed, &legs[0], &legs[1], &strategy)?.is_empty());
+
+            assert_eq!((legs[0].candles_minutes.len(), legs[0].z_score_minutes.len()), (50, 10));
+            assert_eq!(legs[0].candles_minutes.last().unwrap().open_time, first[209].open_time);
+            assert_eq!((fed.candles_minutes.len(), fed.z_score_minutes.len()), (50, 50));
+            for (minute, full) in fed.candles_minutes.iter().zip(&expected.candles_minutes) {
+                assert_eq!(minute.open_time, full.open_time);
+                assert!((minute.price - full.price).abs() < 1e-9);
+            }
+            for (z_score, full) in fed.z_score_minutes.iter().zip(&expected.z_score_minutes) {
+                assert!((z_score - full).abs() < 1e-5);
+            }
+        }
+
+        let weights = [1.0, -1.4, 0.5];
+        let basket = calculate_basket_data_h4(&[&first[..200], &second[..200], &third[..200]], &weights, &PairData::new(TimeInterval::h4, 1), &strategy, TimeInterval::h4, 1)?.unwrap();
+        let minutes_of = |legs: &[PairData]| legs.iter().map(|leg| leg.candles_minutes.clone()).collect::<Vec<_>>();
+        let (close_minutes, later_minutes) = (minutes_of(&close_legs), minutes_of(&later_legs));
+        let mut fed = calculate_basket_data_minutes(&basket, &close_minutes.iter().map(|leg| leg.as_slice()).collect::<Vec<_>>(), &weights, &strategy, TimeInterval::h4, 1)?.unwrap();
+        let expected = calculate_basket_data_minutes(&basket, &later_minutes.iter().map(|leg| leg.as_slice()).collect::<Vec<_>>(), &weights, &strategy, TimeInterval::h4, 1)?.unwrap();
+        let legs_minutes: Vec<Vec<Candle>> = [&first, &second, &third].iter().map(|leg| leg[150..210].to_vec()).collect();
+        let minutes = basket_minutes_since(&fed, &legs_minutes.iter().map(|leg| leg.as_slice()).collect::<Vec<_>>(), &weights);
+        assert_eq!(push_minute_candles(&mut fed, &minutes, 60)?, 10);
+        for (z_score, full) in fed.z_score_minutes.iter().zip(&expected.z_score_minutes) {
+            assert!((z_score - full).abs() < 1e-5);
+        }
+        assert!(basket_minutes_since(&fed, &[], &weights).is_empty());
+        Ok(())
+    }
+}
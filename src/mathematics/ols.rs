@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use ndarray::{Array1, Array2};
+
+// Ordinary least squares via Householder QR, shared by the unit-root and cointegration tests.
+#[derive(Debug, Clone)]
+pub struct OlsFit {
+    pub params: Vec<f64>,
+    pub std_errors: Vec<f64>,
+    pub residuals: Vec<f64>,
+    pub ssr: f64,
+    pub nobs: usize,
+}
+
+impl OlsFit {
+    pub fn t_values(&self) -> Vec<f64> {
+        self.params.iter().zip(self.std_errors.iter()).map(|(p, se)| p / se).collect()
+    }
+    pub fn log_likelihood(&self) -> f64 {
+        let n = self.nobs as f64;
+        -n / 2.0 * ((2.0 * std::f64::consts::PI).ln() + (self.ssr / n).ln() + 1.0)
+    }
+    // Same definitions as statsmodels: every regressor (constant included) is a parameter.
+    pub fn aic(&self) -> f64 {
+        -2.0 * self.log_likelihood() + 2.0 * self.params.len() as f64
+    }
+    pub fn bic(&self) -> f64 {
+        -2.0 * self.log_likelihood() + (self.nobs as f64).ln() * self.params.len() as f64
+    }
+}
+
+pub fn ols(y: &[f64], x: &Array2<f64>) -> Result<OlsFit> {
+    let (n, k) = x.dim();
+    if n != y.len() {
+        return Err(anyhow!("ols: {} rows in design matrix but {} observations", n, y.len()));
+    }
+    if k == 0 || n <= k {
+        return Err(anyhow!("ols: not enough observations ({}) for {} regressors", n, k));
+    }
+
+    let mut a = x.clone();
+    let mut qty = Array1::from(y.to_vec());
+    for j in 0..k {
+        let norm = (j..n).map(|i| a[[i, j]] * a[[i, j]]).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let alpha = if a[[j, j]] > 0.0 { -norm } else { norm };
+        let mut v: Vec<f64> = (j..n).map(|i| a[[i, j]]).collect();
+        v[0] -= alpha;
+        let v_norm_sq: f64 = v.iter().map(|e| e * e).sum();
+        if v_norm_sq == 0.0 {
+            continue;
+        }
+        for c in j..k {
+            let dot: f64 = (j..n).map(|i| v[i - j] * a[[i, c]]).sum();
+            let factor = 2.0 * dot / v_norm_sq;
+            for i in j..n {
+                a[[i, c]] -= factor * v[i - j];
+            }
+        }
+        let dot: f64 = (j..n).map(|i| v[i - j] * qty[i]).sum();
+        let factor = 2.0 * dot / v_norm_sq;
+        for i in j..n {
+            qty[i] -= factor * v[i - j];
+        }
+    }
+
+    let max_diag = (0..k).map(|j| a[[j, j]].abs()).fold(0.0, f64::max);
+    if (0..k).any(|j| a[[j, j]].abs() <= max_diag * 1e-12) {
+        return Err(anyhow!("ols: design matrix is singular"));
+    }
+
+    // Back substitution R * beta = Q'y
+    let mut params = vec![0.0; k];
+    for j in (0..k).rev() {
+        let tail: f64 = (j + 1..k).map(|c| a[[j, c]] * params[c]).sum();
+        params[j] = (qty[j] - tail) / a[[j, j]];
+    }
+
+    // (X'X)^-1 = R^-1 R^-T, only the diagonal is needed for standard errors
+    let mut r_inv = Array2::<f64>::zeros((k, k));
+    for j in 0..k {
+        r_inv[[j, j]] = 1.0 / a[[j, j]];
+        for i in (0..j).rev() {
+            let sum: f64 = (i + 1..=j).map(|l| a[[i, l]] * r_inv[[l, j]]).sum();
+            r_inv[[i, j]] = -sum / a[[i, i]];
+        }
+    }
+
+    let residuals: Vec<f64> = (0..n)
+        .map(|i| y[i] - (0..k).map(|c| x[[i, c]] * params[c]).sum::<f64>())
+        .collect();
+    let ssr: f64 = residuals.iter().map(|e| e * e).sum();
+    let sigma2 = ssr / (n - k) as f64;
+    let std_errors = (0..k)
+        .map(|j| (sigma2 * (j..k).map(|l| r_inv[[j, l]] * r_inv[[j, l]]).sum::<f64>()).sqrt())
+        .collect();
+
+    Ok(OlsFit { params, std_errors, residuals, ssr, nobs: n })
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use ndarray::Array2;
+use crate::mathematics::adf::{mackinnon_critical_values, mackinnon_p_value, AdfRegression};
+use crate::mathematics::ols::ols;
+
+// Phillips-Perron Z-tau test, following arch.unitroot.PhillipsPerron.
+// Same null (unit root) and MacKinnon distribution as ADF, but serial correlation is handled by
+// a Newey-West correction of the Dickey-Fuller t-statistic instead of lagged differences.
+
+#[derive(Debug, Clone)]
+pub struct PhillipsPerronResult {
+    pub statistic: f64,
+    pub p_value: f64,
+    pub lags: usize,
+    pub nobs: usize,
+    // 1%, 5%, 10%
+    pub critical_values: [f64; 3],
+}
+
+pub fn phillips_perron(series: &[f64], regression: AdfRegression, lags: Option<usize>) -> Result<PhillipsPerronResult> {
+    let n = series.len();
+    if n < 4 {
+        return Err(anyhow!("phillips_perron: series is too short ({})", n));
+    }
+    if series.iter().any(|x| !x.is_finite()) {
+        return Err(anyhow!("phillips_perron: series contains non-finite values"));
+    }
+    let lags = lags.unwrap_or_else(|| (12.0 * (n as f64 / 100.0).powf(0.25)).ceil() as usize);
+
+    // y[t] on y[t-1] plus the deterministic terms, level first
+    let rows = n - 1;
+    let columns = match regression {
+        AdfRegression::NoConstant => 1,
+        AdfRegression::Constant => 2,
+        AdfRegression::ConstantTrend => 3,
+    };
+    let mut x = Array2::<f64>::zeros((rows, columns));
+    for r in 0..rows {
+        x[[r, 0]] = series[r];
+        if columns > 1 {
+            x[[r, 1]] = 1.0;
+        }
+        if columns > 2 {
+            x[[r, 2]] = (r + 1) as f64;
+        }
+    }
+    let fit = ols(&series[1..], &x)?;
+
+    let nobs = fit.nobs as f64;
+    let k = columns as f64;
+    let residuals = &fit.residuals;
+
+    let gamma0 = fit.ssr / nobs;
+    let mut lambda2 = gamma0;
+    for j in 1..=lags.min(rows - 1) {
+        let gamma_j: f64 = residuals[j..].iter().zip(residuals.iter()).map(|(a, b)| a * b).sum::<f64>() / nobs;
+        lambda2 += 2.0 * (1.0 - j as f64 / (lags as f64 + 1.0)) * gamma_j;
+    }
+    if lambda2 <= 0.0 {
+        return Err(anyhow!("phillips_perron: non-positive long-run variance"));
+    }
+    let lambda = lambda2.sqrt();
+    let s = (fit.ssr / (nobs - k)).sqrt();
+    let sigma = fit.std_errors[0];
+    let rho = fit.params[0];
+
+    let statistic = (gamma0 / lambda2).sqrt() * ((rho - 1.0) / sigma)
+        - 0.5 * ((lambda2 - gamma0) / lambda) * (nobs * sigma / s);
+
+    Ok(PhillipsPerronResult {
+        statistic,
+        p_value: mackinnon_p_value(statistic, regression),
+        lags,
+        nobs: fit.nobs,
+        critical_values: mackinnon_critical_values(regression, fit.nobs),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathematics::adf::adfuller;
+
+    #[test]
+    fn test_phillips_perron_without_lags_is_dickey_fuller() -> Result<()> {
+        let series: Vec<f64> = (0..200).map(|i| ((i * 37 % 101) as f64).sin() + 0.01 * i as f64).collect();
+
+        let pp = phillips_perron(&series, AdfRegression::Constant, Some(0))?;
+        let df = adfuller(&series, Some(0), AdfRegression::Constant, None)?;
+
+        assert!((pp.statistic - df.statistic).abs() < 1e-9);
+        assert!((pp.p_value - df.p_value).abs() < 1e-9);
+        assert_eq!(pp.nobs, df.nobs);
+        Ok(())
+    }
+
+    #[test]
+    fn test_phillips_perron_separates_noise_from_random_walk() -> Result<()> {
+        let mut state: u64 = 42;
+        let noise: Vec<f64> = (0..500).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        }).collect();
+        let walk: Vec<f64> = noise.iter().scan(0.0, |level, step| {
+            *level += step;
+            Some(*level)
+        }).collect();
+
+        assert!(phillips_perron(&noise, AdfRegression::Constant, None)?.p_value < 0.01);
+        assert!(phillips_perron(&walk, AdfRegression::Constant, None)?.p_value > 0.10);
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+
+// Population mean and variance kept with Welford updates, so a z-score over a sliding window
+// costs O(1) per bar instead of a fresh pass over the whole window.
+
+// Below this std relative to the mean a window counts as flat and its z-score is 0,
+// distinct f32 prices are never that close
+const FLAT_WINDOW_EPSILON: f64 = 1e-12;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunningStats {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    // Exact two-pass statistics, also used to resync a long-running window
+    pub fn from_values(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let m2 = values.iter().map(|x| (x - mean) * (x - mean)).sum();
+        RunningStats { count: values.len(), mean, m2 }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Slides a full window by one: `old` leaves, `new` enters, the count stays the same
+    pub fn replace(&mut self, old: f64, new: f64) {
+        if self.count == 0 {
+            return self.push(new);
+        }
+        let old_mean = self.mean;
+        self.mean += (new - old) / self.count as f64;
+        self.m2 += (new - old) * (new - self.mean + old - old_mean);
+        if self.m2 < 0.0 {
+            self.m2 = 0.0;
+        }
+    }
+
+    // Statistics of this window with one more value, leaving self untouched
+    pub fn with(&self, value: f64) -> Self {
+        let mut stats = *self;
+        stats.push(value);
+        stats
+    }
+
+    // ddof = 0, same as the ndarray std the z-scores used before
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.m2 / self.count as f64
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    pub fn z_score(&self, value: f64) -> f64 {
+        let std_dev = self.std_dev();
+        if std_dev <= FLAT_WINDOW_EPSILON * self.mean.abs() {
+            return 0.0;
+        }
+        (value - self.mean) / std_dev
+    }
+}
+
+// Z-score of every bar against the `period` bars ending at it, 0 until the first full window
+pub fn rolling_z_scores(data: &[f32], period: usize) -> Result<Vec<f32>> {
+    if period == 0 {
+        return Err(anyhow!("z-score period must be positive"));
+    }
+    let values: Vec<f64> = data.iter().map(|&x| x as f64).collect();
+    let mut z_scores = vec![0.0; values.len()];
+    if values.len() < period {
+        return Ok(z_scores);
+    }
+
+    let mut stats = RunningStats::from_values(&values[..period]);
+    let mut slides = 0;
+    z_scores[period - 1] = stats.z_score(values[period - 1]) as f32;
+    for i in period..values.len() {
+        // A fresh pass once per window keeps the accumulated rounding bounded on long series
+        slides += 1;
+        if slides == period {
+            stats = RunningStats::from_values(&values[i + 1 - period..=i]);
+            slides = 0;
+        } else {
+            stats.replace(values[i - period], values[i]);
+        }
+        z_scores[i] = stats.z_score(values[i]) as f32;
+    }
+    Ok(z_scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+    use ndarray::{s, Array1};
+    use crate::core::types::Candle;
+    use crate::mathematics::{calculate_z_score, calculate_z_score_minutes, calculate_z_score_minutes_from_bars, calculate_z_score_ndarray};
+    use crate::test_util::candle;
+
+    fn noise(len: usize, seed: u64) -> Vec<f64> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        }).collect()
+    }
+
+    fn prices(len: usize, level: f64, seed: u64) -> Vec<f32> {
+        noise(len, seed).iter().scan(level, |price, step| {
+            *price += step * level * 0.01;
+            Some(*price as f32)
+        }).collect()
+    }
+
+    // Candles every step_minutes ending right before `end`, with Binance-style close times
+    fn candles_until(prices: &[f32], end: NaiveDateTime, step_minutes: i64) -> Vec<Candle> {
+        let first_open = end - Duration::minutes(step_minutes * prices.len() as i64);
+        prices.iter().enumerate().map(|(i, &price)| candle(first_open + Duration::minutes(step_minutes * i as i64), step_minutes, price as f64)).collect()
+    }
+
+    fn day_end() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 9, 2).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    // The window-by-window implementation the rolling engine replaced
+    fn window_z_scores(data: &[f32], period: usize) -> Vec<f32> {
+        let arr_f64: Array1<f64> = Array1::from(data.iter().map(|&x| x as f64).collect::<Vec<_>>());
+        (0..data.len()).map(|i| {
+            if i < period - 1 {
+                return 0.0;
+            }
+            let window = arr_f64.slice(s![i - (period - 1)..=i]);
+            let mean = window.mean().unwrap();
+            let std_dev = window.std(0.0);
+            if std_dev != 0.0 { ((arr_f64[i] - mean) / std_dev) as f32 } else { 0.0 }
+        }).collect()
+    }
+
+    // Looks the containing bar up by a linear scan and recomputes the whole window for every candle
+    fn window_z_scores_minutes(bars: &[Candle], minutes: &[Candle], period: usize) -> Result<Vec<f32>> {
+        minutes.iter().map(|minute| {
+            let position = bars.iter().position(|bar| bar.open_time <= minute.open_time && minute.open_time <= bar.close_time)
+                .unwrap_or(bars.len());
+            let mut window: Vec<f32> = bars[..position].iter().map(|bar| bar.price as f32).collect();
+            window.push(minute.price as f32);
+            calculate_z_score_minutes(&window, period)
+        }).collect()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (i, (a, e)) in actual.iter().zip(expected.iter()).enumerate() {
+            assert!((a - e).abs() <= 1e-4 * e.abs().max(1.0), "index {}: {} != {}", i, a, e);
+        }
+    }
+
+    #[test]
+    fn test_running_stats_slide_matches_fresh_pass() {
+        let values = noise(500, 3);
+        let mut stats = RunningStats::from_values(&values[..50]);
+        for i in 50..values.len() {
+            stats.replace(values[i - 50], values[i]);
+        }
+        let fresh = RunningStats::from_values(&values[values.len() - 50..]);
+
+        assert_eq!(stats.count, 50);
+        assert!((stats.mean - fresh.mean).abs() < 1e-12);
+        assert!((stats.variance() - fresh.variance()).abs() < 1e-12);
+        assert!((RunningStats::from_values(&values[..49]).with(values[49]).variance()
+            - RunningStats::from_values(&values[..50]).variance()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_rolling_z_scores_match_window_recompute() -> Result<()> {
+        for (level, period) in [(1.0, 2), (0.00002, 20), (100.0, 240), (65000.0, 240), (3.5, 1000)] {
+            let data = prices(3000, level, period as u64);
+            let expected = window_z_scores(&data, period);
+
+            assert_close(&calculate_z_score(&data, period)?, &expected);
+            assert_close(&calculate_z_score_ndarray(&data, period)?, &expected);
+        }
+        assert!(calculate_z_score(&[1.0, 2.0], 3).is_err());
+        assert_eq!(calculate_z_score_ndarray(&[1.0, 2.0], 3)?, vec![0.0, 0.0]);
+        assert_eq!(calculate_z_score(&[7.25; 300], 240)?, vec![0.0; 300]);
+        assert!(calculate_z_score(&[1.0, 2.0], 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_minute_z_scores_match_window_recompute() -> Result<()> {
+        for (level, bar_minutes, resolution, period) in [(1.0, 240, 1, 1), (100.0, 240, 1, 30), (0.5, 240, 5, 240), (2.0, 60, 3, 48), (40.0, 15, 15, 96), (7.0, 1440, 5, 20)] {
+            let bars = candles_until(&prices(600, level, 11), day_end(), bar_minutes);
+            let minutes = candles_until(&prices(1440 / resolution as usize, level, 12), day_end(), resolution);
+
+            assert_close(
+                &calculate_z_score_minutes_from_bars(&bars, &minutes, period)?,
+                &window_z_scores_minutes(&bars, &minutes, period)?,
+            );
+        }
+        let minutes = candles_until(&prices(1440, 1.0, 2), day_end(), 1);
+        assert!(calculate_z_score_minutes_from_bars(&candles_until(&prices(20, 1.0, 1), day_end(), 240), &minutes, 240).is_err());
+        assert!(calculate_z_score_minutes_from_bars(&[], &minutes, 20).is_err());
+        // The window starts before the first bar
+        assert!(calculate_z_score_minutes_from_bars(&candles_until(&prices(12, 1.0, 1), day_end(), 60), &minutes, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_minute_z_scores_follow_timestamps() -> Result<()> {
+        // The last bar closes an hour before the day ends, those candles belong to the bar still forming
+        let bars = candles_until(&prices(100, 50.0, 21), day_end() - Duration::hours(1), 240);
+        let mut minutes = candles_until(&prices(288, 50.0, 22), day_end(), 5);
+        // A hole in the intraday data must not shift the later candles to other bars
+        minutes.drain(40..70);
+
+        let z_scores = calculate_z_score_minutes_from_bars(&bars, &minutes, 30)?;
+        assert_close(&z_scores, &window_z_scores_minutes(&bars, &minutes, 30)?);
+
+        let mut forming: Vec<f32> = bars.iter().map(|bar| bar.price as f32).collect();
+        forming.push(minutes[minutes.len() - 1].price as f32);
+        assert!((z_scores[minutes.len() - 1] - calculate_z_score_minutes(&forming, 30)?).abs() < 1e-4);
+        Ok(())
+    }
+
+    // cargo test --release bench_rolling_z_score -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_rolling_z_score() -> Result<()> {
+        let pairs = 200;
+        let data_h4 = prices(2000, 100.0, 5);
+        let bars = candles_until(&data_h4, day_end(), 240);
+        let minutes = candles_until(&prices(1440, 100.0, 6), day_end(), 1);
+
+        let started = Instant::now();
+        for _ in 0..pairs {
+            window_z_scores(&data_h4, 240);
+            window_z_scores_minutes(&bars, &minutes, 240)?;
+        }
+        let window = started.elapsed();
+
+        let started = Instant::now();
+        for _ in 0..pairs {
+            calculate_z_score(&data_h4, 240)?;
+            calculate_z_score_minutes_from_bars(&bars, &minutes, 240)?;
+        }
+        let rolling = started.elapsed();
+
+        println!("{} pairs, h4 + minute z-scores: window recompute {:?}, rolling {:?} ({:.1}x)",
+            pairs, window, rolling, window.as_secs_f64() / rolling.as_secs_f64());
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Result};
+use crate::core::types::{PairData, StationarityBackend, StationarityRule};
+use crate::mathematics::adf::{adfuller, AdfAutoLag, AdfRegression};
+
+// Backend that turns a synthetic series into a unit-root p-value.
+pub trait StationarityTest: Send + Sync {
+    fn p_value(&self, series: &[f32]) -> Result<f32>;
+}
+
+pub fn stationarity_test(backend: &StationarityBackend) -> Result<Box<dyn StationarityTest>> {
+    match backend {
+        StationarityBackend::Native => Ok(Box::new(NativeAdf::default())),
+        #[cfg(feature = "python")]
+        StationarityBackend::Python => Ok(Box::new(python::PythonAdf)),
+        #[cfg(not(feature = "python"))]
+        StationarityBackend::Python => Err(anyhow!("stationarity backend 'python' requires building with the `python` feature")),
+        StationarityBackend::Stub => Ok(Box::new(StubStationarity::default())),
+    }
+}
+
+pub struct NativeAdf {
+    pub regression: AdfRegression,
+    pub autolag: Option<AdfAutoLag>,
+}
+impl Default for NativeAdf {
+    fn default() -> Self {
+        // statsmodels adfuller defaults
+        Self { regression: AdfRegression::Constant, autolag: Some(AdfAutoLag::Aic) }
+    }
+}
+impl StationarityTest for NativeAdf {
+    fn p_value(&self, series: &[f32]) -> Result<f32> {
+        let series: Vec<f64> = series.iter().map(|&x| x as f64).collect();
+        let result = adfuller(&series, None, self.regression, self.autolag)?;
+        Ok(result.p_value as f32)
+    }
+}
+
+// Always reports the same p-value, so tests don't depend on the series shape.
+pub struct StubStationarity {
+    pub p_value: f32,
+}
+impl Default for StubStationarity {
+    fn default() -> Self {
+        Self { p_value: 0.0 }
+    }
+}
+impl StationarityTest for StubStationarity {
+    fn p_value(&self, series: &[f32]) -> Result<f32> {
+        if series.is_empty() {
+            return Err(anyhow!("empty series"));
+        }
+        Ok(self.p_value)
+    }
+}
+
+// `percent` is the confidence threshold from the configuration, e.g. 95.
+// ADF and Phillips-Perron reject a unit root, KPSS must fail to reject stationarity.
+pub fn passes_stationarity_rule(pair_data: &PairData, rule: StationarityRule, percent: f32) -> bool {
+    let adf = pair_data.stationarity >= percent;
+    let pp = (1.0 - pair_data.pp_p_value) * 100.0 >= percent;
+    let kpss = pair_data.kpss_p_value * 100.0 >= 100.0 - percent;
+    match rule {
+        StationarityRule::Adf => adf,
+        StationarityRule::AdfAndKpss => adf && kpss,
+        StationarityRule::AdfAndPp => adf && pp,
+        StationarityRule::All => adf && kpss && pp,
+        StationarityRule::Majority => [adf, kpss, pp].iter().filter(|&&passed| passed).count() >= 2,
+    }
+}
+
+#[cfg(feature = "python")]
+mod python {
+    use anyhow::Result;
+    use numpy::PyArray1;
+    use once_cell::sync::Lazy;
+    use pyo3::prelude::*;
+    use pyo3::types::PyModule;
+    use super::StationarityTest;
+
+    struct AdFullerUtils {
+        adfuller_func: Py<PyAny>,
+    }
+
+    impl AdFullerUtils {
+        fn new(py: Python) -> PyResult<Self> {
+            let statsmodels_module: Bound<'_, PyModule> = PyModule::import_bound(py, "statsmodels.tsa.stattools")?;
+            let adfuller_attr: Bound<'_, PyAny> = statsmodels_module.getattr("adfuller")?;
+            Ok(AdFullerUtils {
+                adfuller_func: adfuller_attr.unbind(),
+            })
+        }
+    }
+
+    static ADFULLER_UTILS: Lazy<PyResult<AdFullerUtils>> = Lazy::new(|| {
+        Python::with_gil(|py| {
+            AdFullerUtils::new(py)
+        })
+    });
+
+    // statsmodels.tsa.stattools.adfuller through PyO3, kept for cross-checking the native port.
+    pub struct PythonAdf;
+
+    impl StationarityTest for PythonAdf {
+        fn p_value(&self, series: &[f32]) -> Result<f32> {
+            let p = Python::with_gil(|py| {
+                // Обробка помилки ініціалізації Lazy, якщо вона сталася
+                let utils = match ADFULLER_UTILS.as_ref() {
+                    Ok(u) => u,
+                    Err(e) => return Err(e.clone_ref(py)),
+                };
+                let series_py = PyArray1::from_vec_bound(py, series.to_vec());
+                let result = utils.adfuller_func.bind(py).call1((series_py,))?;
+                let p_value: f32 = result.get_item(1)?.extract()?;
+                Ok(p_value)
+            })?;
+            Ok(p)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::TimeInterval;
+
+    #[test]
+    fn test_stub_backend_is_deterministic() -> Result<()> {
+        let test = stationarity_test(&StationarityBackend::Stub)?;
+        assert_eq!(test.p_value(&[1.0, 2.0, 3.0])?, 0.0);
+        assert_eq!(test.p_value(&[5.0, -1.0])?, 0.0);
+        assert!(test.p_value(&[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stationarity_rules() {
+        let mut pair_data = PairData::new(TimeInterval::h4, 1);
+        // ADF and KPSS pass at 95%, Phillips-Perron does not
+        pair_data.stationarity = 97.0;
+        pair_data.kpss_p_value = 0.08;
+        pair_data.pp_p_value = 0.20;
+
+        assert!(passes_stationarity_rule(&pair_data, StationarityRule::Adf, 95.0));
+        assert!(passes_stationarity_rule(&pair_data, StationarityRule::AdfAndKpss, 95.0));
+        assert!(!passes_stationarity_rule(&pair_data, StationarityRule::AdfAndPp, 95.0));
+        assert!(!passes_stationarity_rule(&pair_data, StationarityRule::All, 95.0));
+        assert!(passes_stationarity_rule(&pair_data, StationarityRule::Majority, 95.0));
+
+        pair_data.kpss_p_value = 0.01;
+        assert!(!passes_stationarity_rule(&pair_data, StationarityRule::Majority, 95.0));
+    }
+
+    #[cfg(not(feature = "python"))]
+    #[test]
+    fn test_python_backend_requires_feature() {
+        assert!(stationarity_test(&StationarityBackend::Python).is_err());
+    }
+}
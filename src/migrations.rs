@@ -135,6 +135,325 @@ lazy_static! {
                 GRANT EXECUTE ON FUNCTION add_date_going(TIMESTAMP) TO trading_user;
             "#,
         },
+        Migration {
+            from: 1,
+            to: 2,
+            script: r#"
+                DO $$
+                BEGIN
+                    CREATE TYPE stationarity_backend AS ENUM ('native', 'python', 'stub');
+                EXCEPTION
+                    WHEN duplicate_object THEN null;
+                END $$;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS stationarity_backend stationarity_backend NOT NULL DEFAULT 'native';
+            "#,
+        },
+        Migration {
+            from: 2,
+            to: 3,
+            script: r#"
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS kpss_p_value FLOAT4 NOT NULL DEFAULT -1;
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS pp_p_value FLOAT4 NOT NULL DEFAULT -1;
+                DO $$
+                BEGIN
+                    CREATE TYPE stationarity_rule AS ENUM ('adf', 'adf_and_kpss', 'adf_and_pp', 'all', 'majority');
+                EXCEPTION
+                    WHEN duplicate_object THEN null;
+                END $$;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS stationarity_rule stationarity_rule NOT NULL DEFAULT 'adf';
+            "#,
+        },
+        Migration {
+            from: 3,
+            to: 4,
+            script: r#"
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS hedge_ratio FLOAT4 NOT NULL DEFAULT 1;
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS hedge_intercept FLOAT4 NOT NULL DEFAULT 0;
+                DO $$
+                BEGIN
+                    CREATE TYPE hedge_mode AS ENUM ('ratio', 'engle_granger');
+                EXCEPTION
+                    WHEN duplicate_object THEN null;
+                END $$;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS hedge_mode hedge_mode NOT NULL DEFAULT 'ratio';
+            "#,
+        },
+        Migration {
+            from: 4,
+            to: 5,
+            script: r#"
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS basket_weights FLOAT4[];
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS max_basket_size INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS basket_universe INTEGER NOT NULL DEFAULT 20;
+            "#,
+        },
+        Migration {
+            from: 5,
+            to: 6,
+            script: r#"
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS half_life FLOAT4 NOT NULL DEFAULT -1;
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS hurst FLOAT4 NOT NULL DEFAULT -1;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS max_half_life FLOAT4;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS max_hurst FLOAT4;
+            "#,
+        },
+        Migration {
+            from: 6,
+            to: 7,
+            script: r#"
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS z_score_period INTEGER NOT NULL DEFAULT 240 CHECK (z_score_period > 1);
+            "#,
+        },
+        Migration {
+            from: 7,
+            to: 8,
+            script: r#"
+                DO $$
+                BEGIN
+                    CREATE TYPE spread_kind AS ENUM ('price_ratio', 'log_ratio', 'log_spread', 'normalized_spread');
+                EXCEPTION
+                    WHEN duplicate_object THEN null;
+                END $$;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS spread_kind spread_kind NOT NULL DEFAULT 'price_ratio';
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS spread_kind spread_kind NOT NULL DEFAULT 'price_ratio';
+                -- Engle-Granger pairs and baskets were already computed on the log spread
+                UPDATE configuration SET spread_kind = 'log_spread' WHERE hedge_mode = 'engle_granger';
+                UPDATE stationarity_pairs SET spread_kind = 'log_spread' WHERE basket_weights IS NOT NULL OR hedge_ratio <> 1 OR hedge_intercept <> 0;
+            "#,
+        },
+        Migration {
+            from: 8,
+            to: 9,
+            script: r#"
+                -- Every Binance kline interval, kept in duration order
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'm1' BEFORE 'h1';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'm3' BEFORE 'h1';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'm5' BEFORE 'h1';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'm15' BEFORE 'h1';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'm30' BEFORE 'h1';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'h2' BEFORE 'h4';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'h6' AFTER 'h4';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'h8' AFTER 'h6';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'h12' AFTER 'h8';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'd1' AFTER 'h12';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'd3' AFTER 'd1';
+                ALTER TYPE candle_timeframe ADD VALUE IF NOT EXISTS 'w1' AFTER 'd3';
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS intervals candle_timeframe[] NOT NULL DEFAULT '{h4}';
+                ALTER TABLE configuration DROP CONSTRAINT IF EXISTS configuration_intervals_not_empty;
+                ALTER TABLE configuration ADD CONSTRAINT configuration_intervals_not_empty CHECK (cardinality(intervals) > 0);
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS candles_timeframe candle_timeframe NOT NULL DEFAULT 'h4';
+                -- pairs_info keeps one row per interval, the columns are no longer h4 only
+                DO $$
+                BEGIN
+                    IF EXISTS (SELECT 1 FROM information_schema.columns WHERE table_name = 'pairs_info' AND column_name = 'candles_h4') THEN
+                        ALTER TABLE pairs_info RENAME COLUMN candles_h4 TO candles;
+                        ALTER TABLE pairs_info RENAME COLUMN zscores_h4 TO zscores;
+                        ALTER TABLE pairs_info RENAME COLUMN dates_h4 TO dates;
+                    END IF;
+                END $$;
+            "#,
+        },
+        Migration {
+            from: 9,
+            to: 10,
+            script: r#"
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS intraday_interval candle_timeframe NOT NULL DEFAULT 'm1';
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS intraday_window_minutes INTEGER NOT NULL DEFAULT 1440;
+                ALTER TABLE configuration DROP CONSTRAINT IF EXISTS configuration_intraday_interval_check;
+                ALTER TABLE configuration ADD CONSTRAINT configuration_intraday_interval_check CHECK (intraday_interval IN ('m1', 'm3', 'm5', 'm15'));
+                ALTER TABLE configuration DROP CONSTRAINT IF EXISTS configuration_intraday_window_check;
+                ALTER TABLE configuration ADD CONSTRAINT configuration_intraday_window_check CHECK (intraday_window_minutes > 0);
+            "#,
+        },
+        Migration {
+            from: 10,
+            to: 11,
+            script: r#"
+                DO $$
+                BEGIN
+                    CREATE TYPE gap_policy AS ENUM ('drop', 'forward_fill');
+                EXCEPTION
+                    WHEN duplicate_object THEN null;
+                END $$;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS gap_policy gap_policy NOT NULL DEFAULT 'drop';
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS max_gap_percent FLOAT4;
+                ALTER TABLE stationarity_pairs ADD COLUMN IF NOT EXISTS repaired_bars INTEGER NOT NULL DEFAULT 0;
+            "#,
+        },
+        Migration {
+            from: 11,
+            to: 12,
+            script: r#"
+                DO $$
+                BEGIN
+                    CREATE TYPE quality_action AS ENUM ('keep', 'repair', 'exclude');
+                EXCEPTION
+                    WHEN duplicate_object THEN null;
+                END $$;
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS quality_action quality_action NOT NULL DEFAULT 'keep';
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS max_jump_percent FLOAT4 NOT NULL DEFAULT 50.0;
+                CREATE TABLE IF NOT EXISTS candle_quality_reports (
+                    id SERIAL PRIMARY KEY,
+                    log_id INTEGER REFERENCES date_calculate_logs(id) ON DELETE SET NULL,
+                    pair_name VARCHAR NOT NULL,
+                    candles_timeframe candle_timeframe NOT NULL,
+                    bars INTEGER NOT NULL,
+                    missing_bars INTEGER NOT NULL,
+                    duplicated_bars INTEGER NOT NULL,
+                    unordered_bars INTEGER NOT NULL,
+                    non_positive_prices INTEGER NOT NULL,
+                    extreme_jumps INTEGER NOT NULL,
+                    action quality_action NOT NULL
+                );
+            "#,
+        },
+        Migration {
+            from: 12,
+            to: 13,
+            script: r#"
+                ALTER TABLE pairs_info ALTER COLUMN candles TYPE FLOAT8[];
+                ALTER TABLE pairs_info ALTER COLUMN candles_minutes TYPE FLOAT8[];
+                ALTER TABLE pairs_info ADD COLUMN IF NOT EXISTS opens FLOAT8[] NOT NULL DEFAULT '{}';
+                ALTER TABLE pairs_info ADD COLUMN IF NOT EXISTS highs FLOAT8[] NOT NULL DEFAULT '{}';
+                ALTER TABLE pairs_info ADD COLUMN IF NOT EXISTS lows FLOAT8[] NOT NULL DEFAULT '{}';
+                ALTER TABLE pairs_info ADD COLUMN IF NOT EXISTS volumes FLOAT8[] NOT NULL DEFAULT '{}';
+                ALTER TABLE pairs_info ADD COLUMN IF NOT EXISTS quote_volumes FLOAT8[] NOT NULL DEFAULT '{}';
+                ALTER TABLE pairs_info ADD COLUMN IF NOT EXISTS trades BIGINT[] NOT NULL DEFAULT '{}';
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS export_ohlcv BOOL NOT NULL DEFAULT false;
+            "#,
+        },
+        Migration {
+            from: 13,
+            to: 14,
+            script: r#"
+                CREATE TABLE IF NOT EXISTS klines (
+                    symbol VARCHAR NOT NULL,
+                    candles_timeframe candle_timeframe NOT NULL,
+                    open_time TIMESTAMP NOT NULL,
+                    close_time TIMESTAMP NOT NULL,
+                    open FLOAT8 NOT NULL,
+                    high FLOAT8 NOT NULL,
+                    low FLOAT8 NOT NULL,
+                    close FLOAT8 NOT NULL,
+                    volume FLOAT8 NOT NULL,
+                    quote_volume FLOAT8 NOT NULL,
+                    trades BIGINT NOT NULL,
+                    PRIMARY KEY (symbol, candles_timeframe, open_time)
+                );
+            "#,
+        },
+        Migration {
+            from: 14,
+            to: 15,
+            script: r#"
+                CREATE TABLE IF NOT EXISTS imported_kline_archives (
+                    file_name VARCHAR PRIMARY KEY,
+                    sha256 VARCHAR NOT NULL,
+                    klines BIGINT NOT NULL,
+                    imported_at TIMESTAMP NOT NULL DEFAULT now()
+                );
+            "#,
+        },
+        Migration {
+            from: 15,
+            to: 16,
+            script: r#"
+                CREATE TABLE IF NOT EXISTS runs (
+                    id SERIAL PRIMARY KEY,
+                    start_date DATE NOT NULL,
+                    end_date DATE NOT NULL,
+                    step_days INTEGER NOT NULL DEFAULT 1 CHECK (step_days > 0),
+                    created_at TIMESTAMP NOT NULL DEFAULT now(),
+                    CHECK (end_date >= start_date)
+                );
+                -- The window replayed before it was configurable, so the progress of an existing database resumes on it
+                INSERT INTO runs (start_date, end_date, step_days)
+                SELECT DATE '2024-09-01', DATE '2024-09-01' + 269, 1
+                WHERE NOT EXISTS (SELECT 1 FROM runs);
+            "#,
+        },
+        Migration {
+            from: 16,
+            to: 17,
+            script: r#"
+                -- Every backtest is a run with its own progress, analyses and pair data, the existing ones belong to the first run
+                ALTER TABLE runs ADD COLUMN IF NOT EXISTS name VARCHAR;
+                ALTER TABLE runs ADD COLUMN IF NOT EXISTS configuration_id INTEGER REFERENCES configuration(id) ON DELETE SET NULL;
+                INSERT INTO runs (start_date, end_date, step_days)
+                SELECT DATE '2024-09-01', DATE '2024-09-01' + 269, 1
+                WHERE NOT EXISTS (SELECT 1 FROM runs);
+
+                ALTER TABLE date_calculate_logs ADD COLUMN IF NOT EXISTS run_id INTEGER REFERENCES runs(id) ON DELETE CASCADE;
+                UPDATE date_calculate_logs SET run_id = (SELECT MIN(id) FROM runs) WHERE run_id IS NULL;
+                ALTER TABLE date_calculate_logs ALTER COLUMN run_id SET NOT NULL;
+                CREATE INDEX IF NOT EXISTS date_calculate_logs_run_id_idx ON date_calculate_logs (run_id, start_date);
+
+                ALTER TABLE date_going ADD COLUMN IF NOT EXISTS run_id INTEGER REFERENCES runs(id) ON DELETE CASCADE;
+                UPDATE date_going SET run_id = (SELECT MIN(id) FROM runs) WHERE run_id IS NULL;
+                ALTER TABLE date_going ALTER COLUMN run_id SET NOT NULL;
+
+                ALTER TABLE pairs_info ADD COLUMN IF NOT EXISTS run_id INTEGER REFERENCES runs(id) ON DELETE CASCADE;
+                UPDATE pairs_info SET run_id = (SELECT MIN(id) FROM runs) WHERE run_id IS NULL;
+                ALTER TABLE pairs_info ALTER COLUMN run_id SET NOT NULL;
+                ALTER TABLE pairs_info DROP CONSTRAINT IF EXISTS pairs_info_pair_id_candles_timeframe_configuration_id_key;
+                ALTER TABLE pairs_info DROP CONSTRAINT IF EXISTS pairs_info_run_pair_key;
+                ALTER TABLE pairs_info ADD CONSTRAINT pairs_info_run_pair_key UNIQUE (run_id, pair_id, candles_timeframe, configuration_id);
+
+                -- The analyzer acknowledges the days of one run. add_date_going(TIMESTAMP) of an analyzer unaware of runs
+                -- keeps working with one run waiting, with more it can't tell them apart and refuses
+                CREATE OR REPLACE FUNCTION add_date_going(p_day TIMESTAMP)
+                RETURNS VOID AS $$
+                DECLARE
+                    last_record RECORD;
+                    open_runs INTEGER;
+                BEGIN
+                    SELECT COUNT(*) INTO open_runs FROM (
+                        SELECT DISTINCT ON (run_id) run_id, count FROM date_going ORDER BY run_id, id DESC
+                    ) last_days
+                    WHERE count < 36;
+                    IF open_runs > 1 THEN
+                        RAISE EXCEPTION 'add_date_going: % runs wait for an acknowledgement, call add_date_going(day, run_id)', open_runs;
+                    END IF;
+
+                    SELECT * INTO last_record FROM date_going
+                    ORDER BY id DESC LIMIT 1
+                    FOR UPDATE;
+
+                    UPDATE date_going
+                    SET count = last_record.count + 1, day = p_day
+                    WHERE id = last_record.id;
+                END;
+                $$ LANGUAGE plpgsql SECURITY DEFINER;
+                CREATE OR REPLACE FUNCTION add_date_going(p_day TIMESTAMP, p_run_id INTEGER)
+                RETURNS VOID AS $$
+                DECLARE
+                    last_record RECORD;
+                BEGIN
+                    SELECT * INTO last_record FROM date_going
+                    WHERE run_id = p_run_id
+                    ORDER BY id DESC LIMIT 1
+                    FOR UPDATE;
+
+                    UPDATE date_going
+                    SET count = last_record.count + 1, day = p_day
+                    WHERE id = last_record.id;
+                END;
+                $$ LANGUAGE plpgsql SECURITY DEFINER;
+                GRANT SELECT ON runs TO trading_user;
+                GRANT EXECUTE ON FUNCTION add_date_going(TIMESTAMP, INTEGER) TO trading_user;
+            "#,
+        },
+        Migration {
+            from: 17,
+            to: 18,
+            script: r#"
+                -- Bars fetched per analysed interval, in the order of intervals; NULL fetches h4 bars for every interval
+                ALTER TABLE configuration ADD COLUMN IF NOT EXISTS interval_bars INTEGER[];
+                ALTER TABLE configuration DROP CONSTRAINT IF EXISTS configuration_interval_bars_check;
+                ALTER TABLE configuration ADD CONSTRAINT configuration_interval_bars_check
+                    CHECK (interval_bars IS NULL OR (cardinality(interval_bars) = cardinality(intervals) AND 0 < ALL(interval_bars)));
+            "#,
+        },
 
 
     ];
@@ -0,0 +1,185 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use sqlx::postgres::PgRow;
+use sqlx::{Postgres, Row, Transaction};
+use tracing::info;
+use crate::core::types::{ConfigId, ReplayWindow, RunId, TradingStrategy};
+use crate::storage::database::PostgresDataBase;
+
+// Queries of the operator commands in cli.rs, outside the collector loop
+
+// A run as `runs list` and `runs compare` show it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub id: RunId,
+    pub name: Option<String>,
+    pub strategy: Option<ConfigId>,
+    pub window: ReplayWindow,
+    pub created_at: NaiveDateTime,
+    pub days_done: i64,
+    pub analyses: i64,
+    // Stationarity results over every analysis of the run and the distinct pairs they cover
+    pub results: i64,
+    pub pairs: i64,
+    pub mean_stationarity: Option<f64>,
+    pub last_analysis: Option<NaiveDateTime>,
+}
+
+const RUN_SUMMARY: &str = "SELECT r.id, r.name, r.configuration_id, r.start_date, r.end_date, r.step_days, r.created_at,
+        (SELECT COUNT(*) FROM date_going g WHERE g.run_id = r.id AND g.count > 0) AS days_done,
+        (SELECT COUNT(*) FROM date_calculate_logs l WHERE l.run_id = r.id AND l.start_date != l.end_date) AS analyses,
+        (SELECT MAX(l.start_date) FROM date_calculate_logs l WHERE l.run_id = r.id AND l.start_date != l.end_date) AS last_analysis,
+        s.results, s.pairs, s.mean_stationarity
+    FROM runs r
+    LEFT JOIN LATERAL (
+        SELECT COUNT(*) AS results, COUNT(DISTINCT sp.pair_id) AS pairs, AVG(sp.stationarity)::FLOAT8 AS mean_stationarity
+        FROM stationarity_pairs sp
+        JOIN date_calculate_logs l ON l.id = sp.log_id
+        WHERE l.run_id = r.id
+    ) s ON true";
+
+fn run_summary(row: &PgRow) -> Result<RunSummary> {
+    Ok(RunSummary {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        strategy: row.try_get("configuration_id")?,
+        window: ReplayWindow::new(row.try_get("start_date")?, row.try_get("end_date")?, row.try_get("step_days")?)?,
+        created_at: row.try_get("created_at")?,
+        days_done: row.try_get("days_done")?,
+        analyses: row.try_get("analyses")?,
+        results: row.try_get("results")?,
+        pairs: row.try_get("pairs")?,
+        mean_stationarity: row.try_get("mean_stationarity")?,
+        last_analysis: row.try_get("last_analysis")?,
+    })
+}
+
+impl PostgresDataBase {
+    pub async fn schema_version(&self) -> Result<i32> {
+        Ok(sqlx::query_scalar("SELECT version_number FROM version_binance_collector LIMIT 1")
+            .fetch_one(self.pool.as_ref())
+            .await?)
+    }
+    // The given run if it exists, the latest one otherwise. A database without runs gets one with the default window.
+    pub async fn resolve_run(&self, run_id: Option<RunId>) -> Result<RunId> {
+        if let Some(run_id) = run_id {
+            let exists = sqlx::query("SELECT 1 FROM runs WHERE id = $1")
+                .bind(run_id)
+                .fetch_optional(self.pool.as_ref())
+                .await?;
+            return exists.map(|_| run_id).ok_or(anyhow!("run {} does not exist", run_id));
+        }
+        let latest: Option<RunId> = sqlx::query_scalar("SELECT MAX(id) FROM runs")
+            .fetch_one(self.pool.as_ref())
+            .await?;
+        match latest {
+            Some(run_id) => Ok(run_id),
+            None => self.create_run(None, None, &ReplayWindow::default()).await,
+        }
+    }
+    pub async fn create_run(&self, name: Option<&str>, strategy: Option<ConfigId>, window: &ReplayWindow) -> Result<RunId> {
+        let run_id = sqlx::query_scalar("INSERT INTO runs (name, configuration_id, start_date, end_date, step_days) VALUES ($1, $2, $3, $4, $5) RETURNING id")
+            .bind(name).bind(strategy).bind(window.start).bind(window.end).bind(window.step_days)
+            .fetch_one(self.pool.as_ref())
+            .await?;
+        info!("run {} created: {:?}, strategy {:?}, {:?}", run_id, name, strategy, window);
+        Ok(run_id)
+    }
+    pub async fn list_runs(&self) -> Result<Vec<RunSummary>> {
+        let rows = sqlx::query(&format!("{} ORDER BY r.id", RUN_SUMMARY))
+            .fetch_all(self.pool.as_ref())
+            .await?;
+        rows.iter().map(run_summary).collect()
+    }
+    pub async fn run_summary(&self, run_id: RunId) -> Result<RunSummary> {
+        let row = sqlx::query(&format!("{} WHERE r.id = $1", RUN_SUMMARY))
+            .bind(run_id)
+            .fetch_optional(self.pool.as_ref())
+            .await?
+            .ok_or(anyhow!("run {} does not exist", run_id))?;
+        run_summary(&row)
+    }
+    // Pairs with stationarity results in both runs
+    pub async fn common_pairs(&self, first: RunId, second: RunId) -> Result<i64> {
+        Ok(sqlx::query_scalar(
+            "SELECT COUNT(*) FROM (
+                SELECT sp.pair_id FROM stationarity_pairs sp JOIN date_calculate_logs l ON l.id = sp.log_id WHERE l.run_id = $1
+                INTERSECT
+                SELECT sp.pair_id FROM stationarity_pairs sp JOIN date_calculate_logs l ON l.id = sp.log_id WHERE l.run_id = $2
+            ) common"
+        )
+            .bind(first).bind(second)
+            .fetch_one(self.pool.as_ref())
+            .await?)
+    }
+    // Drops the progress and every analysis result of a run, the run itself and its window stay. Pairs, their
+    // requests, strategies and the kline store are shared and kept. Returns the number of deleted analyses.
+    pub async fn reset_progress(&self, run_id: RunId) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = delete_progress(&mut tx, run_id).await?;
+        tx.commit().await?;
+        info!("reset of run {}: {} analyses deleted", run_id, deleted);
+        Ok(deleted)
+    }
+    // The run with its progress and results, the other runs are left as they are
+    pub async fn delete_run(&self, run_id: RunId) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = delete_progress(&mut tx, run_id).await?;
+        let result = sqlx::query("DELETE FROM runs WHERE id = $1").bind(run_id).execute(&mut *tx).await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("run {} does not exist", run_id));
+        }
+        tx.commit().await?;
+        info!("run {} deleted with {} analyses", run_id, deleted);
+        Ok(deleted)
+    }
+    // The stationarity results of one analysis as a JSON array, one object per pair and interval
+    pub async fn export_results(&self, log_id: i32) -> Result<String> {
+        let exists = sqlx::query("SELECT 1 FROM date_calculate_logs WHERE id = $1")
+            .bind(log_id)
+            .fetch_optional(self.pool.as_ref())
+            .await?;
+        if exists.is_none() {
+            return Err(anyhow!("analysis {} does not exist", log_id));
+        }
+        let row = sqlx::query(
+            "SELECT COALESCE(json_agg(r ORDER BY r.synthetic, r.pair, r.interval), '[]'::json)::text AS results FROM (
+                SELECT p.name AS pair, p.is_synthetic AS synthetic, sp.candles_timeframe::text AS interval, sp.stationarity,
+                       sp.kpss_p_value, sp.pp_p_value, sp.hedge_ratio, sp.hedge_intercept, sp.spread_kind::text AS spread_kind,
+                       sp.half_life, sp.hurst, sp.repaired_bars, sp.basket_weights
+                FROM stationarity_pairs sp
+                JOIN pairs p ON p.id = sp.pair_id
+                WHERE sp.log_id = $1
+            ) r"
+        )
+            .bind(log_id)
+            .fetch_one(self.pool.as_ref())
+            .await?;
+        Ok(row.try_get("results")?)
+    }
+    // The new strategy gets the highest id, so once activated the collector analyses with it from the next day
+    pub async fn add_strategy(&self, strategy: &TradingStrategy, activate: bool) -> Result<ConfigId> {
+        let row = sqlx::query(
+            "INSERT INTO configuration (h4, intervals, percent, is_activated, stationarity_backend, stationarity_rule, hedge_mode, spread_kind, max_basket_size, basket_universe, max_half_life, max_hurst, z_score_period, intraday_interval, intraday_window_minutes, gap_policy, max_gap_percent, quality_action, max_jump_percent, export_ohlcv, interval_bars) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21) RETURNING id",
+        ).bind(strategy.h4).bind(&strategy.intervals).bind(strategy.percent).bind(activate)
+            .bind(strategy.stationarity_backend.clone()).bind(strategy.stationarity_rule).bind(strategy.hedge_mode).bind(strategy.spread_kind)
+            .bind(strategy.max_basket_size).bind(strategy.basket_universe).bind(strategy.max_half_life).bind(strategy.max_hurst)
+            .bind(strategy.z_score_period).bind(strategy.intraday_interval.clone()).bind(strategy.intraday_window_minutes)
+            .bind(strategy.gap_policy).bind(strategy.max_gap_percent).bind(strategy.quality_action).bind(strategy.max_jump_percent).bind(strategy.export_ohlcv)
+            .bind(&strategy.interval_bars)
+            .fetch_one(self.pool.as_ref())
+            .await?;
+        Ok(row.try_get("id")?)
+    }
+}
+// stationarity_pairs and candle_quality_reports hang off the analyses, so they go first
+async fn delete_progress(tx: &mut Transaction<'_, Postgres>, run_id: RunId) -> Result<u64> {
+    for table in ["stationarity_pairs", "candle_quality_reports"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE log_id IN (SELECT id FROM date_calculate_logs WHERE run_id = $1)", table))
+            .bind(run_id).execute(&mut **tx).await?;
+    }
+    for table in ["date_going", "pairs_info"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE run_id = $1", table)).bind(run_id).execute(&mut **tx).await?;
+    }
+    Ok(sqlx::query("DELETE FROM date_calculate_logs WHERE run_id = $1").bind(run_id).execute(&mut **tx).await?.rows_affected())
+}
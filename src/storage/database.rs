@@ -2,15 +2,16 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use sqlx::Executor;
 use sqlx::{PgPool, Error, Row, Transaction, Postgres};
-use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use serde::Deserialize;
 use sqlx::postgres::{PgListener, PgPoolOptions, PgRow};
 use crate::migrations::MIGRATIONS;
-use crate::core::types::{Candle, TimeInterval, MarketDataPoint, ConfigId, TradingStrategy, MarketDataSyncLog, TradingInstrument, PairData, SyntheticPair, SyntheticPairFullData, BaseAsset, TradingPair, ValueType, StartInfo, RequestedPair};
+use crate::core::types::{Candle, TimeInterval, MarketDataPoint, ConfigId, TradingStrategy, MarketDataSyncLog, TradingInstrument, PairData, SyntheticPair, SyntheticPairFullData, SyntheticBasket, BaseAsset, TradingPair, ValueType, StartInfo, RequestedPair, ReplayWindow, RunId};
 use anyhow::{anyhow, Result};
 use std::ops::DerefMut;
 use mockall::automock;
 use crate::core::config::Config;
+use crate::analysis::quality::CandleQualityReport;
 use tracing::info;
 #[automock]
 #[async_trait::async_trait]
@@ -21,34 +22,37 @@ pub trait DatabaseInterface {
     async fn get_active_strategies(&self) -> Result<(HashMap<ConfigId, TradingStrategy>, i32)>;
     async fn get_pairs_by_log_id(&self, log_id: i32, last_configuration: &TradingStrategy) -> Result<Vec<TradingPair>>;
     async fn get_synthetic_pairs_by_log_id(&self, log_id: i32, last_configuration: &TradingStrategy) -> Result<Vec<SyntheticPairFullData>>;
-    async fn get_logs_id_by_date(&self, date: NaiveDateTime) -> Result<i32>;
-    async fn start_market_analysis(&self, start_date: NaiveDateTime, config_id: ConfigId) -> Result<i32>;
-    async fn get_last_analysis_time(&self) -> Result<NaiveDateTime>;
+    async fn get_baskets_by_log_id(&self, log_id: i32, last_configuration: &TradingStrategy) -> Result<Vec<SyntheticBasket>>;
+    async fn get_logs_id_by_date(&self, run_id: RunId, date: NaiveDateTime) -> Result<i32>;
+    async fn start_market_analysis(&self, run_id: RunId, start_date: NaiveDateTime, config_id: ConfigId) -> Result<i32>;
+    async fn get_last_analysis_time(&self, run_id: RunId) -> Result<NaiveDateTime>;
     async fn finish_analysis(&self, end_date: NaiveDateTime, id: i32) -> Result<i32>;
-    async fn save_trading_instrument(&self, config: &Config, trading_pair: &mut TradingPair, logs_id: i32) -> Result<()>;
-    async fn get_days_count(&self) -> Result<i32>;
-    async fn clean_invalid_day_records(&self) -> Result<()>;
-    async fn get_start_info(&self) -> Result<StartInfo>;
-    async fn get_logs_id(&self) -> Result<(i32, i32)>;
-    async fn need_extra_stationaryti(&self) -> Result<bool>;
+    async fn save_trading_instrument(&self, config: &Config, trading_pair: &mut TradingPair, run_id: RunId, logs_id: i32) -> Result<()>;
+    async fn get_days_count(&self, run_id: RunId) -> Result<i32>;
+    async fn clean_invalid_day_records(&self, run_id: RunId) -> Result<()>;
+    async fn get_start_info(&self, run_id: RunId) -> Result<StartInfo>;
+    async fn get_replay_window(&self, run_id: RunId) -> Result<ReplayWindow>;
+    async fn save_replay_window(&self, run_id: RunId, window: &ReplayWindow) -> Result<()>;
+    async fn get_logs_id(&self, run_id: RunId) -> Result<(i32, i32)>;
+    async fn need_extra_stationaryti(&self, run_id: RunId) -> Result<bool>;
     async fn save_synthetic_trading_pair(&self, config: &Config, trading_syn_pair: &mut SyntheticPairFullData, logs_id: i32) -> Result<()>;
+    async fn save_synthetic_basket(&self, config: &Config, basket: &mut SyntheticBasket, logs_id: i32) -> Result<()>;
     async fn save_pair_stationarity_metrics(&self, pair_id: i32, log_id: i32, stationarity: f32) -> Result<i32>;
     async fn insert_pair_transaction<'tx>(&self, name: &str, is_synthetic: bool, tx: &mut Transaction<'tx, Postgres>) -> Result<i32>;
-    async fn insert_stationarity_transaction<'tx>(&self, pair_id: i32, log_id: i32, stationarity: f32, tx: &mut Transaction<'tx, Postgres>) -> Result<i32>;
-    async fn check_for_new_date_going(&self) -> Result<bool>;
-    async fn finish_clear(&self) -> Result<()>;
+    async fn insert_stationarity_transaction<'tx>(&self, pair_id: i32, log_id: i32, pair_data: &PairData, tx: &mut Transaction<'tx, Postgres>) -> Result<i32>;
+    async fn check_for_new_date_going(&self, run_id: RunId) -> Result<bool>;
+    async fn finish_clear(&self, run_id: RunId) -> Result<()>;
     async fn run_migrations(&self) -> Result<()>;
-    async fn start_update(&self, new_log_id: i32, time_now: NaiveDateTime) -> Result<()>;
-    async fn finish_update(&self) -> Result<()>;
-    async fn update_last_date_going_log_id(&self) -> Result<bool>;
-    async fn clear_analysis_data(&self) -> Result<()>;
-    async fn insert_pair_info_transaction<'tx>(&self, candles_h4: &Vec<f32>, zscores_h4: &Vec<f32>,
-                                          candles_minutes: &Vec<f32>, dates_h4: &Vec<NaiveDateTime>, dates_minutes: &Vec<NaiveDateTime>,
-                                          zscore_minutes: &Vec<f32>, pair_id: i32, configuration_id: ConfigId,
-                                          timeframe: TimeInterval, tx: &mut Transaction<'tx, Postgres>) -> Result<i32>;
+    async fn start_update(&self, run_id: RunId, new_log_id: i32, time_now: NaiveDateTime) -> Result<()>;
+    async fn finish_update(&self, run_id: RunId) -> Result<()>;
+    async fn update_last_date_going_log_id(&self, run_id: RunId) -> Result<bool>;
+    async fn clear_analysis_data(&self, run_id: RunId) -> Result<()>;
+    async fn save_candle_quality_reports(&self, log_id: i32, reports: &[CandleQualityReport]) -> Result<()>;
+    async fn insert_pair_info_transaction<'tx>(&self, run_id: RunId, pair_data: &PairData, pair_id: i32, z_score_period: usize, tx: &mut Transaction<'tx, Postgres>) -> Result<i32>;
 }
+#[derive(Clone)]
 pub struct PostgresDataBase {
-    pool:Arc<PgPool>,
+    pub(super) pool:Arc<PgPool>,
 }
 impl PostgresDataBase{
     pub async fn new(database_url:&str)->Result<Self> {
@@ -113,19 +117,38 @@ impl DatabaseInterface for PostgresDataBase {
         Ok(requested_pairs)
     }
     async fn get_active_strategies(&self) -> Result<(HashMap<ConfigId, TradingStrategy>, i32)> {
-       let mut configs: Vec<TradingStrategy> = sqlx::query_as::<_, (ConfigId, i32, f32)>(
-           "SELECT id, h4, percent FROM configuration WHERE is_activated=true ORDER BY id DESC",
+       let rows = sqlx::query(
+           "SELECT id, h4, intervals, interval_bars, percent, stationarity_backend, stationarity_rule, hedge_mode, spread_kind, max_basket_size, basket_universe, max_half_life, max_hurst, z_score_period, intraday_interval, intraday_window_minutes, gap_policy, max_gap_percent, quality_action, max_jump_percent, export_ohlcv FROM configuration WHERE is_activated=true ORDER BY id DESC",
        )
            .fetch_all(self.pool.as_ref())
-           .await?
-           .into_iter()
-           .map(|(id, h4, percent)| TradingStrategy {
-               id,
+           .await?;
+       let mut configs: Vec<TradingStrategy> = Vec::with_capacity(rows.len());
+       for row in rows {
+           configs.push(TradingStrategy {
+               id: row.try_get("id")?,
                h1: 500,
-               h4,
-               percent,
-           })
-           .collect();
+               h4: row.try_get("h4")?,
+               intervals: row.try_get("intervals")?,
+               interval_bars: row.try_get("interval_bars")?,
+               percent: row.try_get("percent")?,
+               stationarity_backend: row.try_get("stationarity_backend")?,
+               stationarity_rule: row.try_get("stationarity_rule")?,
+               hedge_mode: row.try_get("hedge_mode")?,
+               spread_kind: row.try_get("spread_kind")?,
+               max_basket_size: row.try_get("max_basket_size")?,
+               basket_universe: row.try_get("basket_universe")?,
+               max_half_life: row.try_get("max_half_life")?,
+               max_hurst: row.try_get("max_hurst")?,
+               z_score_period: row.try_get("z_score_period")?,
+               intraday_interval: row.try_get("intraday_interval")?,
+               intraday_window_minutes: row.try_get("intraday_window_minutes")?,
+               gap_policy: row.try_get("gap_policy")?,
+               max_gap_percent: row.try_get("max_gap_percent")?,
+               quality_action: row.try_get("quality_action")?,
+               max_jump_percent: row.try_get("max_jump_percent")?,
+               export_ohlcv: row.try_get("export_ohlcv")?,
+           });
+       }
        let last_configuration_id=configs[0].id;
        let config_map = configs
            .into_iter()
@@ -136,7 +159,7 @@ impl DatabaseInterface for PostgresDataBase {
    }
     async fn get_pairs_by_log_id(&self, log_id: i32,last_configuration:&TradingStrategy) -> Result<Vec<TradingPair>> {
        let rows = sqlx::query(
-           "SELECT p.id, p.name
+           "SELECT DISTINCT p.id, p.name
             FROM pairs p
             JOIN stationarity_pairs sp ON p.id = sp.pair_id
             WHERE sp.log_id = $1 AND p.is_synthetic = false"
@@ -153,7 +176,7 @@ impl DatabaseInterface for PostgresDataBase {
                is_request_pair:None,
                pair_data_map: HashMap::new()
            };
-           pair.init_pair_data(last_configuration.id);
+           pair.init_pair_data(last_configuration.id, &last_configuration.intervals);
 
            trading_pairs.push(pair);
        }
@@ -163,57 +186,114 @@ impl DatabaseInterface for PostgresDataBase {
 
     async fn get_synthetic_pairs_by_log_id(&self, log_id: i32,last_configuration:&TradingStrategy) -> Result<Vec<SyntheticPairFullData>> {
        let rows = sqlx::query(
-           "SELECT p.id, p.name, sp.stationarity
+           "SELECT p.id, p.name, sp.candles_timeframe, sp.stationarity, sp.kpss_p_value, sp.pp_p_value, sp.hedge_ratio, sp.hedge_intercept, sp.spread_kind, sp.half_life, sp.hurst, sp.repaired_bars
             FROM pairs p
             JOIN stationarity_pairs sp ON p.id = sp.pair_id
-            WHERE sp.log_id = $1 AND p.is_synthetic = true"
+            WHERE sp.log_id = $1 AND p.is_synthetic = true AND sp.basket_weights IS NULL
+            ORDER BY p.id"
        )
            .bind(log_id)
            .fetch_all(self.pool.as_ref())
            .await?;
 
-       let mut synthetic_pairs = Vec::with_capacity(rows.len());
+       let mut synthetic_pairs: Vec<SyntheticPairFullData> = Vec::with_capacity(rows.len());
        for row in rows {
-           let pair_name: String = row.try_get("name")?;
-           let parts: Vec<&str> = pair_name.split('/').collect();
-
-           let mut syn_pair = SyntheticPairFullData::new();
-           syn_pair.id = row.try_get("id")?;
-           syn_pair.name = pair_name.clone();
+           let id: i32 = row.try_get("id")?;
+           // Rows of one pair are adjacent, one per analysed interval
+           if synthetic_pairs.last().map(|pair| pair.id) != Some(id) {
+               let pair_name: String = row.try_get("name")?;
+               let parts: Vec<&str> = pair_name.split('/').collect();
+
+               let mut syn_pair = SyntheticPairFullData::new();
+               syn_pair.id = id;
+               syn_pair.name = pair_name.clone();
+
+               syn_pair.first_pair = BaseAsset {
+                   id: -1,
+                   name: parts.get(0).unwrap_or(&"").to_string(),
+               };
+               syn_pair.second_pair = BaseAsset {
+                   id: -1,
+                   name: parts.get(1).unwrap_or(&"").to_string(),
+               };
+               syn_pair.is_request_pair=None;
+               syn_pair.synthetic_data=HashMap::new();
+
+               syn_pair.init_pair_data(last_configuration.id, &last_configuration.intervals);
+               synthetic_pairs.push(syn_pair);
+           }
 
-           syn_pair.first_pair = BaseAsset {
-               id: -1,
-               name: parts.get(0).unwrap_or(&"").to_string(),
+           let time_interval: TimeInterval = row.try_get("candles_timeframe")?;
+           let syn_pair = synthetic_pairs.last_mut().ok_or(anyhow!("Error: synthetic pair dont find"))?;
+           // Intervals the current configuration no longer analyses are skipped
+           let Some(timeframe_data) = syn_pair.get_timeframe_data_mut(last_configuration.id, &time_interval) else {
+               continue;
            };
-           syn_pair.second_pair = BaseAsset {
-               id: -1,
-               name: parts.get(1).unwrap_or(&"").to_string(),
-           };
-           syn_pair.is_request_pair=None;
-           syn_pair.synthetic_data=HashMap::new();
+           timeframe_data.stationarity = row.try_get("stationarity")?;
+           timeframe_data.kpss_p_value = row.try_get("kpss_p_value")?;
+           timeframe_data.pp_p_value = row.try_get("pp_p_value")?;
+           timeframe_data.hedge_ratio = row.try_get("hedge_ratio")?;
+           timeframe_data.hedge_intercept = row.try_get("hedge_intercept")?;
+           timeframe_data.spread_kind = row.try_get("spread_kind")?;
+           timeframe_data.half_life = row.try_get("half_life")?;
+           timeframe_data.hurst = row.try_get("hurst")?;
+           timeframe_data.repaired_bars = row.try_get("repaired_bars")?;
+       }
+
+       Ok(synthetic_pairs)
+   }
 
-           syn_pair.init_pair_data(last_configuration.id);
+    async fn get_baskets_by_log_id(&self, log_id: i32,last_configuration:&TradingStrategy) -> Result<Vec<SyntheticBasket>> {
+       let rows = sqlx::query(
+           "SELECT p.id, p.name, sp.stationarity, sp.kpss_p_value, sp.pp_p_value, sp.spread_kind, sp.half_life, sp.hurst, sp.basket_weights
+            FROM pairs p
+            JOIN stationarity_pairs sp ON p.id = sp.pair_id
+            WHERE sp.log_id = $1 AND p.is_synthetic = true AND sp.basket_weights IS NOT NULL"
+       )
+           .bind(log_id)
+           .fetch_all(self.pool.as_ref())
+           .await?;
 
-           let stationarity: f32 = row.try_get("stationarity")?;
-           syn_pair.get_timeframe_data_mut(last_configuration.id,&TimeInterval::h4).unwrap().stationarity=stationarity;
+       let mut baskets = Vec::with_capacity(rows.len());
+       for row in rows {
+           let name: String = row.try_get("name")?;
+           let legs = SyntheticBasket::parse_name(&name).ok_or(anyhow!("Error: bad basket name {}", name))?;
+
+           let mut basket = SyntheticBasket::new(legs.into_iter().map(|leg| BaseAsset { id: -1, name: leg }).collect());
+           basket.id = row.try_get("id")?;
+           basket.weights = row.try_get("basket_weights")?;
+           if basket.weights.len() != basket.legs.len() {
+               return Err(anyhow!("Error: basket {} has {} weights for {} legs", name, basket.weights.len(), basket.legs.len()));
+           }
 
-           synthetic_pairs.push(syn_pair);
+           // Baskets are analysed on the primary interval only
+           let primary_interval = last_configuration.primary_interval();
+           basket.init_pair_data(last_configuration.id, std::slice::from_ref(&primary_interval));
+           let primary_data = basket.get_timeframe_data_mut(last_configuration.id,&primary_interval).unwrap();
+           primary_data.stationarity = row.try_get("stationarity")?;
+           primary_data.kpss_p_value = row.try_get("kpss_p_value")?;
+           primary_data.pp_p_value = row.try_get("pp_p_value")?;
+           primary_data.spread_kind = row.try_get("spread_kind")?;
+           primary_data.half_life = row.try_get("half_life")?;
+           primary_data.hurst = row.try_get("hurst")?;
+
+           baskets.push(basket);
        }
 
-       Ok(synthetic_pairs)
+       Ok(baskets)
    }
 
-    async fn get_logs_id_by_date(&self, date: NaiveDateTime) -> Result<i32> {
+    async fn get_logs_id_by_date(&self, run_id: RunId, date: NaiveDateTime) -> Result<i32> {
        let date_only = date.date().and_hms_opt(0, 0, 0).unwrap();
 
        let row = sqlx::query(
            "SELECT id
             FROM date_calculate_logs
-            WHERE DATE(start_date) = DATE($1) AND start_date!=end_date
+            WHERE run_id = $2 AND DATE(start_date) = DATE($1) AND start_date!=end_date
             ORDER BY id DESC
             LIMIT 1"
        )
-           .bind(date_only)
+           .bind(date_only).bind(run_id)
            .fetch_optional(self.pool.as_ref())
            .await?;
 
@@ -223,11 +303,11 @@ impl DatabaseInterface for PostgresDataBase {
        }
    }
 
-    async fn start_market_analysis(&self, start_date: NaiveDateTime, config_id: ConfigId) -> Result<i32> {
+    async fn start_market_analysis(&self, run_id: RunId, start_date: NaiveDateTime, config_id: ConfigId) -> Result<i32> {
        let row = sqlx::query(
-           "INSERT INTO date_calculate_logs (start_date,end_date,configuration_id) VALUES ($1,$2,$3) RETURNING id"
+           "INSERT INTO date_calculate_logs (start_date,end_date,configuration_id,run_id) VALUES ($1,$2,$3,$4) RETURNING id"
        )
-           .bind(start_date).bind(start_date).bind(config_id)
+           .bind(start_date).bind(start_date).bind(config_id).bind(run_id)
            .fetch_one(self.pool.as_ref())
            .await?;
 
@@ -236,13 +316,14 @@ impl DatabaseInterface for PostgresDataBase {
        Ok(id)
    }
 
-    async fn get_last_analysis_time(&self) -> Result<NaiveDateTime> {
+    async fn get_last_analysis_time(&self, run_id: RunId) -> Result<NaiveDateTime> {
        let row = sqlx::query(
            "SELECT *
-   FROM date_calculate_logs WHERE start_date!=end_date
+   FROM date_calculate_logs WHERE run_id = $1 AND start_date!=end_date
    ORDER BY start_date DESC
    LIMIT 1"
        )
+           .bind(run_id)
            .fetch_optional(self.pool.as_ref())
            .await?;
        let mut date=NaiveDateTime::default();
@@ -271,20 +352,26 @@ impl DatabaseInterface for PostgresDataBase {
    }
 
 
-    async fn save_trading_instrument(&self, config:&Config, trading_pair:&mut  TradingPair, logs_id:i32) ->Result<()>{
+    async fn save_trading_instrument(&self, config:&Config, trading_pair:&mut  TradingPair, run_id:RunId, logs_id:i32) ->Result<()>{
        let mut tx = self.pool.begin().await?;
        trading_pair.id = self.insert_pair_transaction(trading_pair.name.as_str(), false, &mut tx).await?;
        let last_config_id = config.get_last_config_id()?;
        if trading_pair.is_request_pair.unwrap_or(true)!=false{
-           self.insert_stationarity_transaction(  trading_pair.id, logs_id, -1.0, &mut tx).await?;
+           let primary_interval = config.get(last_config_id)?.primary_interval();
+           self.insert_stationarity_transaction(  trading_pair.id, logs_id, &PairData::new(primary_interval, last_config_id), &mut tx).await?;
+       }
+       let z_score_period = config.get(last_config_id)?.z_score_period as usize;
+       for timeframe_data in trading_pair.pair_data_map.get(&last_config_id).into_iter().flatten() {
+           self.insert_pair_info_transaction(run_id, timeframe_data, trading_pair.id, z_score_period, &mut tx).await?;
        }
        tx.commit().await?;
        Ok(())
    }
 
 
-    async fn get_days_count(&self) -> Result<i32> {
-       let row = sqlx::query("SELECT COUNT(*) as days_count FROM date_going WHERE count > 0")
+    async fn get_days_count(&self, run_id: RunId) -> Result<i32> {
+       let row = sqlx::query("SELECT COUNT(*) as days_count FROM date_going WHERE run_id = $1 AND count > 0")
+           .bind(run_id)
            .fetch_one(self.pool.as_ref())
            .await?;
 
@@ -292,37 +379,65 @@ impl DatabaseInterface for PostgresDataBase {
 
        Ok(days_count as i32)
    }
-    async fn clean_invalid_day_records(&self) -> Result<()> {
-       let result = sqlx::query("DELETE FROM date_going WHERE count <= 0")
+    async fn clean_invalid_day_records(&self, run_id: RunId) -> Result<()> {
+       let result = sqlx::query("DELETE FROM date_going WHERE run_id = $1 AND count <= 0")
+           .bind(run_id)
            .execute(self.pool.as_ref())
            .await?;
-       let result = sqlx::query("DELETE FROM date_calculate_logs WHERE start_date=end_date")
+       let result = sqlx::query("DELETE FROM date_calculate_logs WHERE run_id = $1 AND start_date=end_date")
+           .bind(run_id)
            .execute(self.pool.as_ref())
            .await?;
 
        Ok(())
    }
-    async fn get_start_info(&self)->Result<StartInfo>{
-       let (logs_id,configuration_id)=self.get_logs_id().await?;
-       self.clean_invalid_day_records().await?;
-       let count_day=self.get_days_count().await?;
-       let mut base_date=NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
-       let last_date_bypass=base_date+chrono::Duration::days(count_day as i64);
+    async fn get_start_info(&self, run_id: RunId)->Result<StartInfo>{
+       let (logs_id,configuration_id)=self.get_logs_id(run_id).await?;
+       self.clean_invalid_day_records(run_id).await?;
+       let count_day=self.get_days_count(run_id).await?;
+       let replay_window=self.get_replay_window(run_id).await?;
+       let strategy=sqlx::query_scalar("SELECT configuration_id FROM runs WHERE id = $1")
+           .bind(run_id)
+           .fetch_optional(self.pool.as_ref())
+           .await?
+           .ok_or(anyhow!("run {} does not exist", run_id))?;
        Ok(StartInfo{
            last_logs_id: logs_id,
            last_configuration_id: configuration_id,
-           base_date,
-           last_date_bypass,
-           current_day:count_day
+           base_date:replay_window.day(0),
+           last_date_bypass:replay_window.day(count_day),
+           current_day:count_day,
+           replay_window,
+           strategy
        })
    }
-    async fn get_logs_id(&self)->Result<(i32,i32)>{
+    async fn get_replay_window(&self, run_id: RunId)->Result<ReplayWindow>{
+       let row = sqlx::query("SELECT start_date, end_date, step_days FROM runs WHERE id = $1")
+           .bind(run_id)
+           .fetch_optional(self.pool.as_ref())
+           .await?
+           .ok_or(anyhow!("run {} does not exist", run_id))?;
+       ReplayWindow::new(row.try_get("start_date")?, row.try_get("end_date")?, row.try_get("step_days")?)
+   }
+    async fn save_replay_window(&self, run_id: RunId, window:&ReplayWindow)->Result<()>{
+       let updated = sqlx::query("UPDATE runs SET start_date = $1, end_date = $2, step_days = $3 WHERE id = $4")
+           .bind(window.start).bind(window.end).bind(window.step_days).bind(run_id)
+           .execute(self.pool.as_ref())
+           .await?;
+       if updated.rows_affected() == 0 {
+           return Err(anyhow!("run {} does not exist", run_id));
+       }
+       info!("run {} replay window: {} to {} every {} days", run_id, window.start, window.end, window.step_days);
+       Ok(())
+   }
+    async fn get_logs_id(&self, run_id: RunId)->Result<(i32,i32)>{
        let row = sqlx::query(
            "SELECT *
-   FROM date_calculate_logs WHERE start_date!=end_date
+   FROM date_calculate_logs WHERE run_id = $1 AND start_date!=end_date
    ORDER BY start_date DESC
    LIMIT 1;"
        )
+           .bind(run_id)
            .fetch_all(self.pool.as_ref())
            .await?;
        if row.len()==1{
@@ -333,13 +448,14 @@ impl DatabaseInterface for PostgresDataBase {
            Ok((-1,-1))
        }
    }
-    async fn need_extra_stationaryti(&self)->Result<bool>{
+    async fn need_extra_stationaryti(&self, run_id: RunId)->Result<bool>{
        let row = sqlx::query(
            "SELECT *
-   FROM date_calculate_logs WHERE start_date!=end_date
+   FROM date_calculate_logs WHERE run_id = $1 AND start_date!=end_date
    ORDER BY start_date DESC
    LIMIT 1;"
        )
+           .bind(run_id)
            .fetch_all(self.pool.as_ref())
            .await?;
        if row.len()==1{
@@ -359,23 +475,40 @@ impl DatabaseInterface for PostgresDataBase {
 
        let last_config_id = config.get_last_config_id()?;
 
-       let stationarity = if let Some(timeframes) = trading_syn_pair.synthetic_data.get(&last_config_id) {
-           if let Some(h4_data) = timeframes.iter().find(|data| data.time_interval == TimeInterval::h4) {
-               h4_data.stationarity
-           } else {
-               return Err(anyhow!("Error: h4 dont find"));
-           }
-       } else {
-           return Err(anyhow!("Error: config_id dont find"));
-       };
+       let timeframes = trading_syn_pair.synthetic_data.get(&last_config_id)
+           .ok_or(anyhow!("Error: config_id dont find"))?;
+       if timeframes.is_empty() {
+           return Err(anyhow!("Error: timeframes dont find"));
+       }
        if trading_syn_pair.is_request_pair.unwrap_or(true)!=false {
-           self.insert_stationarity_transaction(trading_syn_pair.id, logs_id, stationarity, &mut tx).await?;
+           for timeframe_data in timeframes {
+               self.insert_stationarity_transaction(trading_syn_pair.id, logs_id, timeframe_data, &mut tx).await?;
+           }
        }
 
        tx.commit().await?;
        Ok(())
    }
 
+    async fn save_synthetic_basket(&self, config:&Config, basket:&mut SyntheticBasket, logs_id:i32) ->Result<()>{
+       let mut tx = self.pool.begin().await?;
+       basket.id = self.insert_pair_transaction(basket.name.as_str(), true, &mut tx).await?;
+
+       let last_config_id = config.get_last_config_id()?;
+       let primary_interval = config.get(last_config_id)?.primary_interval();
+       let primary_data = basket.get_timeframe_data(last_config_id, &primary_interval)
+           .ok_or(anyhow!("Error: {} dont find", primary_interval.binance_name()))?;
+       let stationarity_id = self.insert_stationarity_transaction(basket.id, logs_id, primary_data, &mut tx).await?;
+       sqlx::query("UPDATE stationarity_pairs SET basket_weights = $1 WHERE id = $2")
+           .bind(&basket.weights)
+           .bind(stationarity_id)
+           .execute(tx.deref_mut())
+           .await?;
+
+       tx.commit().await?;
+       Ok(())
+   }
+
     async fn save_pair_stationarity_metrics(&self, pair_id:i32, log_id:i32, stationarity:f32) -> Result<i32> {
        let row = sqlx::query(
            "INSERT INTO stationarity_pairs (pair_id, log_id, stationarity) VALUES ($1, $2, $3) RETURNING id",
@@ -400,18 +533,21 @@ RETURNING id;
        Ok(id)
    }
 
-    async fn insert_stationarity_transaction<'tx>(&self, pair_id:i32,log_id:i32,stationarity:f32,tx:&mut Transaction<'tx,Postgres>) -> Result<i32> {
+    async fn insert_stationarity_transaction<'tx>(&self, pair_id:i32,log_id:i32,pair_data:&PairData,tx:&mut Transaction<'tx,Postgres>) -> Result<i32> {
        let row = sqlx::query(
-           "INSERT INTO stationarity_pairs (pair_id, log_id, stationarity) VALUES ($1, $2, $3) RETURNING id",
+           "INSERT INTO stationarity_pairs (pair_id, log_id, stationarity, kpss_p_value, pp_p_value, hedge_ratio, hedge_intercept, spread_kind, half_life, hurst, candles_timeframe, repaired_bars) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id",
 
-       ).bind(pair_id).bind(log_id).bind(stationarity)
+       ).bind(pair_id).bind(log_id).bind(pair_data.stationarity).bind(pair_data.kpss_p_value).bind(pair_data.pp_p_value)
+           .bind(pair_data.hedge_ratio).bind(pair_data.hedge_intercept).bind(pair_data.spread_kind).bind(pair_data.half_life).bind(pair_data.hurst)
+           .bind(pair_data.time_interval.clone()).bind(pair_data.repaired_bars)
            .fetch_one(tx.deref_mut())
            .await?;
        let id: i32 = row.get("id");
        Ok(id)
    }
-    async fn check_for_new_date_going(&self) -> Result<bool> {
-       let last_id_row = sqlx::query("SELECT count FROM date_going ORDER BY id DESC LIMIT 1")
+    async fn check_for_new_date_going(&self, run_id: RunId) -> Result<bool> {
+       let last_id_row = sqlx::query("SELECT count FROM date_going WHERE run_id = $1 ORDER BY id DESC LIMIT 1")
+           .bind(run_id)
            .fetch_optional(self.pool.as_ref())
            .await?;
        if last_id_row.is_some(){
@@ -425,9 +561,10 @@ RETURNING id;
        }
        Ok(false)
    }
-    async fn finish_clear(&self)-> Result<()> {
+    async fn finish_clear(&self, run_id: RunId)-> Result<()> {
        let mut tx = self.pool.begin().await?;
-       sqlx::query("DELETE FROM date_going")
+       sqlx::query("DELETE FROM date_going WHERE run_id = $1")
+           .bind(run_id)
            .execute(&mut *tx)
            .await?;
 
@@ -497,22 +634,24 @@ RETURNING id;
 
         Ok(())
     }
-     async fn start_update(&self,new_log_id: i32,time_now:NaiveDateTime)->Result<()>{
-        let result = sqlx::query("INSERT INTO date_going (day, count,log_id)
-                        VALUES ($1, -1, $2)")
+     async fn start_update(&self,run_id: RunId,new_log_id: i32,time_now:NaiveDateTime)->Result<()>{
+        let result = sqlx::query("INSERT INTO date_going (day, count,log_id,run_id)
+                        VALUES ($1, -1, $2, $3)")
             .bind(time_now)
             .bind(new_log_id)
+            .bind(run_id)
             .execute(self.pool.as_ref())
             .await?;
         Ok(())
     }
-     async fn finish_update(&self)->Result<()>{
-        self.update_last_date_going_log_id().await?;
+     async fn finish_update(&self, run_id: RunId)->Result<()>{
+        self.update_last_date_going_log_id(run_id).await?;
         Ok(())
     }
-     async fn update_last_date_going_log_id(&self) -> Result<bool> {
+     async fn update_last_date_going_log_id(&self, run_id: RunId) -> Result<bool> {
 
-        let last_id_row = sqlx::query("SELECT id FROM date_going ORDER BY id DESC LIMIT 1")
+        let last_id_row = sqlx::query("SELECT id FROM date_going WHERE run_id = $1 ORDER BY id DESC LIMIT 1")
+            .bind(run_id)
             .fetch_optional(self.pool.as_ref())
             .await?;
 
@@ -527,24 +666,47 @@ RETURNING id;
         Ok(false)
     }
 
-     async fn clear_analysis_data(&self) -> Result<()> {
+     async fn clear_analysis_data(&self, run_id: RunId) -> Result<()> {
         let mut tx = self.pool.begin().await?;
-        sqlx::query("DELETE FROM pairs_info")
+        sqlx::query("DELETE FROM pairs_info WHERE run_id = $1")
+            .bind(run_id)
             .execute(&mut *tx)
             .await?;
         tx.commit().await?;
         info!("All data from stationarity_pairs and pairs_info tables has been deleted");
         Ok(())
     }
-     async fn insert_pair_info_transaction<'tx>(&self, candles_h4:&Vec<f32>, zscores_h4:&Vec<f32>, candles_minutes: &Vec<f32>, dates_h4: &Vec<NaiveDateTime>, dates_minutes: &Vec<NaiveDateTime>, zscore_minutes:&Vec<f32>, pair_id: i32, configuration_id:ConfigId, timeframe: TimeInterval, tx:&mut Transaction<'tx,Postgres>) -> Result<i32> {
-        let zscores_h4_last_240 = if zscores_h4.len() > 240 {
-            zscores_h4[zscores_h4.len() - 240..].to_vec()
+     async fn save_candle_quality_reports(&self, log_id: i32, reports: &[CandleQualityReport]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for report in reports {
+            sqlx::query(
+                "INSERT INTO candle_quality_reports (log_id, pair_name, candles_timeframe, bars, missing_bars, duplicated_bars, unordered_bars, non_positive_prices, extreme_jumps, action) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            ).bind(log_id).bind(&report.pair_name).bind(report.time_interval.clone()).bind(report.bars).bind(report.missing_bars)
+                .bind(report.duplicated_bars).bind(report.unordered_bars).bind(report.non_positive_prices).bind(report.extreme_jumps).bind(report.action)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+     async fn insert_pair_info_transaction<'tx>(&self, run_id: RunId, pair_data:&PairData, pair_id: i32, z_score_period: usize, tx:&mut Transaction<'tx,Postgres>) -> Result<i32> {
+        let zscores = &pair_data.z_score;
+        let zscores_last_period = if zscores.len() > z_score_period {
+            zscores[zscores.len() - z_score_period..].to_vec()
         } else {
-            zscores_h4.clone()
+            zscores.clone()
         };
+        let candles = &pair_data.candles;
+        let column = |value: fn(&Candle) -> f64| candles.iter().map(value).collect::<Vec<f64>>();
         let row = sqlx::query(
-            "INSERT INTO pairs_info (candles_minutes, dates_h4, dates_minutes, zscores_minutes, candles_h4, zscores_h4, pair_id, candles_timeframe,configuration_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (pair_id, candles_timeframe, configuration_id) DO NOTHING;",
-        ).bind(&candles_minutes).bind(&dates_h4).bind(&dates_minutes).bind(&zscore_minutes).bind(candles_h4).bind(zscores_h4_last_240).bind(pair_id).bind(timeframe.clone()).bind(configuration_id)
+            "INSERT INTO pairs_info (candles_minutes, dates, dates_minutes, zscores_minutes, candles, opens, highs, lows, volumes, quote_volumes, trades, zscores, pair_id, candles_timeframe,configuration_id,run_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16) ON CONFLICT (run_id, pair_id, candles_timeframe, configuration_id) DO NOTHING;",
+        ).bind(pair_data.candles_minutes.iter().map(|candle| candle.price).collect::<Vec<f64>>())
+            .bind(candles.iter().map(|candle| candle.close_time).collect::<Vec<NaiveDateTime>>())
+            .bind(pair_data.candles_minutes.iter().map(|candle| candle.close_time).collect::<Vec<NaiveDateTime>>())
+            .bind(&pair_data.z_score_minutes)
+            .bind(column(|candle| candle.price)).bind(column(|candle| candle.open)).bind(column(|candle| candle.high)).bind(column(|candle| candle.low))
+            .bind(column(|candle| candle.volume)).bind(column(|candle| candle.quote_volume)).bind(candles.iter().map(|candle| candle.trades).collect::<Vec<i64>>())
+            .bind(zscores_last_period).bind(pair_id).bind(pair_data.time_interval.clone()).bind(pair_data.configuration_id).bind(run_id)
             .execute(tx.deref_mut())
             .await?;
         Ok(0)
@@ -0,0 +1,186 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use binance_async::rest_model::KlineSummary;
+use sha2::{Digest, Sha256};
+use tokio::task;
+use tracing::{error, info};
+use crate::exchange::replay::{archive_files, archive_market, read_archive, series_key, FUTURES_UM};
+use crate::logic::convert_to_candles;
+use crate::storage::klines::KlineStore;
+
+// Bulk load of data.binance.vision kline archives into the kline store, the backfill that would
+// otherwise go through get_minutes_only. Every loaded archive is recorded by its path under the
+// imported directory, so an interrupted import resumes with the first archive it did not finish.
+// The store holds USD-M futures bars: archives of another market are left out, a flat directory
+// without the market directories is taken as USD-M futures.
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImportSummary {
+    pub archives: usize,
+    pub imported: usize,
+    // Loaded by an earlier run
+    pub already_imported: usize,
+    // File names without a modelled interval, e.g. 1s or 1M
+    pub unsupported: usize,
+    // Spot, COIN-M or options archives
+    pub other_market: usize,
+    // Checksum mismatch or unreadable archive, retried on the next run
+    pub failed: usize,
+    pub klines: usize,
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".CHECKSUM");
+    PathBuf::from(name)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// `BTCUSDT-1m-2024-09.zip.CHECKSUM` holds `<sha256>  BTCUSDT-1m-2024-09.zip`, archives without one are
+// taken as they are. Returns the sha256 of the archive and its klines sorted by open_time without repeats.
+fn read_verified_archive(path: &Path) -> Result<(String, Vec<KlineSummary>)> {
+    let sha256 = sha256_hex(&std::fs::read(path)?);
+    let checksum_path = checksum_path(path);
+    if checksum_path.exists() {
+        let content = std::fs::read_to_string(&checksum_path)?;
+        let expected = content.split_whitespace().next().ok_or_else(|| anyhow!("empty {}", checksum_path.display()))?;
+        if !expected.eq_ignore_ascii_case(&sha256) {
+            return Err(anyhow!("checksum mismatch: expected {}, got {}", expected, sha256));
+        }
+    }
+    let mut klines = read_archive(path)?;
+    klines.sort_by_key(|kline| kline.open_time);
+    klines.dedup_by_key(|kline| kline.open_time);
+    Ok((sha256, klines))
+}
+
+// Klines repeated across overlapping monthly and daily archives are dropped by the store
+pub async fn import_klines<S: KlineStore + Sync + ?Sized>(store: &S, root: &Path) -> Result<ImportSummary> {
+    let files = archive_files(root)?;
+    let imported = store.imported_archives().await?;
+    let mut summary = ImportSummary { archives: files.len(), ..Default::default() };
+    for (number, path) in files.into_iter().enumerate() {
+        // Spot and futures archives share their file names, so the path under the root tells them apart
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let file_name = relative.components().filter_map(|component| component.as_os_str().to_str()).collect::<Vec<_>>().join("/");
+        let progress = format!("[{}/{}] {}", number + 1, summary.archives, file_name);
+        if imported.contains(&file_name) {
+            summary.already_imported += 1;
+            continue;
+        }
+        if let Some(market) = archive_market(relative).filter(|market| market != FUTURES_UM) {
+            info!("import: {} skipped, {} is not the {} market of the store", progress, market, FUTURES_UM);
+            summary.other_market += 1;
+            continue;
+        }
+        let Some((symbol, interval)) = series_key(&path) else {
+            info!("import: {} skipped, interval is not supported", progress);
+            summary.unsupported += 1;
+            continue;
+        };
+        let (sha256, klines) = match task::spawn_blocking(move || read_verified_archive(&path)).await? {
+            Ok(archive) => archive,
+            Err(e) => {
+                error!("import: {} failed: {}", progress, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        store.save_klines(&symbol, &interval, &convert_to_candles(&klines)).await?;
+        store.mark_archive_imported(&file_name, &sha256, klines.len() as i64).await?;
+        info!("import: {} {} klines", progress, klines.len());
+        summary.imported += 1;
+        summary.klines += klines.len();
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use crate::core::types::TimeInterval;
+    use crate::storage::klines::MockKlineStore;
+    use crate::test_util::write_zip;
+
+    const SEPTEMBER_1: i64 = 1725148800000;
+
+    // Klines CSV of `count` hourly candles opened from `first_open`
+    fn csv(first_open: i64, count: i64) -> String {
+        let mut content = String::from("open_time,open,high,low,close,volume,close_time,quote_volume,count,taker_buy_volume,taker_buy_quote_volume,ignore\n");
+        for k in 0..count {
+            let open_time = first_open + k * 3_600_000;
+            content.push_str(&format!("{},1.0,2.0,0.5,{},10.0,{},100.0,7,4.0,40.0,0\n", open_time, k + 1, open_time + 3_599_999));
+        }
+        content
+    }
+
+    fn write_checksum(path: &Path, sha256: &str) -> Result<()> {
+        std::fs::write(checksum_path(path), format!("{}  {}\n", sha256, path.file_name().unwrap().to_str().unwrap()))?;
+        Ok(())
+    }
+
+    // A monthly archive with a valid checksum, a daily one that overlaps it, one with a corrupted
+    // checksum, one with an interval that is not modelled and one of the spot market
+    fn archives() -> Result<PathBuf> {
+        let root = std::env::temp_dir().join(format!("import-klines-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("monthly"))?;
+        std::fs::create_dir_all(root.join("daily"))?;
+        let monthly = root.join("monthly/BTCUSDT-1h-2024-09.zip");
+        write_zip(&monthly, &csv(SEPTEMBER_1, 48))?;
+        write_checksum(&monthly, &sha256_hex(&std::fs::read(&monthly)?))?;
+        let daily = root.join("daily/BTCUSDT-1h-2024-09-02.zip");
+        write_zip(&daily, &(csv(SEPTEMBER_1 + 86_400_000, 24) + &csv(SEPTEMBER_1 + 86_400_000, 24)))?;
+        let corrupted = root.join("daily/ETHUSDT-1h-2024-09-02.zip");
+        write_zip(&corrupted, &csv(SEPTEMBER_1 + 86_400_000, 24))?;
+        write_checksum(&corrupted, &"0".repeat(64))?;
+        write_zip(&root.join("daily/BTCUSDT-1s-2024-09-02.zip"), &csv(SEPTEMBER_1, 1))?;
+        // A spot archive named like a futures one
+        std::fs::create_dir_all(root.join("data/spot/daily/klines/BTCUSDT/1h"))?;
+        write_zip(&root.join("data/spot/daily/klines/BTCUSDT/1h/BTCUSDT-1h-2024-09-02.zip"), &csv(SEPTEMBER_1, 24))?;
+        Ok(root)
+    }
+
+    #[tokio::test]
+    async fn test_import_is_verified_and_resumable() -> Result<()> {
+        let root = archives()?;
+        let imported = Arc::new(Mutex::new(HashSet::new()));
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let mut store = MockKlineStore::new();
+        let names = imported.clone();
+        store.expect_imported_archives().returning(move || Ok(names.lock().unwrap().clone()));
+        let names = imported.clone();
+        store.expect_mark_archive_imported().returning(move |file_name, sha256, _| {
+            assert_eq!(sha256.len(), 64);
+            names.lock().unwrap().insert(file_name.to_string());
+            Ok(())
+        });
+        let candles = saved.clone();
+        store.expect_save_klines().returning(move |symbol, interval, klines| {
+            assert_eq!((symbol, interval), ("BTCUSDT", &TimeInterval::h1));
+            assert!(klines.windows(2).all(|pair| pair[0].open_time < pair[1].open_time));
+            candles.lock().unwrap().push(klines.len());
+            Ok(())
+        });
+
+        let summary = import_klines(&store, &root).await?;
+        assert_eq!(summary, ImportSummary { archives: 5, imported: 2, already_imported: 0, unsupported: 1, other_market: 1, failed: 1, klines: 72 });
+        assert!(imported.lock().unwrap().contains("daily/BTCUSDT-1h-2024-09-02.zip"));
+        // The repeated rows of the daily archive are loaded once
+        assert_eq!(*saved.lock().unwrap(), vec![24, 48]);
+
+        // The next run only retries the archive that failed
+        let summary = import_klines(&store, &root).await?;
+        assert_eq!((summary.imported, summary.already_imported, summary.other_market, summary.failed), (0, 2, 1, 1));
+        assert_eq!(saved.lock().unwrap().len(), 2);
+
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
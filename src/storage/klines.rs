@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use chrono::NaiveDateTime;
+use anyhow::Result;
+use mockall::automock;
+use crate::core::types::{Candle, TimeInterval};
+use crate::storage::database::PostgresDataBase;
+
+// Persistent store of closed klines, keyed by symbol, interval and open_time. The exchange consults it
+// before Binance, so consecutive simulated days only request the bars opened since the last run.
+
+#[automock]
+#[async_trait::async_trait]
+pub trait KlineStore {
+    // The last `limit` stored candles opened at or before `until`, oldest first
+    async fn load_klines(&self, symbol: &str, interval: &TimeInterval, until: NaiveDateTime, limit: u16) -> Result<Vec<Candle>>;
+    async fn save_klines(&self, symbol: &str, interval: &TimeInterval, candles: &[Candle]) -> Result<()>;
+    // File names of the archives already loaded by import-klines
+    async fn imported_archives(&self) -> Result<HashSet<String>>;
+    async fn mark_archive_imported(&self, file_name: &str, sha256: &str, klines: i64) -> Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheLookup {
+    // The stored candles cover the whole request
+    Complete,
+    // Only the candles opened after this open_time are missing
+    Tail(NaiveDateTime),
+    // Nothing usable is stored, the request goes to the exchange as a whole
+    Full,
+}
+
+// `cached` is what load_klines returned for the request. A tail longer than the request, a stored
+// range that does not reach back far enough, or one with holes (archives of non-adjacent months, bars
+// an earlier run skipped) is fetched in full.
+pub fn cache_lookup(cached: &[Candle], interval: &TimeInterval, until: NaiveDateTime, limit: usize) -> CacheLookup {
+    let Some(last) = cached.last() else {
+        return CacheLookup::Full;
+    };
+    let missing = ((until - last.open_time).num_minutes() / interval.minutes()).max(0) as usize;
+    if missing > limit || cached.len() + missing < limit {
+        return CacheLookup::Full;
+    }
+    // The stored bars served with the request must follow each other
+    let served = &cached[cached.len() + missing - limit..];
+    if served.windows(2).any(|bars| (bars[1].open_time - bars[0].open_time).num_minutes() != interval.minutes()) {
+        return CacheLookup::Full;
+    }
+    if missing == 0 {
+        return CacheLookup::Complete;
+    }
+    CacheLookup::Tail(last.open_time)
+}
+
+#[async_trait::async_trait]
+impl KlineStore for PostgresDataBase {
+    async fn load_klines(&self, symbol: &str, interval: &TimeInterval, until: NaiveDateTime, limit: u16) -> Result<Vec<Candle>> {
+        let mut candles = sqlx::query_as::<_, Candle>(
+            "SELECT close AS price, open, high, low, volume, quote_volume, trades, close_time, open_time FROM klines
+             WHERE symbol = $1 AND candles_timeframe = $2 AND open_time <= $3 ORDER BY open_time DESC LIMIT $4"
+        )
+            .bind(symbol).bind(interval.clone()).bind(until).bind(limit as i64)
+            .fetch_all(self.pool.as_ref())
+            .await?;
+        candles.reverse();
+        Ok(candles)
+    }
+    async fn save_klines(&self, symbol: &str, interval: &TimeInterval, candles: &[Candle]) -> Result<()> {
+        let column = |value: fn(&Candle) -> f64| candles.iter().map(value).collect::<Vec<f64>>();
+        sqlx::query(
+            "INSERT INTO klines (symbol, candles_timeframe, open_time, close_time, open, high, low, close, volume, quote_volume, trades)
+             SELECT $1, $2, * FROM UNNEST($3::timestamp[], $4::timestamp[], $5::float8[], $6::float8[], $7::float8[], $8::float8[], $9::float8[], $10::float8[], $11::int8[])
+             ON CONFLICT (symbol, candles_timeframe, open_time) DO NOTHING"
+        )
+            .bind(symbol).bind(interval.clone())
+            .bind(candles.iter().map(|candle| candle.open_time).collect::<Vec<NaiveDateTime>>())
+            .bind(candles.iter().map(|candle| candle.close_time).collect::<Vec<NaiveDateTime>>())
+            .bind(column(|candle| candle.open)).bind(column(|candle| candle.high)).bind(column(|candle| candle.low)).bind(column(|candle| candle.price))
+            .bind(column(|candle| candle.volume)).bind(column(|candle| candle.quote_volume))
+            .bind(candles.iter().map(|candle| candle.trades).collect::<Vec<i64>>())
+            .execute(self.pool.as_ref())
+            .await?;
+        Ok(())
+    }
+    async fn imported_archives(&self) -> Result<HashSet<String>> {
+        let names: Vec<String> = sqlx::query_scalar("SELECT file_name FROM imported_kline_archives")
+            .fetch_all(self.pool.as_ref())
+            .await?;
+        Ok(names.into_iter().collect())
+    }
+    async fn mark_archive_imported(&self, file_name: &str, sha256: &str, klines: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO imported_kline_archives (file_name, sha256, klines) VALUES ($1, $2, $3)
+             ON CONFLICT (file_name) DO UPDATE SET sha256 = EXCLUDED.sha256, klines = EXCLUDED.klines, imported_at = now()"
+        )
+            .bind(file_name).bind(sha256).bind(klines)
+            .execute(self.pool.as_ref())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::test_util::{candle, september_1 as day_start};
+
+    // `count` H4 candles, the last one opened at `last_open`
+    fn cached(count: i64, last_open: NaiveDateTime) -> Vec<Candle> {
+        (0..count).rev().map(|k| candle(last_open - Duration::hours(4 * k), 240, 1.0)).collect()
+    }
+
+    #[test]
+    fn test_cache_lookup() {
+        // The request of the next simulated day ends one second before its midnight
+        let until = day_start() + Duration::days(1) - Duration::seconds(1);
+        let interval = TimeInterval::h4;
+
+        assert_eq!(cache_lookup(&[], &interval, until, 1000), CacheLookup::Full);
+        // Yesterday's run stored the bars up to 20:00 of the previous day, six bars opened since
+        let yesterday = cached(1000, day_start() - Duration::hours(4));
+        assert_eq!(cache_lookup(&yesterday, &interval, until, 1000), CacheLookup::Tail(day_start() - Duration::hours(4)));
+        // A replay of a day that is already stored
+        let today = cached(1000, day_start() + Duration::hours(20));
+        assert_eq!(cache_lookup(&today, &interval, until, 1000), CacheLookup::Complete);
+        assert_eq!(cache_lookup(&today, &interval, until, 500), CacheLookup::Complete);
+        // Too few stored bars in front of the tail, or a tail longer than the request
+        assert_eq!(cache_lookup(&yesterday[10..], &interval, until, 1000), CacheLookup::Full);
+        assert_eq!(cache_lookup(&cached(10, day_start() - Duration::days(30)), &interval, until, 100), CacheLookup::Full);
+        assert_eq!(cache_lookup(&today[10..], &interval, until, 1000), CacheLookup::Full);
+        // A hole in the stored bars is refetched, unless it lies before the requested window
+        let mut holed = today.clone();
+        holed.remove(500);
+        assert_eq!(cache_lookup(&holed, &interval, until, 999), CacheLookup::Full);
+        assert_eq!(cache_lookup(&holed, &interval, until, 400), CacheLookup::Complete);
+        let mut holed = yesterday.clone();
+        holed.remove(998);
+        assert_eq!(cache_lookup(&holed, &interval, until, 990), CacheLookup::Full);
+        assert_eq!(cache_lookup(&holed, &interval, until, 6), CacheLookup::Tail(day_start() - Duration::hours(4)));
+        // The last stored minute candle is the one before `until`
+        let minutes: Vec<Candle> = (0..1440).map(|k| candle(day_start() + Duration::minutes(k), 1, 0.0)).collect();
+        assert_eq!(cache_lookup(&minutes, &TimeInterval::m1, until, 1440), CacheLookup::Complete);
+        assert_eq!(cache_lookup(&minutes[..1380], &TimeInterval::m1, until, 1440), CacheLookup::Tail(day_start() + Duration::minutes(1379)));
+    }
+}
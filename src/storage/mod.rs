@@ -1 +1,4 @@
-pub mod database;
\ No newline at end of file
+pub mod database;
+pub mod klines;
+pub mod import;
+pub mod admin;
@@ -0,0 +1,30 @@
+// Fixtures shared by the unit tests
+use std::io::Write;
+use std::path::Path;
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use crate::core::types::Candle;
+
+// Midnight of the first day of the default replay window
+pub fn september_1() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+}
+
+// A bar of `minutes` opened at `open_time`, closed a millisecond before the next one opens
+pub fn candle(open_time: NaiveDateTime, minutes: i64, price: f64) -> Candle {
+    Candle { price, open_time, close_time: open_time + Duration::minutes(minutes) - Duration::milliseconds(1), ..Candle::default() }
+}
+
+// H4 candles at the given bar numbers from September 1 with the given prices, in the order they are listed
+pub fn h4_candles(bars: &[(i64, f64)]) -> Vec<Candle> {
+    bars.iter().map(|&(k, price)| candle(september_1() + Duration::hours(4 * k), 240, price)).collect()
+}
+
+// A data.binance.vision style archive: one csv named after the zip
+pub fn write_zip(path: &Path, content: &str) -> Result<()> {
+    let mut archive = zip::ZipWriter::new(std::fs::File::create(path)?);
+    archive.start_file(format!("{}.csv", path.file_stem().unwrap().to_str().unwrap()), zip::write::SimpleFileOptions::default())?;
+    archive.write_all(content.as_bytes())?;
+    archive.finish()?;
+    Ok(())
+}
@@ -0,0 +1,103 @@
+"""Regenerates the statsmodels golden fixtures used by the native ADF and Johansen tests.
+
+    pip install statsmodels
+    python tests/fixtures/statsmodels_golden.py
+
+Writes statsmodels_adfuller_cases.json and statsmodels_johansen.json next to this file.
+"""
+import json
+import os
+import sys
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+MASK = (1 << 64) - 1
+
+
+# Same generator as `noise` / `random_walk` in src/mathematics/johansen.rs
+def noise(seed, length):
+    state = seed
+    values = []
+    for _ in range(length):
+        state = (state * 6364136223846793005 + 1442695040888963407) & MASK
+        values.append((state >> 11) / float(1 << 53) - 0.5)
+    return values
+
+
+def random_walk(seed, length):
+    level, values = 0.0, []
+    for step in noise(seed, length):
+        level += step
+        values.append(level)
+    return values
+
+
+def johansen_series():
+    a, b, stationary = random_walk(1, 600), random_walk(2, 600), noise(3, 600)
+    c = [0.5 * a[i] + 2.0 * b[i] + stationary[i] for i in range(600)]
+    return [
+        ("basket", [a, b, c]),
+        ("independent_walks", [random_walk(11, 500), random_walk(12, 500), random_walk(13, 500)]),
+    ]
+
+
+def adfuller_cases(series):
+    from statsmodels.tsa.stattools import adfuller
+
+    cases = []
+    for regression, autolag, maxlag in [("c", "BIC", None), ("ct", "AIC", None), ("n", "AIC", None), ("c", None, 4)]:
+        result = adfuller(series, maxlag=maxlag, regression=regression, autolag=autolag)
+        critical = result[4]
+        cases.append({
+            "regression": regression,
+            "autolag": autolag,
+            "maxlag": maxlag,
+            "statistic": result[0],
+            "p_value": result[1],
+            "used_lag": result[2],
+            "nobs": result[3],
+            "critical_values": [critical["1%"], critical["5%"], critical["10%"]],
+        })
+    return cases
+
+
+def johansen_cases():
+    import numpy as np
+    from statsmodels.tsa.vector_ar.vecm import coint_johansen
+
+    cases = []
+    for name, columns in johansen_series():
+        result = coint_johansen(np.column_stack(columns), det_order=0, k_ar_diff=1)
+        cases.append({
+            "name": name,
+            "det_order": 0,
+            "k_ar_diff": 1,
+            "series": columns,
+            "eigenvalues": result.eig.tolist(),
+            "trace_statistics": result.lr1.tolist(),
+            "max_eigen_statistics": result.lr2.tolist(),
+            # 90%, 95%, 99%
+            "trace_critical_values": result.cvt.tolist(),
+            "max_eigen_critical_values": result.cvm.tolist(),
+            "normalized_vector": (result.evec[:, 0] / result.evec[0, 0]).tolist(),
+        })
+    return cases
+
+
+def write(name, payload):
+    with open(os.path.join(HERE, name), "w") as f:
+        json.dump(payload, f, indent=2)
+        f.write("\n")
+
+
+def main():
+    import statsmodels
+
+    with open(os.path.join(HERE, "statsmodels_sunspots.json")) as f:
+        series = json.load(f)["series"]
+    source = "statsmodels %s, generated by tests/fixtures/statsmodels_golden.py" % statsmodels.__version__
+    write("statsmodels_adfuller_cases.json", {"description": source, "cases": adfuller_cases(series)})
+    write("statsmodels_johansen.json", {"description": source, "cases": johansen_cases()})
+
+
+if __name__ == "__main__":
+    sys.exit(main())